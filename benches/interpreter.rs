@@ -0,0 +1,102 @@
+use std::io::Cursor;
+
+use alic3::asm_parser::Parser;
+use alic3::assembler::assemble;
+use alic3::emulator::Cpu;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// Nested counting loops: nothing but arithmetic and branches.
+const COUNT: &str = ".ORIG x3000
+LD R1, OUTER
+OUTER_LOOP LD R2, INNER
+INNER_LOOP ADD R0, R0, #1
+ADD R2, R2, #-1
+BRp INNER_LOOP
+ADD R1, R1, #-1
+BRp OUTER_LOOP
+AND R0, R0, #0
+STI R0, MCR
+OUTER .FILL #100
+INNER .FILL #500
+MCR .FILL xFFFE
+.END";
+
+/// Bubble sort of a reversed array, calling a subroutine to swap: lots of loads, stores, and JSRs.
+const SORT: &str = ".ORIG x3000
+LD R6, STACK
+LEA R0, ARRAY
+LD R1, LENGTH
+FILL STR R1, R0, #0
+ADD R0, R0, #1
+ADD R1, R1, #-1
+BRp FILL
+LD R5, LENGTH
+PASS ADD R5, R5, #-1
+BRz DONE
+LEA R0, ARRAY
+ADD R1, R5, #0
+COMPARE LDR R2, R0, #0
+LDR R3, R0, #1
+NOT R4, R2
+ADD R4, R4, #1
+ADD R4, R3, R4
+BRzp NEXT
+JSR SWAP
+NEXT ADD R0, R0, #1
+ADD R1, R1, #-1
+BRp COMPARE
+BRnzp PASS
+DONE AND R0, R0, #0
+STI R0, MCR
+SWAP ADD R6, R6, #-1
+STR R7, R6, #0
+STR R3, R0, #0
+STR R2, R0, #1
+LDR R7, R6, #0
+ADD R6, R6, #1
+RET
+STACK .FILL x3000
+LENGTH .FILL #150
+MCR .FILL xFFFE
+ARRAY .BLKW #150
+.END";
+
+fn object(source: &str) -> Vec<u8> {
+    assemble(Parser::parse(source).unwrap())
+        .unwrap()
+        .into_iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect()
+}
+
+/// Run a program to completion, returning how many instructions it took.
+fn run(object: &[u8], block_cache: bool) -> u64 {
+    let mut cpu = Cpu::new(Cursor::new(Vec::new()), Vec::new());
+    cpu.pc = cpu.load_program(Cursor::new(object)).unwrap();
+    cpu.set_block_cache_enabled(block_cache);
+    while !cpu.should_halt() {
+        if block_cache {
+            cpu.run_block(u64::MAX).unwrap();
+        } else {
+            cpu.step().unwrap();
+        }
+    }
+    cpu.instructions_retired()
+}
+
+fn interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter");
+    for (name, source) in [("count", COUNT), ("sort", SORT)] {
+        let object = object(source);
+        group.throughput(Throughput::Elements(run(&object, false)));
+        for (engine, block_cache) in [("step", false), ("block cache", true)] {
+            group.bench_with_input(BenchmarkId::new(engine, name), &object, |b, object| {
+                b.iter(|| run(object, block_cache))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
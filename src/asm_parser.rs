@@ -53,6 +53,8 @@ enum Token<'a> {
     Br((bool, bool, bool)),
     #[token("jmp", ignore(case))]
     Jmp,
+    #[token("jmpt", ignore(case))]
+    Jmpt,
     #[token("jsr", ignore(case))]
     Jsr,
     #[token("jsrr", ignore(case))]
@@ -71,6 +73,8 @@ enum Token<'a> {
     Ret,
     #[token("rti", ignore(case))]
     Rti,
+    #[token("rtt", ignore(case))]
+    Rtt,
     #[token("sti", ignore(case))]
     Sti,
     #[token("str", ignore(case))]
@@ -152,6 +156,9 @@ pub enum Operation<'a> {
     Jmp {
         base_r: Register,
     },
+    Jmpt {
+        base_r: Register,
+    },
     Jsr {
         pc_offset: Location<'a>,
     },
@@ -230,6 +237,8 @@ pub struct CodeLine<'a> {
     pub label: Option<&'a str>,
     pub instruction: Instruction<'a>,
     pub location: u16,
+    /// Line number in the source file, starting from 1
+    pub line: usize,
 }
 
 pub struct Program<'a> {
@@ -277,6 +286,9 @@ pub struct Parser<'a> {
     scanner: Scanner<'a>,
     location_cursor: u16,
     labels: HashMap<&'a str, u16>,
+    /// The line number of the source text up to `line_cursor`, to avoid recounting from the start on every line
+    line_number: usize,
+    line_cursor: usize,
 }
 
 impl<'a> Parser<'a> {
@@ -351,10 +363,31 @@ impl<'a> Parser<'a> {
                 self.scanner.next();
                 Some(label_value)
             }
+            // JMPT and RTT only became instructions along with the extension, so programs written without it can have
+            // labels by those names
+            Some(Token::Jmpt | Token::Rtt) => {
+                let label_value = self.scanner.lexer.slice();
+                self.scanner.next();
+                Some(label_value)
+            }
             _ => None,
         }
     }
 
+    /// Whether the next token, at the start of a line, is a JMPT or RTT instruction rather than a label. It's a label
+    /// if anything other than a register follows it on the same line.
+    fn next_is_jmpt_instruction(&self) -> bool {
+        if !matches!(self.scanner.peek(), Some(Token::Jmpt | Token::Rtt)) {
+            return false;
+        }
+        let mut lexer = self.scanner.lexer.clone();
+        let end = lexer.span().end;
+        match lexer.next() {
+            None | Some(Token::Register(_)) => true,
+            Some(_) => lexer.source()[end..lexer.span().start].contains('\n'),
+        }
+    }
+
     fn parse_register(&mut self) -> Option<Register> {
         match self.scanner.peek() {
             Some(Token::Register(r)) => {
@@ -414,12 +447,17 @@ impl<'a> Parser<'a> {
                     pc_offset,
                 })))
             }
-            Some(Token::Jmp) => {
+            Some(Token::Jmp | Token::Jmpt) => {
                 self.scanner.next();
                 let base_r = self
                     .parse_register()
                     .ok_or_else(|| anyhow!("Expected register, got {:?}", self.scanner.peek()))?;
-                Ok(Some(Instruction::Operation(Operation::Jmp { base_r })))
+                let operation = match token {
+                    Some(Token::Jmp) => Operation::Jmp { base_r },
+                    Some(Token::Jmpt) => Operation::Jmpt { base_r },
+                    _ => unreachable!(),
+                };
+                Ok(Some(Instruction::Operation(operation)))
             }
             Some(Token::Jsr) => {
                 self.scanner.next();
@@ -503,6 +541,12 @@ impl<'a> Parser<'a> {
                 self.scanner.next();
                 Ok(Some(Instruction::Operation(Operation::Rti)))
             }
+            Some(Token::Rtt) => {
+                self.scanner.next();
+                Ok(Some(Instruction::Operation(Operation::Jmpt {
+                    base_r: Register(7),
+                })))
+            }
             Some(Token::St | Token::Sti) => {
                 self.scanner.next();
                 let sr = self
@@ -608,8 +652,24 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// The line number of the next token.
+    fn current_line(&mut self) -> usize {
+        let offset = self.scanner.lexer.span().start;
+        let source = self.scanner.lexer.source();
+        if offset > self.line_cursor {
+            self.line_number += source[self.line_cursor..offset].matches('\n').count();
+            self.line_cursor = offset;
+        }
+        self.line_number
+    }
+
     fn parse_code_line(&mut self) -> Result<CodeLine<'a>> {
-        let label = self.parse_label();
+        let line = self.current_line();
+        let label = if self.next_is_jmpt_instruction() {
+            None
+        } else {
+            self.parse_label()
+        };
         let instruction = self
             .parse_instruction()?
             .ok_or_else(|| anyhow!("Expected instruction, got {:?}", self.scanner.peek()))?;
@@ -618,6 +678,7 @@ impl<'a> Parser<'a> {
             label,
             instruction,
             location: self.location_cursor,
+            line,
         })
     }
 
@@ -665,6 +726,8 @@ impl<'a> Parser<'a> {
             scanner: Scanner::new(lexer),
             location_cursor: 0,
             labels: HashMap::new(),
+            line_number: 1,
+            line_cursor: 0,
         };
 
         let (origin, lines) = parser.parse_program().map_err(|err| ParseError {
@@ -80,6 +80,9 @@ pub fn assemble(pgm: Program) -> Result<Vec<u16>> {
                         (Opcode::Br.to_int() << 12) | (nzp << 9) | truncate::<9>(loc)?
                     }
                     Operation::Jmp { base_r } => (Opcode::Jmp.to_int() << 12) | (base_r.0 << 6),
+                    Operation::Jmpt { base_r } => {
+                        (Opcode::Jmp.to_int() << 12) | (base_r.0 << 6) | 1
+                    }
                     Operation::Jsr { pc_offset } => {
                         let loc = resolve_location_relative(&pgm, pc_offset, line.location)?;
                         (Opcode::Jsr.to_int() << 12) | (1 << 11) | truncate::<11>(loc)?
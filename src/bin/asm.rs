@@ -1,5 +1,6 @@
 use alic3::asm_parser::Parser;
 use alic3::assembler::assemble;
+use alic3::debug_info::DebugInfo;
 use std::{
     env::args,
     fs::File,
@@ -16,6 +17,8 @@ fn main() -> anyhow::Result<()> {
     let in_path = Path::new(&args[1]);
     let mut out_path = in_path.to_path_buf();
     out_path.set_extension("obj");
+    let mut debug_info_path = in_path.to_path_buf();
+    debug_info_path.set_extension("dbg");
 
     let mut asm = File::open(&args[1])?;
     let mut asm_str = String::new();
@@ -47,6 +50,8 @@ fn main() -> anyhow::Result<()> {
         anyhow::anyhow!("Line {}: {}", line_num, err)
     })?;
 
+    let mut debug_info = DebugInfo::from_program(&program);
+    debug_info.set_source(&args[1]);
     let machine_code = assemble(program)?;
 
     let mut out_file = File::create(out_path)?;
@@ -58,5 +63,8 @@ fn main() -> anyhow::Result<()> {
 
     out_file.write(&machine_code_bytes)?;
 
+    // Emulator tools use this to report source lines and labels instead of bare addresses
+    debug_info.write(File::create(debug_info_path)?)?;
+
     Ok(())
 }
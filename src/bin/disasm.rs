@@ -52,7 +52,17 @@ fn disassemble_instruction(instruction: u16) -> String {
 
         Opcode::Jmp => {
             let base_register = get_bits::<6, 8>(instruction);
-            parts.push(if base_register == 7 { "RET" } else { "JMP" }.to_string());
+            // JMPT/RTT is JMP with the lowest bit set
+            let privileged = get_bits::<0, 0>(instruction) == 1;
+            parts.push(
+                match (base_register, privileged) {
+                    (7, false) => "RET",
+                    (7, true) => "RTT",
+                    (_, false) => "JMP",
+                    (_, true) => "JMPT",
+                }
+                .to_string(),
+            );
             if base_register != 7 {
                 parts.push(format!("R{}", base_register));
             }
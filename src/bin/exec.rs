@@ -1,36 +1,694 @@
+use std::cell::RefCell;
 use std::env::args;
-use std::fs::File;
-use std::io::stdin;
-use std::io::stdout;
+use std::fs::{self, File};
+use std::io::{stdin, stdout, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use anyhow::{anyhow, Context};
+
+use alic3::debug_info::{parse_address, DebugInfo};
 use alic3::emulator::*;
 
+const USAGE: &str = "Usage: exec [OPTIONS] [OS] <PROGRAM>
+
+If no OS image is given, execution starts at the program's origin.
+
+Options:
+  --input <STRING>       Feed keyboard input from STRING (supports \\n, \\r, \\t, \\\\ escapes)
+  --input-file <PATH>    Feed keyboard input from the contents of PATH
+  --input-delay <N>      Make each scripted key available N instructions after the previous one is read
+  --output <PATH>        Write display output to PATH instead of the terminal
+  --non-interactive      Don't put the terminal into raw mode
+  --max-instructions <N> Stop after executing N instructions
+  --timeout <SECONDS>    Stop after running for SECONDS of wall-clock time. The clock is checked every 4096 steps, so
+                         the run may go on for a moment longer
+  --summary <PATH>       Write a JSON summary of the run to PATH
+  --hle <TRAPS>          Service the given traps natively instead of through the OS. TRAPS is a comma-separated list
+                         of getc, out, puts, in, putsp, and halt, or \"all\"
+  --edition <2|3>        Which edition of the textbook's ISA to emulate
+  --fault-policy <POLICY>
+                         What to do when delivering an exception faults: \"halt\" (the default), \"reset\", or
+                         \"vector:<VECTOR>\" to deliver a double fault exception through the given vector
+  --sanitize <MODE>      Check for reads of registers and memory that were never written: \"warn\" to report each
+                         one and carry on, or \"stop\" to stop at the first
+  --check-execution <MODE>
+                         Check for instructions fetched from .FILL, .BLKW, or .STRINGZ data (given a .dbg file) or
+                         from words the program modified after they were loaded: \"warn\" or \"stop\"
+  --display-delay <N>    Make the display take N cycles to print each character, during which DSR reads as not ready
+  --check-display <MODE> Check that the program waits for DSR before each write to DDR: \"warn\" (the default with
+                         --display-delay) or \"stop\"
+  --check-calls          Report subroutines that break the calling convention: R7 not saved, callee-saved registers
+                         not restored, R6 not balanced, or RET without a call
+  --callee-saved <REGS>  Which registers subroutines must preserve when checking calls, e.g. \"R1,R2,R3\" or \"none\"
+                         (default R1-R5). Implies --check-calls
+  --supervisor-stack <RANGE>
+                         Stop if the supervisor stack leaves RANGE, e.g. \"x2E00-x2FFF\"
+  --user-stack <RANGE>   Stop if R6 leaves RANGE while in user mode
+  --stack-policy <POLICY>
+                         What to do when a stack leaves its bounds: \"error\" (the default) or \"vector:<VECTOR>\"
+                         to deliver an exception through the given vector
+  --profile <PATH>       Write a report of how many instructions ran in each routine, at each address, and of each
+                         opcode, along with subroutine and trap call counts, to PATH
+  --profile-folded <PATH>
+                         Write instruction counts for each JSR/RET call stack to PATH, in the folded format taken
+                         by flamegraph.pl and inferno
+  --coverage <PATH>      Write the program's source to PATH, annotated with how many times each line executed and
+                         which ways each conditional branch went. Needs the program's .dbg file
+  --lcov <PATH>          Write the program's line and branch coverage to PATH as an LCOV tracefile
+  --memory-latency <N>   How many cycles each memory access takes in the textbook's state machine (default 5). The
+                         cycle count drives device timing, such as the interval timer at xFE08
+  --cycles <LIST>        Override the cycles taken by particular opcodes, e.g. \"ADD=4,LDI=20\". \"taken\" sets the
+                         extra cycles for a taken branch, and \"handler\" the cycles to enter an interrupt or
+                         exception handler
+  --framebuffer          Treat xC000-xFDFF as 128x124 video memory of 15-bit RGB pixels (red in bits 14-10, green in
+                         9-5, blue in 4-0)
+  --frame <PATH>         Save the framebuffer to PATH, a .png or .ppm file, when the run ends. Each write to VCR
+                         (xFE0C) saves it then as well, numbered, e.g. PATH-0001.png. Implies --framebuffer
+  --files <DIR>          Let the program open, read, and write files in DIR through the file device at xFE30-xFE34
+  --seed <N>             Seed the random number register at xFE18 with N, so that runs are repeatable. By default,
+                         it's seeded from the time
+  --block-cache          Cache predecoded blocks of instructions, which speeds up long-running programs
+  --jmpt                 Recognize the JMPT/RTT instruction (JMP with bit 0 set), which enters user mode
+  --protection <PATH>    Read which memory user mode may access from PATH. Each line is an address range and its
+                         permissions, e.g. \"x3000-x3FFF r-x\"; later lines take precedence. By default, user mode
+                         may access x3000-xFDFF
+
+Exit status:
+  0  The program halted by clearing the machine control register
+  1  The emulator couldn't start (bad arguments, missing files, etc.)
+  2  The instruction limit was reached
+  3  The time limit was reached
+  4  An exception was raised with no handler address in the interrupt vector table
+  5  The PC ran into uninitialized memory
+  6  An exception faulted while being delivered and the fault policy couldn't recover
+  7  The sanitizer caught a read of an uninitialized register or memory word, the execution checker caught an
+     instruction fetched from data or modified code, or the display checker caught a write to DDR while the
+     display wasn't ready
+  8  A stack left its bounds
+
+As in the textbook, exceptions and interrupts enter the handler whose address is stored in the interrupt vector
+table (x0100-x01FF), rather than executing the table entry itself.
+
+If the assembler left a .dbg file next to the OS or program, errors and warnings give source lines.";
+
+/// How often to check the wall clock, in steps. Checking every step would be needlessly slow.
+const TIMEOUT_CHECK_INTERVAL: u64 = 4096;
+
+/// Where keyboard input comes from
+enum Keyboard {
+    Terminal(ThreadedInput),
+    Script(ScriptedInput),
+}
+
+impl KeySource for Keyboard {
+    fn poll_key(&mut self, now: u64) -> Option<u8> {
+        match self {
+            Keyboard::Terminal(terminal) => terminal.poll_key(now),
+            Keyboard::Script(script) => script.poll_key(now),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    os_path: Option<String>,
+    program_path: String,
+    input: Option<Vec<u8>>,
+    input_delay: u64,
+    output_path: Option<String>,
+    non_interactive: bool,
+    max_instructions: Option<u64>,
+    timeout: Option<Duration>,
+    summary_path: Option<String>,
+    hle_traps: Vec<TrapRoutine>,
+    edition: Option<Edition>,
+    jmpt: bool,
+    fault_policy: FaultPolicy,
+    protection: Option<ProtectionMap>,
+    sanitizer: SanitizerMode,
+    execution_checker: SanitizerMode,
+    display_delay: u64,
+    display_checker: Option<SanitizerMode>,
+    calling_convention: Option<CallingConvention>,
+    stack_bounds: StackBounds,
+    stack_policy: StackPolicy,
+    profile_path: Option<String>,
+    folded_profile_path: Option<String>,
+    coverage_path: Option<String>,
+    memory_latency: Option<u32>,
+    cycle_overrides: Option<String>,
+    lcov_path: Option<String>,
+    block_cache: bool,
+    framebuffer: bool,
+    frame: Option<(PathBuf, ImageFormat)>,
+    files: Option<PathBuf>,
+    seed: Option<u64>,
+}
+
+/// What to save pictures of the framebuffer as
+#[derive(Clone, Copy)]
+enum ImageFormat {
+    Png,
+    Ppm,
+}
+
+/// Why the emulator stopped running
+enum Outcome {
+    Halted,
+    InstructionLimit,
+    TimeLimit,
+    Error(EmulatorError),
+}
+
+impl Outcome {
+    fn exit_code(&self) -> i32 {
+        match self {
+            Outcome::Halted => 0,
+            Outcome::InstructionLimit => 2,
+            Outcome::TimeLimit => 3,
+            Outcome::Error(EmulatorError::UnhandledException { .. }) => 4,
+            Outcome::Error(EmulatorError::UninitializedExecution { .. }) => 5,
+            Outcome::Error(EmulatorError::NestedFault(_)) => 6,
+            Outcome::Error(EmulatorError::Sanitizer(_)) => 7,
+            Outcome::Error(EmulatorError::StackFault(_)) => 8,
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        match self {
+            Outcome::Halted => "halted",
+            Outcome::InstructionLimit => "instruction_limit",
+            Outcome::TimeLimit => "time_limit",
+            Outcome::Error(EmulatorError::UnhandledException { .. }) => "unhandled_exception",
+            Outcome::Error(EmulatorError::UninitializedExecution { .. }) => "uninitialized_memory",
+            Outcome::Error(EmulatorError::NestedFault(_)) => "nested_fault",
+            Outcome::Error(EmulatorError::Sanitizer(_)) => "sanitizer",
+            Outcome::Error(EmulatorError::StackFault(_)) => "stack_fault",
+        }
+    }
+}
+
+/// Expand the handful of backslash escapes that are useful when passing input on the command line.
+fn unescape(string: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(string.len());
+    let mut chars = string.bytes();
+    while let Some(char) = chars.next() {
+        if char != b'\\' {
+            bytes.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(other) => bytes.push(other),
+            None => bytes.push(b'\\'),
+        }
+    }
+    bytes
+}
+
+fn parse_traps(list: &str) -> anyhow::Result<Vec<TrapRoutine>> {
+    list.split(',')
+        .map(|name| match name.trim().to_ascii_lowercase().as_str() {
+            "all" => Ok(TrapRoutine::ALL.to_vec()),
+            "getc" => Ok(vec![TrapRoutine::Getc]),
+            "out" => Ok(vec![TrapRoutine::Out]),
+            "puts" => Ok(vec![TrapRoutine::Puts]),
+            "in" => Ok(vec![TrapRoutine::In]),
+            "putsp" => Ok(vec![TrapRoutine::Putsp]),
+            "halt" => Ok(vec![TrapRoutine::Halt]),
+            _ => Err(anyhow!("Unknown trap {}", name)),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map(|routines| routines.concat())
+}
+
+/// Parse a comma-separated list of registers into a bitmask.
+fn parse_registers(list: &str) -> anyhow::Result<u8> {
+    if list == "none" {
+        return Ok(0);
+    }
+    list.split(',').try_fold(0, |mask, name| {
+        match name.trim().trim_start_matches(['R', 'r']).parse::<u8>() {
+            Ok(reg) if reg < 8 => Ok(mask | 1 << reg),
+            _ => Err(anyhow!("Unknown register {}", name)),
+        }
+    })
+}
+
+/// Parse an inclusive address range like `x2E00-x2FFF`.
+fn parse_range(range: &str) -> anyhow::Result<RangeInclusive<u16>> {
+    let (start, end) = range
+        .split_once('-')
+        .ok_or_else(|| anyhow!("Expected a range like x2E00-x2FFF, got {}", range))?;
+    Ok(parse_address(start)?..=parse_address(end)?)
+}
+
+/// Parse an exception vector, which is written in hex like an address but has to fit in a byte.
+fn parse_vector(vector: &str) -> anyhow::Result<u8> {
+    u8::try_from(parse_address(vector)?).map_err(|_| anyhow!("Invalid vector {}", vector))
+}
+
+fn parse_fault_policy(policy: &str) -> anyhow::Result<FaultPolicy> {
+    match policy.split_once(':') {
+        None if policy == "halt" => Ok(FaultPolicy::Halt),
+        None if policy == "reset" => Ok(FaultPolicy::Reset),
+        Some(("vector", vector)) => Ok(FaultPolicy::Vector(parse_vector(vector)?)),
+        _ => Err(anyhow!("Unknown fault policy {}", policy)),
+    }
+}
+
+fn parse_stack_policy(policy: &str) -> anyhow::Result<StackPolicy> {
+    match policy.split_once(':') {
+        None if policy == "error" => Ok(StackPolicy::Error),
+        Some(("vector", vector)) => Ok(StackPolicy::Vector(parse_vector(vector)?)),
+        _ => Err(anyhow!("Unknown stack policy {}", policy)),
+    }
+}
+
+fn parse_sanitizer_mode(mode: &str) -> anyhow::Result<SanitizerMode> {
+    match mode {
+        "warn" => Ok(SanitizerMode::Warn),
+        "stop" => Ok(SanitizerMode::Stop),
+        _ => Err(anyhow!("Unknown sanitizer mode {}", mode)),
+    }
+}
+
+/// Work out the image format from a path's extension.
+fn image_format(path: &Path) -> anyhow::Result<ImageFormat> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png") => Ok(ImageFormat::Png),
+        Some("ppm") => Ok(ImageFormat::Ppm),
+        _ => Err(anyhow!(
+            "Can't tell what format to save {} in; use .png or .ppm",
+            path.display()
+        )),
+    }
+}
+
+/// Save a picture of the framebuffer.
+fn save_frame(frame: &Frame, path: &Path, format: ImageFormat) -> anyhow::Result<()> {
+    let file = File::create(path).with_context(|| format!("Couldn't create {}", path.display()))?;
+    match format {
+        ImageFormat::Png => frame.write_png(file)?,
+        ImageFormat::Ppm => frame.write_ppm(file)?,
+    }
+    Ok(())
+}
+
+/// `frame.png` numbered as `frame-0001.png`.
+fn numbered(path: &Path, number: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}-{:04}.{}", stem, number, extension))
+}
+
+fn parse_args() -> anyhow::Result<Options> {
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| anyhow!("{} expects a value\n\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--input" => options.input = Some(unescape(&value()?)),
+            "--input-file" => {
+                let path = value()?;
+                options.input =
+                    Some(fs::read(&path).with_context(|| format!("Couldn't read {}", path))?);
+            }
+            "--input-delay" => options.input_delay = value()?.parse()?,
+            "--output" => options.output_path = Some(value()?),
+            "--non-interactive" => options.non_interactive = true,
+            "--max-instructions" => options.max_instructions = Some(value()?.parse()?),
+            "--timeout" => options.timeout = Some(Duration::from_secs_f64(value()?.parse()?)),
+            "--summary" => options.summary_path = Some(value()?),
+            "--hle" => options.hle_traps = parse_traps(&value()?)?,
+            "--jmpt" => options.jmpt = true,
+            "--block-cache" => options.block_cache = true,
+            "--framebuffer" => options.framebuffer = true,
+            "--seed" => options.seed = Some(value()?.parse()?),
+            "--files" => {
+                let dir = PathBuf::from(value()?);
+                if !dir.is_dir() {
+                    return Err(anyhow!("{} isn't a directory", dir.display()));
+                }
+                options.files = Some(dir);
+            }
+            "--frame" => {
+                let path = PathBuf::from(value()?);
+                let format = image_format(&path)?;
+                options.frame = Some((path, format));
+                options.framebuffer = true;
+            }
+            "--fault-policy" => options.fault_policy = parse_fault_policy(&value()?)?,
+            "--check-calls" => {
+                options
+                    .calling_convention
+                    .get_or_insert_with(Default::default);
+            }
+            "--callee-saved" => {
+                options
+                    .calling_convention
+                    .get_or_insert_with(Default::default)
+                    .callee_saved = parse_registers(&value()?)?
+            }
+            "--supervisor-stack" => options.stack_bounds.supervisor = Some(parse_range(&value()?)?),
+            "--user-stack" => options.stack_bounds.user = Some(parse_range(&value()?)?),
+            "--stack-policy" => options.stack_policy = parse_stack_policy(&value()?)?,
+            "--profile" => options.profile_path = Some(value()?),
+            "--profile-folded" => options.folded_profile_path = Some(value()?),
+            "--coverage" => options.coverage_path = Some(value()?),
+            "--lcov" => options.lcov_path = Some(value()?),
+            "--memory-latency" => options.memory_latency = Some(value()?.parse()?),
+            "--cycles" => options.cycle_overrides = Some(value()?),
+            "--sanitize" => options.sanitizer = parse_sanitizer_mode(&value()?)?,
+            "--check-execution" => options.execution_checker = parse_sanitizer_mode(&value()?)?,
+            "--display-delay" => options.display_delay = value()?.parse()?,
+            "--check-display" => options.display_checker = Some(parse_sanitizer_mode(&value()?)?),
+            "--protection" => {
+                let path = value()?;
+                let map = fs::read_to_string(&path)
+                    .with_context(|| format!("Couldn't read protection map {}", path))?;
+                options.protection = Some(
+                    ProtectionMap::parse(&map)
+                        .with_context(|| format!("In protection map {}", path))?,
+                );
+            }
+            "--edition" => {
+                options.edition = Some(match value()?.as_str() {
+                    "2" => Edition::Second,
+                    "3" => Edition::Third,
+                    edition => return Err(anyhow!("Unknown edition {}", edition)),
+                })
+            }
+            _ if arg.starts_with("--") => {
+                return Err(anyhow!("Unknown option {}\n\n{}", arg, USAGE))
+            }
+            _ => positional.push(arg),
+        }
+    }
+
+    match <[String; 2]>::try_from(positional) {
+        Ok([os_path, program_path]) => {
+            options.os_path = Some(os_path);
+            options.program_path = program_path;
+            Ok(options)
+        }
+        Err(positional) => match <[String; 1]>::try_from(positional) {
+            Ok([program_path]) => {
+                options.program_path = program_path;
+                Ok(options)
+            }
+            Err(_) => Err(anyhow!("Invalid arguments\n\n{}", USAGE)),
+        },
+    }
+}
+
+/// Load the debug info the assembler wrote next to an object file, if there is any.
+fn load_debug_info(object_path: &str, debug_info: &mut DebugInfo) -> anyhow::Result<()> {
+    let path = Path::new(object_path).with_extension("dbg");
+    if path.exists() {
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Couldn't read debug info {}", path.display()))?;
+        debug_info.merge(
+            DebugInfo::parse(&text).with_context(|| format!("In debug info {}", path.display()))?,
+        );
+    }
+    Ok(())
+}
+
+/// Read the source file an object file was assembled from. The path in its debug info is tried as given, then relative
+/// to the object file.
+fn read_source(object_path: &str, debug_info: &DebugInfo) -> anyhow::Result<String> {
+    let source = debug_info
+        .source()
+        .ok_or_else(|| anyhow!("No debug info for {}; assemble it again", object_path))?;
+    let beside_object = Path::new(object_path).with_file_name(source);
+    let path = if Path::new(source).exists() {
+        Path::new(source)
+    } else {
+        &beside_object
+    };
+    fs::read_to_string(path).with_context(|| format!("Couldn't read source {}", path.display()))
+}
+
+/// Where an address came from in the source, if known, e.g. ` (hello.asm:12)`.
+fn source_location(debug_info: &DebugInfo, addr: u16) -> String {
+    match (debug_info.line(addr), debug_info.source()) {
+        (Some(line), Some(source)) => format!(" ({}:{})", source, line),
+        (Some(line), None) => format!(" (line {})", line),
+        (None, _) => String::new(),
+    }
+}
+
 fn main() -> anyhow::Result<()> {
-    let args = args().collect::<Vec<String>>();
-    if args.len() != 3 {
-        return Err(anyhow::anyhow!("Invalid arguments"));
+    let options = parse_args()?;
+
+    let mut program_debug_info = DebugInfo::default();
+    load_debug_info(&options.program_path, &mut program_debug_info)?;
+    let mut debug_info = program_debug_info.clone();
+    if let Some(os_path) = &options.os_path {
+        load_debug_info(os_path, &mut debug_info)?;
     }
 
-    let os = File::open(&args[1])?;
-    let pgm = File::open(&args[2])?;
+    let os = options.os_path.as_ref().map(File::open).transpose()?;
+    let pgm = File::open(&options.program_path)?;
 
-    crossterm::terminal::enable_raw_mode()?;
+    // Only take over the terminal if we're actually going to read keys from it
+    let interactive = !options.non_interactive && options.input.is_none();
 
-    let stdin = stdin();
-    let stdout_v = stdout();
-    let mut cpu = Cpu::new(stdin, stdout_v);
-    cpu.load_program(os)?;
-    cpu.load_program(pgm)?;
+    let keyboard = match options.input {
+        Some(keys) => Keyboard::Script(ScriptedInput::new(keys, options.input_delay)),
+        None => Keyboard::Terminal(ThreadedInput::new(stdin())),
+    };
+    let display: Box<dyn Write> = match &options.output_path {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(stdout()),
+    };
 
-    cpu.pc = 0x0200;
-    loop {
-        cpu.step();
+    let mut cpu = Cpu::new(keyboard, display);
+    cpu.set_display_mode(if interactive && options.output_path.is_none() {
+        DisplayMode::Terminal
+    } else {
+        DisplayMode::Verbatim
+    });
+    if let Some(edition) = options.edition {
+        cpu.set_edition(edition);
+    }
+    cpu.set_jmpt_enabled(options.jmpt);
+    cpu.set_block_cache_enabled(options.block_cache);
+    cpu.set_framebuffer_enabled(options.framebuffer);
+    if let Some(dir) = &options.files {
+        cpu.enable_files(dir);
+    }
+    cpu.seed_random(options.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+    }));
+    let mut timing =
+        TimingModel::textbook(options.memory_latency.unwrap_or(DEFAULT_MEMORY_LATENCY));
+    if let Some(overrides) = &options.cycle_overrides {
+        timing.parse_overrides(overrides)?;
+    }
+    cpu.set_timing_model(timing);
+    cpu.set_fault_policy(options.fault_policy);
+    if let Some(map) = options.protection {
+        cpu.set_protection_map(map);
+    }
+    cpu.set_sanitizer(options.sanitizer);
+    cpu.set_execution_checker(options.execution_checker);
+    cpu.set_display_delay(options.display_delay);
+    cpu.set_display_checker(match options.display_checker {
+        Some(mode) => mode,
+        None if options.display_delay > 0 => SanitizerMode::Warn,
+        None => SanitizerMode::Off,
+    });
+    for range in debug_info.data() {
+        cpu.mark_data(range);
+    }
+    cpu.set_stack_bounds(options.stack_bounds);
+    cpu.set_stack_policy(options.stack_policy);
+    let call_checker = options
+        .calling_convention
+        .map(|convention| Rc::new(RefCell::new(CallChecker::new(convention))));
+    if let Some(call_checker) = &call_checker {
+        cpu.add_observer(Box::new(call_checker.clone()));
+    }
+    let profiler = if options.profile_path.is_some() || options.folded_profile_path.is_some() {
+        Some(Rc::new(RefCell::new(Profiler::new())))
+    } else {
+        None
+    };
+    if let Some(profiler) = &profiler {
+        cpu.add_observer(Box::new(profiler.clone()));
+    }
+    for routine in &options.hle_traps {
+        cpu.set_trap_hle(*routine, true);
+    }
+    if let Some(os) = os {
+        cpu.load_program(os)?;
+    }
+    let origin = cpu.load_program(pgm)?;
+    let coverage = if options.coverage_path.is_some() || options.lcov_path.is_some() {
+        let mut coverage = Coverage::new();
+        coverage.add_program(&cpu, &program_debug_info);
+        let coverage = Rc::new(RefCell::new(coverage));
+        cpu.add_observer(Box::new(coverage.clone()));
+        Some(coverage)
+    } else {
+        None
+    };
+
+    if interactive {
+        crossterm::terminal::enable_raw_mode()?;
+    }
+
+    // The OS starts at x0200 and jumps to the user program from there
+    cpu.pc = if options.os_path.is_some() {
+        0x0200
+    } else {
+        origin
+    };
+    let start_time = Instant::now();
+    let mut frames_saved = 0;
+    let mut steps: u64 = 0;
+    let outcome = loop {
+        let remaining = match options.max_instructions {
+            Some(max) if cpu.instructions_retired() >= max => break Outcome::InstructionLimit,
+            Some(max) => max - cpu.instructions_retired(),
+            None => u64::MAX,
+        };
+        if let Some(timeout) = options.timeout {
+            if steps.is_multiple_of(TIMEOUT_CHECK_INTERVAL) && start_time.elapsed() >= timeout {
+                break Outcome::TimeLimit;
+            }
+        }
+        steps += 1;
+
+        // Without the block cache this is a single step
+        match cpu.run_block(remaining) {
+            Ok(StepEvent::FaultRecovered(chain)) => {
+                eprintln!("Recovered from nested fault {}", chain);
+            }
+            Ok(_) => {}
+            Err(error) => break Outcome::Error(error),
+        }
+        for diagnostic in cpu.take_diagnostics() {
+            eprintln!(
+                "warning: {}{}",
+                diagnostic,
+                source_location(&debug_info, diagnostic.pc)
+            );
+        }
+
+        if cpu.take_frame_request() {
+            if let Some((path, format)) = &options.frame {
+                frames_saved += 1;
+                // Keep running rather than leave the terminal in raw mode
+                if let Err(error) = save_frame(&cpu.frame(), &numbered(path, frames_saved), *format)
+                {
+                    eprintln!("warning: {:#}", error);
+                }
+            }
+        }
 
         // Exit once the machine control register says to
         if cpu.should_halt() {
-            break;
+            break Outcome::Halted;
+        }
+    };
+
+    cpu.flush_display();
+    if interactive {
+        crossterm::terminal::disable_raw_mode()?;
+    }
+
+    if let Some((path, format)) = &options.frame {
+        save_frame(&cpu.frame(), path, *format)?;
+    }
+
+    if let Some(call_checker) = &call_checker {
+        for violation in call_checker.borrow_mut().take_violations() {
+            eprintln!(
+                "warning: {}{}",
+                violation,
+                source_location(&debug_info, violation.return_site)
+            );
+        }
+    }
+
+    if let Some(profiler) = &profiler {
+        let profiler = profiler.borrow();
+        if let Some(path) = &options.profile_path {
+            profiler.write_report(&debug_info, File::create(path)?)?;
+        }
+        if let Some(path) = &options.folded_profile_path {
+            profiler.write_folded(&debug_info, File::create(path)?)?;
+        }
+    }
+
+    if let Some(coverage) = &coverage {
+        let coverage = coverage.borrow();
+        if let Some(path) = &options.coverage_path {
+            let source = read_source(&options.program_path, &program_debug_info)?;
+            coverage.write_annotated(&program_debug_info, &source, File::create(path)?)?;
+        }
+        if let Some(path) = &options.lcov_path {
+            coverage.write_lcov(&program_debug_info, File::create(path)?)?;
+        }
+    }
+
+    if let Outcome::Error(error) = &outcome {
+        eprintln!("{}{}", error, source_location(&debug_info, error.pc()));
+    }
+
+    if let Some(path) = &options.summary_path {
+        write_summary(
+            path,
+            &options.program_path,
+            &outcome,
+            &cpu,
+            start_time.elapsed(),
+        )?;
+    }
+
+    exit(outcome.exit_code());
+}
+
+fn write_summary<I: KeySource, O: Write>(
+    path: &str,
+    program_path: &str,
+    outcome: &Outcome,
+    cpu: &Cpu<I, O>,
+    elapsed: Duration,
+) -> anyhow::Result<()> {
+    let mut summary = serde_json::json!({
+        "program": program_path,
+        "status": outcome.status(),
+        "exit_code": outcome.exit_code(),
+        "instructions": cpu.instructions_retired(),
+        "cycles": cpu.cycles(),
+        "pc": cpu.pc,
+        "elapsed_ms": elapsed.as_millis() as u64,
+    });
+    if let Outcome::Error(error) = outcome {
+        if let EmulatorError::UnhandledException { exception, .. } = error {
+            summary["exception"] = exception.to_string().into();
         }
+        summary["message"] = error.to_string().into();
     }
 
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", summary)?;
     Ok(())
 }
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::asm_parser::{Instruction, Program, PseudoOp};
+
+/// Maps addresses in an assembled program back to its source, so that tools can report source lines and labels instead
+/// of bare addresses.
+///
+/// The assembler writes this next to the object file as a `.dbg` text file, one entry per line:
+///
+/// ```text
+/// source hello.asm
+/// line x3000 4
+/// label x3000 MAIN
+/// data x3004 13
+/// ```
+///
+/// `line` gives the source line an address was assembled from, `label` names an address, and `data` marks a run of
+/// words reserved by `.FILL`, `.BLKW`, or `.STRINGZ` rather than assembled from instructions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    source: Option<String>,
+    lines: BTreeMap<u16, usize>,
+    labels: BTreeMap<u16, String>,
+    /// Start address and length of each run of data
+    data: BTreeMap<u16, u16>,
+}
+
+impl DebugInfo {
+    pub fn from_program(program: &Program) -> Self {
+        let mut info = DebugInfo::default();
+        for line in &program.lines {
+            info.lines.insert(line.location, line.line);
+            if let Some(label) = line.label {
+                info.labels.insert(line.location, label.to_string());
+            }
+            let data_length = match &line.instruction {
+                Instruction::PseudoOp(PseudoOp::Fill(_)) => 1,
+                Instruction::PseudoOp(PseudoOp::Blkw(n)) => *n,
+                Instruction::PseudoOp(PseudoOp::Stringz(string)) => string.len() as u16,
+                _ => 0,
+            };
+            if data_length > 0 {
+                info.data.insert(line.location, data_length);
+            }
+        }
+        info
+    }
+
+    /// The path of the source file, if known.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn set_source(&mut self, path: &str) {
+        self.source = Some(path.to_string());
+    }
+
+    /// The source line that the word at `addr` was assembled from.
+    pub fn line(&self, addr: u16) -> Option<usize> {
+        // Data spanning several words (.BLKW and .STRINGZ) only has an entry for its first word
+        match self.lines.range(..=addr).next_back() {
+            Some((&start, &line)) if start == addr || self.data_containing(addr) == Some(start) => {
+                Some(line)
+            }
+            _ => None,
+        }
+    }
+
+    /// Every address that has a source line, in order.
+    pub fn lines(&self) -> impl Iterator<Item = (u16, usize)> + '_ {
+        self.lines.iter().map(|(&addr, &line)| (addr, line))
+    }
+
+    /// The label attached to `addr`, if there is one.
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    /// The closest label at or before `addr`, and its address. This names the routine `addr` is in, for code laid out
+    /// one labelled routine after another.
+    pub fn enclosing_label(&self, addr: u16) -> Option<(u16, &str)> {
+        self.labels
+            .range(..=addr)
+            .next_back()
+            .map(|(&addr, name)| (addr, name.as_str()))
+    }
+
+    /// The address of a label, if it's defined.
+    pub fn address_of(&self, label: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .find(|(_, name)| name.as_str() == label)
+            .map(|(&addr, _)| addr)
+    }
+
+    /// Every label and its address, in address order.
+    pub fn labels(&self) -> impl Iterator<Item = (u16, &str)> + '_ {
+        self.labels
+            .iter()
+            .map(|(&addr, name)| (addr, name.as_str()))
+    }
+
+    /// Whether `addr` was reserved as data rather than assembled from an instruction.
+    pub fn is_data(&self, addr: u16) -> bool {
+        self.data_containing(addr).is_some()
+    }
+
+    /// Every run of data, in address order.
+    pub fn data(&self) -> impl Iterator<Item = RangeInclusive<u16>> + '_ {
+        self.data
+            .iter()
+            .map(|(&start, &length)| start..=start.wrapping_add(length - 1))
+    }
+
+    /// The start of the run of data containing `addr`
+    fn data_containing(&self, addr: u16) -> Option<u16> {
+        let (&start, &length) = self.data.range(..=addr).next_back()?;
+        if addr - start < length {
+            Some(start)
+        } else {
+            None
+        }
+    }
+
+    /// Add everything from another program's debug info, e.g. when an OS and a user program are both loaded. Where the
+    /// two overlap, the other program's entries win. Only one source file is kept.
+    pub fn merge(&mut self, other: DebugInfo) {
+        if self.source.is_none() {
+            self.source = other.source;
+        }
+        self.lines.extend(other.lines);
+        self.labels.extend(other.labels);
+        self.data.extend(other.data);
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        if let Some(source) = &self.source {
+            writeln!(writer, "source {}", source)?;
+        }
+        for (addr, line) in &self.lines {
+            writeln!(writer, "line x{:04X} {}", addr, line)?;
+        }
+        for (addr, label) in &self.labels {
+            writeln!(writer, "label x{:04X} {}", addr, label)?;
+        }
+        for (addr, length) in &self.data {
+            writeln!(writer, "data x{:04X} {}", addr, length)?;
+        }
+        Ok(())
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut info = DebugInfo::default();
+        for (line_number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            info.parse_line(line)
+                .with_context(|| format!("Line {} of debug info", line_number + 1))?;
+        }
+        Ok(info)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<()> {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next().unwrap_or_default();
+        if kind == "source" {
+            // The path may contain spaces
+            self.source = Some(line.trim_start()["source".len()..].trim().to_string());
+            return Ok(());
+        }
+
+        let addr = fields
+            .next()
+            .ok_or_else(|| anyhow!("Expected an address"))?;
+        let addr = parse_address(addr)?;
+        let value = fields.next().ok_or_else(|| anyhow!("Expected a value"))?;
+        match kind {
+            "line" => {
+                self.lines.insert(addr, value.parse()?);
+            }
+            "label" => {
+                self.labels.insert(addr, value.to_string());
+            }
+            "data" => {
+                let length = value.parse()?;
+                if length == 0 {
+                    return Err(anyhow!("Empty data at x{:04X}", addr));
+                }
+                self.data.insert(addr, length);
+            }
+            _ => return Err(anyhow!("Unknown entry {:?}", kind)),
+        }
+        Ok(())
+    }
+}
+
+/// Parse an address written in hex, with or without an `x` or `0x` prefix. Debug info files, protection maps, and the
+/// emulator's command line all write addresses this way.
+pub fn parse_address(string: &str) -> Result<u16> {
+    let digits = ["0x", "0X", "x", "X"]
+        .iter()
+        .find_map(|prefix| string.strip_prefix(prefix))
+        .unwrap_or(string);
+    u16::from_str_radix(digits, 16).with_context(|| format!("Invalid address {:?}", string))
+}
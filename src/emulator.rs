@@ -1,15 +1,70 @@
 use std::cell::RefCell;
+use std::collections::HashSet;
 use std::io::prelude::*;
-use std::io::{Stdin, Stdout};
 
-use byteorder::ReadBytesExt;
-use byteorder::{BigEndian, WriteBytesExt};
+use byteorder::{BigEndian, ReadBytesExt};
 
 use crate::bit_twiddling::*;
 use crate::opcode::*;
 
+mod block_cache;
+mod call_stack;
+mod calls;
+mod clock;
+mod coverage;
+mod display;
+mod error;
+mod events;
+mod files;
+mod framebuffer;
+mod history;
+mod hle;
+mod input;
+mod microarch;
+mod profiler;
+mod protection;
+mod random;
+mod sanitizer;
+mod stack;
+mod timing;
+use block_cache::BlockCache;
+pub use calls::{CallChecker, CallingConvention, Violation, ViolationKind};
+use clock::Clock;
+pub use coverage::{BranchCounts, Coverage};
+use display::Display;
+pub use display::DisplayMode;
+pub use error::{EmulatorError, Exception, Fault, FaultChain, FaultPolicy, FaultReason};
+pub use events::{Observer, StepEvent, StepRecord};
+use files::FileDevice;
+pub use files::{file_command, FileError, FILE_HANDLES};
+use framebuffer::Framebuffer;
+pub use framebuffer::{Frame, FRAMEBUFFER_HEIGHT, FRAMEBUFFER_START, FRAMEBUFFER_WIDTH};
+use history::{History, UndoRecord};
+pub use hle::TrapRoutine;
+pub use input::{KeySource, ScriptedInput, ThreadedInput};
+pub use microarch::{Gate, Latch, MicroStep, Microsequencer};
+pub use profiler::Profiler;
+pub use protection::{Access, Permissions, ProtectionMap};
+use random::Random;
+use sanitizer::Sanitizer;
+pub use sanitizer::{Diagnostic, DiagnosticKind, SanitizerMode};
+pub use stack::{Stack, StackBounds, StackFault, StackFaultKind, StackPolicy};
+use timing::Timer;
+pub use timing::{TimingModel, DEFAULT_MEMORY_LATENCY};
+
 const MEMORY_SIZE: usize = (u16::MAX as usize) + 1;
 
+/// Start of the table holding the addresses of interrupt and exception handlers
+const INTERRUPT_VECTOR_TABLE: u16 = 0x0100;
+/// Start of OS code, just past the trap and interrupt vector tables. This is also where execution begins on reset.
+const SYSTEM_CODE: u16 = 0x0200;
+/// Initial stack pointers. We decrement the stack pointer before writing and increment after reading, so these start at
+/// 1 after the addresses at which the stack actually begins. Stacks grow downwards in memory.
+const INITIAL_SSP: u16 = 0x3000;
+const INITIAL_USP: u16 = 0xFE00;
+/// Start of the memory-mapped device registers
+const DEVICE_REGISTERS: u16 = 0xFE00;
+
 /// Memory addresses of all memory-mapped registers
 struct MemRegisters {}
 
@@ -36,21 +91,96 @@ impl MemRegisters {
     /// Display data register.
     /// Writing a character into this register will print that character to the display.
     const DDR: u16 = 0xFE06;
+
+    /// Timer status register. See `Timer`.
+    const TMR: u16 = 0xFE08;
+    /// Timer interval register
+    const TMI: u16 = 0xFE0A;
+
+    /// Video control register. When the framebuffer is enabled, writing anything here asks for the picture to be
+    /// saved.
+    const VCR: u16 = 0xFE0C;
+
+    /// Random number register. See `Random`.
+    const RNG: u16 = 0xFE18;
+
+    /// First of the clock registers, ICR0. See `Clock`.
+    const CLOCK_START: u16 = 0xFE20;
+    /// Last of the clock registers, WCR3
+    const CLOCK_END: u16 = 0xFE2E;
+
+    /// File status register. See `FileDevice`. The file registers stay clear of xFE12, which the textbook uses for
+    /// the memory protection register.
+    const FSR: u16 = 0xFE30;
+    /// File command register
+    const FCR: u16 = 0xFE32;
+    /// File data register
+    const FDR: u16 = 0xFE34;
 }
 
-struct Memory<Input: Read, Output: Write> {
+/// How a memory word got its current value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WordState {
+    Uninitialized,
+    /// Loaded as part of a program
+    Loaded,
+    /// Written by the running program
+    Written,
+}
+
+struct Memory<Input: KeySource, Output: Write> {
     memory: [u16; MEMORY_SIZE],
+    state: Vec<WordState>,
+    /// Whether each memory word was reserved as data (by .FILL, .BLKW, or .STRINGZ) rather than assembled from an
+    /// instruction, if we were told
+    data: Vec<bool>,
     keyboard_io: RefCell<KeyboardIO<Input>>,
-    stdout: Output,
+    display: RefCell<Display<Output>>,
+    timer: Timer,
+    random: Random,
+    clock: Clock,
+    /// The number of instructions executed so far
+    instructions_retired: u64,
+    /// The number of clock cycles elapsed so far. Devices use this to keep time.
+    cycles: u64,
+    /// When recording history, the address, previous value, and previous state of every memory word written, in order
+    journal: Option<Vec<(u16, u16, WordState)>>,
+    /// Predecoded blocks of instructions, if enabled
+    blocks: Option<BlockCache<Input, Output>>,
+    /// Set when the block being run has to stop early: a store to a device register, or a write to cached code
+    leave_block: bool,
+    /// Video memory, if enabled
+    framebuffer: Option<Framebuffer>,
+    /// The file device, if enabled
+    files: Option<FileDevice>,
 }
 
-impl<Input: Read, Output: Write> Memory<Input, Output> {
+impl<Input: KeySource, Output: Write> Memory<Input, Output> {
     fn get(&self, addr: u16) -> u16 {
         match addr {
-            MemRegisters::KBSR => self.keyboard_io.borrow_mut().read_kbsr(),
-            MemRegisters::KBDR => self.keyboard_io.borrow_mut().read_kbdr(),
-            // The display is always ready for more data
-            MemRegisters::DSR => 0x8000,
+            MemRegisters::KBSR | MemRegisters::KBDR => {
+                // Show whatever's been printed before possibly waiting on the keyboard, such as a prompt
+                self.display.borrow_mut().flush();
+                let mut keyboard_io = self.keyboard_io.borrow_mut();
+                // Scripted keys are timed in instructions, whatever the timing model
+                if addr == MemRegisters::KBSR {
+                    keyboard_io.read_kbsr(self.instructions_retired)
+                } else {
+                    keyboard_io.read_kbdr(self.instructions_retired)
+                }
+            }
+            MemRegisters::DSR => self.display.borrow().read_dsr(self.cycles),
+            MemRegisters::TMR => self.timer.read_tmr(),
+            MemRegisters::TMI => self.timer.read_tmi(),
+            MemRegisters::RNG => self.random.read_rng(),
+            MemRegisters::CLOCK_START..=MemRegisters::CLOCK_END => self
+                .clock
+                .read(addr - MemRegisters::CLOCK_START, self.instructions_retired),
+            MemRegisters::FSR | MemRegisters::FDR => match &self.files {
+                Some(files) if addr == MemRegisters::FSR => files.read_fsr(),
+                Some(files) => files.read_fdr(),
+                None => self.memory[addr as usize],
+            },
             _ => self.memory[addr as usize],
         }
     }
@@ -59,34 +189,74 @@ impl<Input: Read, Output: Write> Memory<Input, Output> {
         match addr {
             // Ignore writes into status/read-only registers
             MemRegisters::KBSR | MemRegisters::KBDR | MemRegisters::DSR => (),
-            MemRegisters::DDR => {
-                // Because we're in raw mode so we can get each character individually, line feeds don't reset the
-                // cursor position to the left. Do that manually.
-                // TODO: proper terminal driver
-                if value == (b'\n' as u16) {
-                    let _ = self.stdout.write_u8(b'\r');
+            MemRegisters::CLOCK_START..=MemRegisters::CLOCK_END => (),
+            MemRegisters::RNG => self.random.seed(value as u64),
+            MemRegisters::TMR => self.timer.write_tmr(value),
+            MemRegisters::TMI => self.timer.write_tmi(value, self.cycles),
+            MemRegisters::DDR => self.display.get_mut().write(value, self.cycles),
+            MemRegisters::VCR => {
+                if self.framebuffer.is_some() {
+                    self.request_frame();
+                } else {
+                    self.store(addr, value);
                 }
-                // Ignore potential errors writing to stdout
-                let _ = self.stdout.write_u8((value & 0xFF) as u8);
-                // TODO: flushing stdout after every character seems expensive but also the only way to ensure proper
-                // emulation.
-                let _ = self.stdout.flush();
             }
-            _ => {
-                self.memory[addr as usize] = value;
+            MemRegisters::FSR | MemRegisters::FCR | MemRegisters::FDR => {
+                if self.files.is_some() {
+                    self.write_file_register(addr, value);
+                } else {
+                    self.store(addr, value);
+                }
             }
+            _ => self.store(addr, value),
         };
     }
+
+    /// Write an ordinary memory word, or a register that no enabled device claims.
+    fn store(&mut self, addr: u16, value: u16) {
+        if let Some(journal) = &mut self.journal {
+            journal.push((addr, self.memory[addr as usize], self.state[addr as usize]));
+        }
+        self.invalidate(addr);
+        self.memory[addr as usize] = value;
+        self.state[addr as usize] = WordState::Written;
+        // Halting. Show everything the program printed.
+        if addr == MemRegisters::MCR && get_bits::<15, 15>(value) == 0 {
+            self.display.get_mut().flush();
+        }
+    }
+
+    /// Place a word into memory as part of loading a program. Unlike `set`, this bypasses devices.
+    fn load(&mut self, addr: u16, value: u16) {
+        self.invalidate(addr);
+        self.memory[addr as usize] = value;
+        self.state[addr as usize] = WordState::Loaded;
+    }
+
+    fn is_initialized(&self, addr: u16) -> bool {
+        self.state[addr as usize] != WordState::Uninitialized
+    }
+
+    /// Forget anything derived from the word at an address that's about to change: its predecoded instruction, and
+    /// the picture if it's a pixel.
+    fn invalidate(&mut self, addr: u16) {
+        self.touch_framebuffer(addr);
+        if let Some(blocks) = &mut self.blocks {
+            if blocks.invalidate(addr) {
+                self.leave_block = true;
+            }
+        }
+    }
 }
 
-struct KeyboardIO<T: Read> {
+struct KeyboardIO<T: KeySource> {
     need_more_input: bool,
     kbsr: bool,
     kbdr: u16,
     stdin: T,
 }
 
-impl<T: Read> KeyboardIO<T> {
+impl<T: KeySource> KeyboardIO<T> {
     fn new(stdin: T) -> Self {
         KeyboardIO {
             need_more_input: true,
@@ -96,8 +266,8 @@ impl<T: Read> KeyboardIO<T> {
         }
     }
 
-    fn update_input(&mut self) {
-        if let Ok(keycode) = self.stdin.read_u8() {
+    fn update_input(&mut self, now: u64) {
+        if let Some(keycode) = self.stdin.poll_key(now) {
             self.kbdr = keycode as u16;
             self.kbsr = true;
             self.need_more_input = false;
@@ -106,9 +276,9 @@ impl<T: Read> KeyboardIO<T> {
         }
     }
 
-    fn read_kbsr(&mut self) -> u16 {
+    fn read_kbsr(&mut self, now: u64) -> u16 {
         if self.need_more_input {
-            self.update_input();
+            self.update_input(now);
         }
 
         if self.kbsr {
@@ -118,9 +288,9 @@ impl<T: Read> KeyboardIO<T> {
         }
     }
 
-    fn read_kbdr(&mut self) -> u16 {
+    fn read_kbdr(&mut self, now: u64) -> u16 {
         if self.need_more_input {
-            self.update_input();
+            self.update_input(now);
         }
 
         self.need_more_input = true;
@@ -128,13 +298,36 @@ impl<T: Read> KeyboardIO<T> {
     }
 }
 
+/// Which edition of the textbook's LC-3 to emulate. The two differ in a couple of places:
+/// - In the second edition, LEA sets the condition codes. In the third edition, it doesn't.
+/// - In the second edition, TRAP saves the return address in R7. In the third edition, it pushes the PSR and PC onto
+///   the supervisor stack like an interrupt, and the service routine returns with RTI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edition {
+    Second,
+    Third,
+}
+
+impl Default for Edition {
+    /// The `third_edition` feature picks which edition is used unless told otherwise.
+    fn default() -> Self {
+        if cfg!(feature = "third_edition") {
+            Edition::Third
+        } else {
+            Edition::Second
+        }
+    }
+}
+
 const COND_NEGATIVE: u16 = 0b100;
 const COND_ZERO: u16 = 0b010;
 const COND_POSITIVE: u16 = 0b001;
 
-pub struct Cpu<Input: Read, Output: Write> {
+pub struct Cpu<Input: KeySource, Output: Write> {
     /// All CPU registers
     registers: [u16; 8],
+    /// Bitmask of registers that have been written since power-on or reset
+    registers_initialized: u8,
     /// RAM
     memory: Memory<Input, Output>,
     /// Program counter
@@ -143,35 +336,138 @@ pub struct Cpu<Input: Read, Output: Write> {
     saved_usp: u16,
     /// The saved supervisor mode stack pointer
     saved_ssp: u16,
+    /// Undo log for reverse execution, if enabled
+    history: Option<History>,
+    /// An error raised partway through executing an instruction, to be returned from `step`
+    pending_error: Option<EmulatorError>,
+    /// Bitmask of trap routines to service natively instead of jumping into the OS. See `TrapRoutine::mask`.
+    hle_traps: u8,
+    /// Whether a natively serviced IN has already printed its prompt while waiting for a key
+    hle_prompted: bool,
+    /// Which edition of the ISA to emulate
+    edition: Edition,
+    /// Whether JMP with bit 0 set is treated as JMPT/RTT, a jump that also enters user mode
+    jmpt_enabled: bool,
+    /// What to do when delivering an exception faults
+    fault_policy: FaultPolicy,
+    /// What memory user mode code may access
+    protection: ProtectionMap,
+    /// Checks for reads of uninitialized registers and memory
+    sanitizer: Sanitizer,
+    /// Where the stacks may grow, and what to do if they leave those regions
+    stack_bounds: StackBounds,
+    stack_policy: StackPolicy,
+    /// How many cycles each instruction takes
+    timing: TimingModel,
+    /// What happened during the current step, to be returned from `step`
+    event: StepEvent,
+    /// An interrupt waiting for the processor's priority level to drop low enough to be accepted, along with its
+    /// priority
+    pending_interrupt: Option<(u8, u16)>,
+    /// Everything watching the emulator run
+    observers: Vec<Box<dyn Observer>>,
 }
 
-impl<Input: Read, Output: Write> Cpu<Input, Output> {
+impl<Input: KeySource, Output: Write> Cpu<Input, Output> {
     pub fn new(stdin: Input, stdout: Output) -> Self {
         let mut mem_raw = [0u16; MEMORY_SIZE];
         // Initialize MCR so we don't halt immediately
         mem_raw[MemRegisters::MCR as usize] = 0xFFFF;
+        // Device registers always hold meaningful values, even if they've never been written to
+        let mut state = vec![WordState::Uninitialized; MEMORY_SIZE];
+        state[DEVICE_REGISTERS as usize..].fill(WordState::Loaded);
         Cpu {
             registers: [0u16; 8],
+            registers_initialized: 0,
             memory: Memory {
                 memory: mem_raw,
+                state,
+                data: vec![false; MEMORY_SIZE],
                 keyboard_io: RefCell::new(KeyboardIO::new(stdin)),
-                stdout,
+                display: RefCell::new(Display::new(stdout)),
+                timer: Timer::default(),
+                random: Random::new(),
+                clock: Clock::default(),
+                instructions_retired: 0,
+                cycles: 0,
+                journal: None,
+                blocks: None,
+                leave_block: false,
+                framebuffer: None,
+                files: None,
             },
             pc: 0u16,
-            // We decrement the stack pointer before writing and increment after reading, so these start at 1 after the
-            // addresses at which the stack actually begins. Stacks grow downwards in memory.
-            saved_ssp: 0x3000,
-            saved_usp: 0xFE00,
+            saved_ssp: INITIAL_SSP,
+            saved_usp: INITIAL_USP,
+            history: None,
+            pending_error: None,
+            hle_traps: 0,
+            hle_prompted: false,
+            edition: Edition::default(),
+            jmpt_enabled: false,
+            fault_policy: FaultPolicy::default(),
+            protection: ProtectionMap::default(),
+            sanitizer: Sanitizer::default(),
+            stack_bounds: StackBounds::default(),
+            stack_policy: StackPolicy::default(),
+            timing: TimingModel::default(),
+            event: StepEvent::Executed,
+            pending_interrupt: None,
+            observers: Vec::new(),
         }
     }
 
-    fn address_accessible(&self, addr: u16) -> bool {
+    fn address_accessible(&self, addr: u16, access: Access) -> bool {
         // Only protect memory if not in privileged mode
         if get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 0 {
             return true;
         }
 
-        addr >= 0x3000 && addr <= 0xFDFF
+        self.protection.permissions(addr).allows(access)
+    }
+
+    /// Check a memory access on behalf of the running program. Every instruction fetch, load, store, and stack
+    /// operation goes through here. If the access isn't allowed, an access control violation is raised and `None` is
+    /// returned, in which case the instruction must stop without any further effects.
+    fn check_access(&mut self, addr: u16, access: Access) -> Option<()> {
+        if !self.address_accessible(addr, access) {
+            self.raise(Exception::AccessControlViolation);
+            return None;
+        }
+        Some(())
+    }
+
+    /// Load a word on behalf of the running program, like `check_access`.
+    fn read(&mut self, addr: u16) -> Option<u16> {
+        self.check_access(addr, Access::Read)?;
+        self.sanitize_memory(addr)?;
+        Some(self.memory.get(addr))
+    }
+
+    /// Store a word on behalf of the running program, like `check_access`.
+    fn write(&mut self, addr: u16, value: u16) -> Option<()> {
+        self.check_access(addr, Access::Write)?;
+        self.sanitize_store(addr)?;
+        self.memory.set(addr, value);
+        // Devices can do anything, including halting the machine
+        if addr >= DEVICE_REGISTERS {
+            self.memory.leave_block = true;
+        }
+        Some(())
+    }
+
+    /// Fetch an instruction, like `check_access`.
+    fn fetch(&mut self, addr: u16) -> Option<u16> {
+        self.check_access(addr, Access::Execute)?;
+        Some(self.memory.get(addr))
+    }
+
+    /// Push a value onto the current stack.
+    fn push(&mut self, value: u16) -> Option<()> {
+        let addr = self.registers[6].wrapping_sub(1);
+        self.write(addr, value)?;
+        self.set_register(6, addr);
+        Some(())
     }
 
     fn enter_supervisor_mode(&mut self) {
@@ -179,7 +475,7 @@ impl<Input: Read, Output: Write> Cpu<Input, Output> {
         if get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 1 {
             // Switch from the user stack pointer to the system stack pointer
             self.saved_usp = self.registers[6];
-            self.registers[6] = self.saved_ssp;
+            self.set_register(6, self.saved_ssp);
             // Clear the "is user mode" bit of the PSR
             self.memory.set(
                 MemRegisters::PSR,
@@ -187,28 +483,192 @@ impl<Input: Read, Output: Write> Cpu<Input, Output> {
             );
         }
 
-        // Push old MemRegisters::PSR and PC to stack
-        self.registers[6] -= 1;
-        self.memory.set(self.registers[6], old_psr);
-        self.registers[6] -= 1;
-        self.memory.set(self.registers[6], self.pc);
+        // Push old MemRegisters::PSR and PC to stack. We're in supervisor mode by now, so these can't raise an access
+        // control violation; whether they land somewhere sensible is up to `check_delivery`.
+        self.push(old_psr);
+        self.push(self.pc);
     }
 
+    /// Enter the handler for an exception or interrupt. As in the textbook, each entry of the vector table holds the
+    /// address of a handler, rather than being the start of the handler itself.
     fn handle_exception(&mut self, exception_vector: u8) {
-        // TODO: it's possible to trigger an exception in an exception handler, etc. ad infinitum.
-        // A triple-fault handler would technically be against spec but probably useful.
+        // Callers are expected to have checked that this won't fault; see `check_delivery`.
         self.enter_supervisor_mode();
-        let exception_addr = (exception_vector as u16) | 0x0100;
-        self.pc = exception_addr;
+        // Jump to the handler whose address is stored in the vector table
+        self.pc = self
+            .memory
+            .get(INTERRUPT_VECTOR_TABLE | (exception_vector as u16));
     }
 
-    fn handle_interrupt(&mut self, interrupt_vector: u8, priority_level: u16) {
-        if priority_level <= self.get_priority_level() {
+    /// Check whether an exception or interrupt can be delivered without faulting. The PSR and PC have to be pushed
+    /// into ordinary memory (not over the vector tables or device registers) within the supervisor stack's bounds, and
+    /// there has to be a handler to jump to.
+    fn check_delivery(&self, vector: u8) -> Result<(), FaultReason> {
+        let ssp = if get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 1 {
+            self.saved_ssp
+        } else {
+            self.registers[6]
+        };
+        for addr in [ssp.wrapping_sub(1), ssp.wrapping_sub(2)] {
+            if !(SYSTEM_CODE..DEVICE_REGISTERS).contains(&addr)
+                || !self.stack_bounds.contains(Stack::Supervisor, addr)
+            {
+                return Err(FaultReason::StackPush { addr });
+            }
+        }
+
+        if !self
+            .memory
+            .is_initialized(INTERRUPT_VECTOR_TABLE | (vector as u16))
+        {
+            return Err(FaultReason::NoHandler);
+        }
+
+        Ok(())
+    }
+
+    /// Raise an exception caused by the instruction currently being executed.
+    fn raise(&mut self, exception: Exception) {
+        let pc = self.pc.wrapping_sub(1);
+        // Running out of supervisor stack while pushing the PSR and PC is a stack fault, like it would be for TRAP,
+        // rather than the delivery itself faulting
+        let ssp = if get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 1 {
+            self.saved_ssp
+        } else {
+            self.registers[6]
+        };
+        if self
+            .check_stack_pointer(Stack::Supervisor, ssp, ssp.wrapping_sub(2))
+            .is_none()
+        {
             return;
         }
 
-        self.handle_exception(interrupt_vector);
-        self.set_priority_level(priority_level);
+        match self.check_delivery(exception.vector()) {
+            Ok(()) => {
+                self.handle_exception(exception.vector());
+                self.event = StepEvent::Exception { cause: exception };
+            }
+            // If nothing was loaded into the vector table, there's no handler to jump to. Stop at the offending
+            // instruction instead of jumping off into the weeds.
+            Err(FaultReason::NoHandler) => {
+                self.pc = pc;
+                self.pending_error = Some(EmulatorError::UnhandledException { exception, pc });
+            }
+            Err(reason) => self.handle_nested_fault(FaultChain {
+                pc,
+                faults: vec![Fault {
+                    vector: exception.vector(),
+                    reason,
+                }],
+            }),
+        }
+    }
+
+    /// Deal with an exception that faulted while being delivered, according to the fault policy. Without this, a
+    /// handler that faults itself (for instance, because the supervisor stack ran into the vector table) would recurse
+    /// forever.
+    fn handle_nested_fault(&mut self, mut chain: FaultChain) {
+        match self.fault_policy {
+            FaultPolicy::Halt => {}
+            FaultPolicy::Reset => {
+                self.reset();
+                self.event = StepEvent::FaultRecovered(chain);
+                return;
+            }
+            FaultPolicy::Vector(vector) => {
+                // The supervisor stack is the likely culprit, so give the double fault handler a fresh one
+                let in_user_mode = get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 1;
+                let ssp = if in_user_mode {
+                    &mut self.saved_ssp
+                } else {
+                    &mut self.registers[6]
+                };
+                let old_ssp = std::mem::replace(ssp, INITIAL_SSP);
+
+                match self.check_delivery(vector) {
+                    Ok(()) => {
+                        self.handle_exception(vector);
+                        self.event = StepEvent::FaultRecovered(chain);
+                        return;
+                    }
+                    // Triple fault. Give up.
+                    Err(reason) => {
+                        chain.faults.push(Fault { vector, reason });
+                        if in_user_mode {
+                            self.saved_ssp = old_ssp;
+                        } else {
+                            self.registers[6] = old_ssp;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pc = chain.pc;
+        self.pending_error = Some(EmulatorError::NestedFault(chain));
+    }
+
+    /// Put the processor back into its initial state and restart the OS. Memory is left alone.
+    fn reset(&mut self) {
+        self.registers = [0u16; 8];
+        self.registers_initialized = 0;
+        self.memory.set(MemRegisters::PSR, 0);
+        self.saved_ssp = INITIAL_SSP;
+        self.saved_usp = INITIAL_USP;
+        self.pc = SYSTEM_CODE;
+    }
+
+    /// Accept an interrupt if its priority is higher than that of whatever's currently running. Returns whether it was
+    /// accepted.
+    fn handle_interrupt(&mut self, interrupt_vector: u8, priority_level: u16) -> bool {
+        if priority_level <= self.get_priority_level() {
+            return false;
+        }
+
+        // As with exceptions, running out of supervisor stack is a stack fault. No instruction is to blame, so the PC
+        // stays on the one that was about to run.
+        let ssp = if get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 1 {
+            self.saved_ssp
+        } else {
+            self.registers[6]
+        };
+        let sp = ssp.wrapping_sub(2);
+        if let Some(kind) = self.stack_bounds.check(Stack::Supervisor, ssp, sp) {
+            self.stack_fault(StackFault {
+                stack: Stack::Supervisor,
+                kind,
+                pc: self.pc,
+                sp,
+            });
+            return true;
+        }
+
+        match self.check_delivery(interrupt_vector) {
+            Ok(()) => {
+                self.handle_exception(interrupt_vector);
+                self.set_priority_level(priority_level);
+                self.event = StepEvent::InterruptAccepted {
+                    vector: interrupt_vector,
+                    priority: priority_level,
+                };
+            }
+            Err(reason) => self.handle_nested_fault(FaultChain {
+                pc: self.pc,
+                faults: vec![Fault {
+                    vector: interrupt_vector,
+                    reason,
+                }],
+            }),
+        }
+        true
+    }
+
+    /// Request an interrupt from a device. It's accepted before the next instruction whose priority level is lower.
+    /// Only one interrupt can be pending at a time; a new request replaces any earlier one that hasn't been accepted.
+    pub fn request_interrupt(&mut self, vector: u8, priority_level: u16) {
+        assert!(priority_level <= 7);
+        self.pending_interrupt = Some((vector, priority_level));
     }
 
     fn get_priority_level(&self) -> u16 {
@@ -223,6 +683,20 @@ impl<Input: Read, Output: Write> Cpu<Input, Output> {
         );
     }
 
+    /// Replace the lower 3 bits of the PSR with condition codes describing the given result.
+    fn set_condition_codes(&mut self, result: u16) {
+        self.memory.set(
+            MemRegisters::PSR,
+            (self.memory.get(MemRegisters::PSR) & !0b111)
+                | match (result as i16).signum() {
+                    -1 => COND_NEGATIVE,
+                    0 => COND_ZERO,
+                    1 => COND_POSITIVE,
+                    _ => unreachable!(),
+                },
+        );
+    }
+
     /// Read the contents of the register specified in the high bits of an instruction (a common operation).
     /// This is usually the destination register for an operation, but can be the source register if we're starved for
     /// bits.
@@ -236,8 +710,11 @@ impl<Input: Read, Output: Write> Cpu<Input, Output> {
         self.registers[get_bits::<6, 8>(instruction) as usize]
     }
 
-    /// Decode and execute a single instruction. Specialized based on opcode.
-    pub fn execute_instruction<const OP: u8>(&mut self, instruction: u16) {
+    /// Decode and execute a single instruction. Specialized based on opcode and ISA edition.
+    pub fn execute_instruction<const OP: u8, const THIRD_EDITION: bool>(
+        &mut self,
+        instruction: u16,
+    ) {
         let opcode = Opcode::from_int(OP);
 
         match opcode {
@@ -275,25 +752,20 @@ impl<Input: Read, Output: Write> Cpu<Input, Output> {
                             // load effective address: just return the address
                             Opcode::Lea => addr,
                             // load direct
-                            Opcode::Ld => {
-                                if !self.address_accessible(addr) {
-                                    self.handle_exception(0x02);
-                                    return;
-                                }
-                                self.memory.get(addr)
-                            }
-                            // load indirect
+                            Opcode::Ld => match self.read(addr) {
+                                Some(value) => value,
+                                None => return,
+                            },
+                            // load indirect: both the pointer and what it points to have to be accessible
                             Opcode::Ldi => {
-                                if !self.address_accessible(addr) {
-                                    self.handle_exception(0x02);
-                                    return;
-                                }
-                                let indirect_addr = self.memory.get(addr);
-                                if !self.address_accessible(addr) {
-                                    self.handle_exception(0x02);
-                                    return;
+                                let indirect_addr = match self.read(addr) {
+                                    Some(indirect_addr) => indirect_addr,
+                                    None => return,
+                                };
+                                match self.read(indirect_addr) {
+                                    Some(value) => value,
+                                    None => return,
                                 }
-                                self.memory.get(indirect_addr)
                             }
                             _ => unreachable!(),
                         }
@@ -302,11 +774,10 @@ impl<Input: Read, Output: Write> Cpu<Input, Output> {
                     Opcode::Ldr => {
                         let offset = sign_extend::<6>(get_bits::<0, 5>(instruction) as i16);
                         let addr = u16::wrapping_add(self.get_reg_lo(instruction), offset as u16);
-                        if !self.address_accessible(addr) {
-                            self.handle_exception(0x02);
-                            return;
+                        match self.read(addr) {
+                            Some(value) => value,
+                            None => return,
                         }
-                        self.memory.get(addr)
                     }
 
                     Opcode::Not => !self.get_reg_lo(instruction),
@@ -314,25 +785,18 @@ impl<Input: Read, Output: Write> Cpu<Input, Output> {
                     _ => unreachable!(),
                 };
 
-                self.registers[get_bits::<9, 11>(instruction) as usize] = result;
+                let dr = get_bits::<9, 11>(instruction) as usize;
+                if dr == 6 && self.check_program_stack_pointer(result).is_none() {
+                    return;
+                }
+                self.set_register(dr, result);
 
                 // Third edition: LEA doesn't set condition codes
-                #[cfg(not(feature = "third_edition"))]
-                if let Opcode::Lea = opcode {
+                if THIRD_EDITION && opcode == Opcode::Lea {
                     return;
                 }
 
-                // Replace lower 3 bits of PSR with the new condition bits
-                self.memory.set(
-                    MemRegisters::PSR,
-                    (self.memory.get(MemRegisters::PSR) & !0b111)
-                        | match (result as i16).signum() {
-                            -1 => COND_NEGATIVE,
-                            0 => COND_ZERO,
-                            1 => COND_POSITIVE,
-                            _ => unreachable!(),
-                        },
-                );
+                self.set_condition_codes(result);
             }
 
             Opcode::Br => {
@@ -341,30 +805,37 @@ impl<Input: Read, Output: Write> Cpu<Input, Output> {
                 if (nzp & self.memory.get(MemRegisters::PSR)) > 0 {
                     let pc_offset = sign_extend::<9>(get_bits::<0, 8>(instruction) as i16);
                     self.pc = u16::wrapping_add(self.pc, pc_offset as u16);
+                    self.event = StepEvent::BranchTaken { target: self.pc };
                 }
             }
 
             Opcode::Jmp => {
                 // TODO: it looks like there's no protection against jumping into the middle of privileged code.
                 // The book has nothing to say on the matter but it may be useful to implement protections against that.
-                self.pc = self.get_reg_lo(instruction);
+                let target = self.get_reg_lo(instruction);
 
-                // Undocumented JMPT/RTT instruction. No idea who came up with this or where it is or isn't implemented.
-                // If the LSB of the instruction is set, the user mode bit is set to 1.
-                // The only other way to do this is to modify the PSR directly.
-                // Commenting this out for now because I have no idea about the specifics of its functionality.
-                /* if get_bits::<0, 0>(instruction) == 1 {
+                // Undocumented JMPT/RTT instruction. If the LSB of the instruction is set, jump and drop to user mode.
+                // The only other way to do this is to modify the PSR directly. Since the original encoding leaves that
+                // bit unused, this only happens when explicitly enabled.
+                if self.jmpt_enabled && get_bits::<0, 0>(instruction) == 1 {
                     if get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 1 {
                         // Tried to execute JMPT/RTT from user mode--trigger a privilege mode violation
-                        // TODO: do the emulators actually do this? do they even check the privilege bit at all?
-                        // how has no one actually fully implemented this architecture?
-                        self.handle_exception(0x00);
+                        self.raise(Exception::PrivilegeViolation);
                         return;
                     }
 
-                    // Set user-mode MemRegisters::PSR bit
-                    self.memory.get(MemRegisters::PSR) |= 1 << 15;
-                } */
+                    // Switch from the system stack pointer to the user stack pointer, just like RTI does
+                    self.saved_ssp = self.registers[6];
+                    self.set_register(6, self.saved_usp);
+                    // Set the "is user mode" bit of the PSR
+                    self.memory.set(
+                        MemRegisters::PSR,
+                        self.memory.get(MemRegisters::PSR) | (1 << 15),
+                    );
+                }
+
+                self.pc = target;
+                self.event = StepEvent::BranchTaken { target };
             }
 
             Opcode::Jsr => {
@@ -380,118 +851,402 @@ impl<Input: Read, Output: Write> Cpu<Input, Output> {
                 }
 
                 // Make sure to set R7 *after* potentially reading the program counter from it (e.g. a JSRR)
-                self.registers[7] = old_pc;
+                self.set_register(7, old_pc);
+                self.event = StepEvent::BranchTaken { target: self.pc };
             }
 
             Opcode::St | Opcode::Sti => {
                 let pc_offset = sign_extend::<9>(get_bits::<0, 8>(instruction) as i16);
                 let mut addr = u16::wrapping_add(self.pc, pc_offset as u16);
 
-                if !self.address_accessible(addr) {
-                    self.handle_exception(0x02);
-                    return;
-                }
-
                 // read address from memory if indirect store
                 if let Opcode::Sti = opcode {
-                    addr = self.memory.get(addr);
-                    if !self.address_accessible(addr) {
-                        self.handle_exception(0x02);
-                    }
+                    addr = match self.read(addr) {
+                        Some(indirect_addr) => indirect_addr,
+                        None => return,
+                    };
                 }
 
-                self.memory.set(addr, self.get_reg_hi(instruction));
+                self.write(addr, self.get_reg_hi(instruction));
             }
 
             Opcode::Str => {
                 let offset = sign_extend::<6>(get_bits::<0, 5>(instruction) as i16);
                 let addr = u16::wrapping_add(self.get_reg_lo(instruction), offset as u16);
-                if !self.address_accessible(addr) {
-                    self.handle_exception(0x02);
-                    return;
-                }
-                self.memory.set(addr, self.get_reg_hi(instruction));
+                self.write(addr, self.get_reg_hi(instruction));
             }
 
             Opcode::Rti => {
                 if get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 1 {
                     // Tried to execute RTI from user mode--trigger a privilege mode violation
-                    self.handle_exception(0x00);
+                    self.raise(Exception::PrivilegeViolation);
                     return;
                 }
 
-                // Restore PC from supervisor stack pointer
-                self.pc = self.memory.get(self.registers[6]);
-                self.registers[6] += 1;
-
+                // Pop the PC and PSR off the supervisor stack. Check and read both before changing anything, so that if
+                // either pop faults, the instruction has no effect.
+                if self
+                    .check_stack_pointer(
+                        Stack::Supervisor,
+                        self.registers[6],
+                        self.registers[6].wrapping_add(2),
+                    )
+                    .is_none()
+                {
+                    return;
+                }
+                let new_pc = match self.read(self.registers[6]) {
+                    Some(value) => value,
+                    None => return,
+                };
                 // TODO: the book says to pop the system stack before restoring the PSR. Why?
-                let new_psr = self.memory.get(self.registers[6]);
-                self.registers[6] += 1;
+                let new_psr = match self.read(self.registers[6].wrapping_add(1)) {
+                    Some(value) => value,
+                    None => return,
+                };
+                self.pc = new_pc;
+                self.set_register(6, self.registers[6].wrapping_add(2));
                 self.memory.set(MemRegisters::PSR, new_psr);
 
                 if get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 1 {
                     // We are now back in user mode
                     self.saved_ssp = self.registers[6];
-                    self.registers[6] = self.saved_usp;
+                    self.set_register(6, self.saved_usp);
                 }
+                self.event = StepEvent::Rti;
             }
 
             Opcode::Reserved => {
-                self.handle_exception(0x01);
+                self.raise(Exception::IllegalOpcode);
             }
 
             Opcode::Trap => {
-                // In the third-edition LC-3, the old PC and PSR are stored on the stack and supervisor mode is entered
-                #[cfg(feature = "third_edition")]
-                self.enter_supervisor_mode();
+                let trap_vector = get_bits::<0, 7>(instruction) as u8;
+                self.event = StepEvent::TrapEntered {
+                    vector: trap_vector,
+                };
+                if let Some(routine) = self.hle_routine(instruction) {
+                    self.service_trap(routine);
+                    return;
+                }
 
-                // In the second-edition LC-3, TRAP sets R7 to the previous PC
-                #[cfg(not(feature = "third_edition"))]
-                {
-                    self.registers[7] = self.pc;
+                if THIRD_EDITION {
+                    // In the third-edition LC-3, the old PC and PSR are stored on the stack and supervisor mode is
+                    // entered
+                    let ssp = if get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 1 {
+                        self.saved_ssp
+                    } else {
+                        self.registers[6]
+                    };
+                    if self
+                        .check_stack_pointer(Stack::Supervisor, ssp, ssp.wrapping_sub(2))
+                        .is_none()
+                    {
+                        return;
+                    }
+                    self.enter_supervisor_mode();
+                } else {
+                    // In the second-edition LC-3, TRAP sets R7 to the previous PC
+                    self.set_register(7, self.pc);
                 }
 
                 // Jump into code specified by trap vector table
-                self.pc = self.memory.get(get_bits::<0, 7>(instruction));
+                self.pc = self.memory.get(trap_vector as u16);
             }
         }
     }
 
-    pub fn step(&mut self) {
-        let instruction = self.memory.get(self.pc);
-        // println!("PC: {:#06x}, instruction: {:#06x} ({})", self.pc, instruction, disassemble_instruction(instruction));
-        self.pc = self.pc.wrapping_add(1);
-        let op = get_bits::<12, 15>(instruction);
+    /// Execute a single instruction, or accept a pending interrupt, and report what happened. If history is enabled,
+    /// also record what it changed so it can be undone later. Observers are notified after every successful step.
+    /// If an error is returned, the instruction was not executed and the PC is left pointing at it.
+    pub fn step(&mut self) -> Result<StepEvent, EmulatorError> {
+        if self.history.is_none() && self.observers.is_empty() {
+            return self.execute_next().map(|_| self.event.clone());
+        }
+
+        let pc = self.pc;
+        let registers = self.registers;
+        let registers_initialized = self.registers_initialized;
+        let saved_usp = self.saved_usp;
+        let saved_ssp = self.saved_ssp;
+        let instructions_retired = self.memory.instructions_retired;
+        let cycles = self.memory.cycles;
+        let random = self.memory.random.clone();
+        let clock = self.memory.clock.clone();
+        let hle_prompted = self.hle_prompted;
+        if self.history.is_some() {
+            self.memory.journal = Some(Vec::new());
+        }
+
+        let result = self.execute_next();
+
+        if let Some(history) = &mut self.history {
+            let record = UndoRecord {
+                pc,
+                registers: (0..8u8)
+                    .filter(|&i| self.registers[i as usize] != registers[i as usize])
+                    .map(|i| (i, registers[i as usize]))
+                    .collect(),
+                memory: self.memory.journal.take().unwrap_or_default(),
+                registers_initialized,
+                saved_usp,
+                saved_ssp,
+                instructions_retired,
+                cycles,
+                random,
+                clock,
+                hle_prompted,
+            };
+            if result.is_ok() {
+                history.push(record);
+            }
+        }
 
+        let instruction = result?;
+        if !self.observers.is_empty() {
+            let record = StepRecord {
+                pc,
+                next_pc: self.pc,
+                instruction,
+                registers_before: registers,
+                registers: self.registers,
+                psr: self.memory.get(MemRegisters::PSR),
+                event: self.event.clone(),
+            };
+            for observer in &mut self.observers {
+                observer.on_step(&record);
+            }
+        }
+        Ok(self.event.clone())
+    }
+
+    fn dispatch<const THIRD_EDITION: bool>(&mut self, instruction: u16) {
         // We take advantage of const generics to compile 16 specialized code paths through execute_instruction
-        // depending on which instruction it is. This seemingly redundant code is necessary because these are basically
-        // 16 different functions--the opcode's essentially a template parameter which must be known at compile time.
-        match op {
-            0 => self.execute_instruction::<0>(instruction),
-            1 => self.execute_instruction::<1>(instruction),
-            2 => self.execute_instruction::<2>(instruction),
-            3 => self.execute_instruction::<3>(instruction),
-            4 => self.execute_instruction::<4>(instruction),
-            5 => self.execute_instruction::<5>(instruction),
-            6 => self.execute_instruction::<6>(instruction),
-            7 => self.execute_instruction::<7>(instruction),
-            8 => self.execute_instruction::<8>(instruction),
-            9 => self.execute_instruction::<9>(instruction),
-            10 => self.execute_instruction::<10>(instruction),
-            11 => self.execute_instruction::<11>(instruction),
-            12 => self.execute_instruction::<12>(instruction),
-            13 => self.execute_instruction::<13>(instruction),
-            14 => self.execute_instruction::<14>(instruction),
-            15 => self.execute_instruction::<15>(instruction),
+        // depending on which instruction it is (32 counting both editions). This seemingly redundant code is necessary
+        // because these are basically 16 different functions--the opcode's essentially a template parameter which must
+        // be known at compile time.
+        match get_bits::<12, 15>(instruction) {
+            0 => self.execute_instruction::<0, THIRD_EDITION>(instruction),
+            1 => self.execute_instruction::<1, THIRD_EDITION>(instruction),
+            2 => self.execute_instruction::<2, THIRD_EDITION>(instruction),
+            3 => self.execute_instruction::<3, THIRD_EDITION>(instruction),
+            4 => self.execute_instruction::<4, THIRD_EDITION>(instruction),
+            5 => self.execute_instruction::<5, THIRD_EDITION>(instruction),
+            6 => self.execute_instruction::<6, THIRD_EDITION>(instruction),
+            7 => self.execute_instruction::<7, THIRD_EDITION>(instruction),
+            8 => self.execute_instruction::<8, THIRD_EDITION>(instruction),
+            9 => self.execute_instruction::<9, THIRD_EDITION>(instruction),
+            10 => self.execute_instruction::<10, THIRD_EDITION>(instruction),
+            11 => self.execute_instruction::<11, THIRD_EDITION>(instruction),
+            12 => self.execute_instruction::<12, THIRD_EDITION>(instruction),
+            13 => self.execute_instruction::<13, THIRD_EDITION>(instruction),
+            14 => self.execute_instruction::<14, THIRD_EDITION>(instruction),
+            15 => self.execute_instruction::<15, THIRD_EDITION>(instruction),
             _ => unreachable!(),
         };
     }
 
+    /// Run one step, leaving what happened in `self.event`. Returns the instruction that was executed, or `None` if
+    /// nothing was (an interrupt was accepted, or fetching the instruction raised an access control violation).
+    fn execute_next(&mut self) -> Result<Option<u16>, EmulatorError> {
+        self.event = StepEvent::Executed;
+
+        if let Some((vector, priority_level)) = self.pending_interrupt {
+            if self.handle_interrupt(vector, priority_level) {
+                self.pending_interrupt = None;
+                if let Some(error) = self.pending_error.take() {
+                    return Err(error);
+                }
+                self.advance_clock(None);
+                return Ok(None);
+            }
+        }
+
+        // Fetching from memory the program isn't allowed to access is an access control violation, not an error
+        if self.address_accessible(self.pc, Access::Execute) && !self.memory.is_initialized(self.pc)
+        {
+            return Err(EmulatorError::UninitializedExecution { pc: self.pc });
+        }
+
+        let was_running = !self.should_halt();
+        let fetch_addr = self.pc;
+        // Increment the PC first, so that a fetch that faults looks like any other instruction that raises an exception
+        self.pc = self.pc.wrapping_add(1);
+        let instruction = self.fetch(fetch_addr);
+        // println!("PC: {:#06x}, instruction: {:#06x} ({})", fetch_addr, instruction, disassemble_instruction(instruction));
+
+        if let Some(instruction) =
+            instruction.filter(|&i| self.sanitize_instruction(fetch_addr, i).is_some())
+        {
+            match self.edition {
+                Edition::Second => self.dispatch::<false>(instruction),
+                Edition::Third => self.dispatch::<true>(instruction),
+            }
+        }
+
+        if let Some(error) = self.pending_error.take() {
+            return Err(error);
+        }
+        self.advance_clock(instruction);
+        let instruction = match instruction {
+            Some(instruction) => instruction,
+            None => return Ok(None),
+        };
+
+        self.memory.instructions_retired += 1;
+        if was_running && self.should_halt() {
+            self.event = StepEvent::Halted;
+        }
+        Ok(Some(instruction))
+    }
+
+    /// Start recording an undo log so that execution can be reversed, keeping at most `capacity` steps of history.
+    /// Any previously recorded history is discarded. See [`step_back`](Self::step_back) for what can't be undone.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    /// Stop recording history and discard the undo log.
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// The number of steps that can currently be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    fn undo(&mut self, record: UndoRecord) {
+        // Undo writes newest-first so that a word written more than once ends up with its oldest value
+        for &(addr, value, state) in record.memory.iter().rev() {
+            self.memory.invalidate(addr);
+            self.memory.memory[addr as usize] = value;
+            self.memory.state[addr as usize] = state;
+        }
+        for &(reg, value) in &record.registers {
+            self.registers[reg as usize] = value;
+        }
+        self.registers_initialized = record.registers_initialized;
+        self.pc = record.pc;
+        self.saved_usp = record.saved_usp;
+        self.saved_ssp = record.saved_ssp;
+        self.memory.instructions_retired = record.instructions_retired;
+        self.memory.cycles = record.cycles;
+        self.memory.random = record.random;
+        self.memory.clock = record.clock;
+        self.hle_prompted = record.hle_prompted;
+    }
+
+    /// Undo the most recent step. Returns false if there is no history left to undo.
+    ///
+    /// The processor, memory, random number generator (xFE18), and clock snapshots (xFE20-xFE2E) are rewound. Other
+    /// devices are left as they are: characters printed stay printed, keys read stay read, the interval timer keeps
+    /// counting towards its deadline, and files the program opened, read, or wrote stay that way.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.as_mut().and_then(History::pop) {
+            Some(record) => {
+                self.undo(record);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Step backwards until the PC lands on one of the given breakpoints. Returns false if the history ran out first.
+    pub fn reverse_continue(&mut self, breakpoints: &HashSet<u16>) -> bool {
+        while self.step_back() {
+            if breakpoints.contains(&self.pc) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Step backwards until just before the most recent instruction that wrote to `addr`, leaving the PC pointing at
+    /// that instruction. Returns false if the history ran out first.
+    pub fn reverse_to_write(&mut self, addr: u16) -> bool {
+        while let Some(record) = self.history.as_mut().and_then(History::pop) {
+            let wrote = record.wrote_to(addr);
+            self.undo(record);
+            if wrote {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn edition(&self) -> Edition {
+        self.edition
+    }
+
+    pub fn set_edition(&mut self, edition: Edition) {
+        self.edition = edition;
+        // Cached instructions were decoded for the old edition
+        self.clear_block_cache();
+    }
+
+    /// Choose whether to recognize the JMPT/RTT instruction: JMP with bit 0 set, which jumps to the base register and
+    /// enters user mode. It can only be executed in supervisor mode. When disabled, bit 0 of JMP is ignored.
+    pub fn set_jmpt_enabled(&mut self, enabled: bool) {
+        self.jmpt_enabled = enabled;
+    }
+
+    pub fn set_fault_policy(&mut self, policy: FaultPolicy) {
+        self.fault_policy = policy;
+    }
+
+    /// Replace the map of what memory user mode code may access. By default, it may access x3000-xFDFF.
+    pub fn set_protection_map(&mut self, map: ProtectionMap) {
+        self.protection = map;
+        // Cached blocks may straddle the new map's regions
+        self.clear_block_cache();
+    }
+
+    /// Start notifying an observer after every step. To read back what it collected, pass in an `Rc<RefCell<_>>` and
+    /// keep a clone.
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// The current contents of the general-purpose registers.
+    pub fn registers(&self) -> [u16; 8] {
+        self.registers
+    }
+
+    /// The current contents of the processor status register.
+    pub fn psr(&self) -> u16 {
+        self.peek(MemRegisters::PSR)
+    }
+
+    /// Read a word of memory without triggering any device side effects. Device registers read as whatever was last
+    /// written to them.
+    pub fn peek(&self, addr: u16) -> u16 {
+        self.memory.memory[addr as usize]
+    }
+
+    /// The number of instructions executed so far.
+    pub fn instructions_retired(&self) -> u64 {
+        self.memory.instructions_retired
+    }
+
+    /// Choose how characters written to the display reach the output. By default, it's assumed to be a terminal in raw
+    /// mode; use `DisplayMode::Verbatim` when writing to a file or pipe.
+    pub fn set_display_mode(&mut self, mode: DisplayMode) {
+        self.memory.display.get_mut().set_mode(mode);
+    }
+
+    /// Write out anything printed to the display that's still buffered. This happens on its own when the program polls
+    /// the keyboard or halts, and when the CPU is dropped.
+    pub fn flush_display(&mut self) {
+        self.memory.display.get_mut().flush();
+    }
+
     pub fn should_halt(&mut self) -> bool {
-        self.memory.get(MemRegisters::MCR) & (1 << 15) == 0
+        self.peek(MemRegisters::MCR) & (1 << 15) == 0
     }
 
-    pub fn load_program<F>(&mut self, mut file: F) -> std::io::Result<()>
+    /// Load an object file into memory, returning its origin address.
+    pub fn load_program<F>(&mut self, mut file: F) -> std::io::Result<u16>
     where
         F: Read,
     {
@@ -499,8 +1254,11 @@ impl<Input: Read, Output: Write> Cpu<Input, Output> {
         let origin = file.read_u16::<BigEndian>()? as usize;
         file.read_to_end(&mut buf)?;
         buf.chunks(2).enumerate().for_each(|(i, chunk)| {
-            self.memory.memory[i + origin] = ((chunk[0] as u16) << 8) | (chunk[1] as u16)
+            self.memory.load(
+                (i + origin) as u16,
+                ((chunk[0] as u16) << 8) | (chunk[1] as u16),
+            )
         });
-        Ok(())
+        Ok(origin as u16)
     }
 }
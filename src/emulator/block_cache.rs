@@ -0,0 +1,228 @@
+use std::io::Write;
+use std::rc::Rc;
+
+use super::{
+    Access, Cpu, Edition, EmulatorError, KeySource, StepEvent, WordState, DEVICE_REGISTERS,
+    MEMORY_SIZE,
+};
+use crate::bit_twiddling::get_bits;
+use crate::opcode::Opcode;
+
+/// The most instructions in one block
+const MAX_BLOCK_LEN: u16 = 32;
+
+type Handler<Input, Output> = fn(&mut Cpu<Input, Output>, u16);
+
+/// An instruction along with the specialization of `execute_instruction` that executes it
+struct Decoded<Input: KeySource, Output: Write> {
+    instruction: u16,
+    execute: Handler<Input, Output>,
+    /// The cycles it takes when it doesn't branch or raise an exception
+    cycles: u64,
+}
+
+/// A straight-line run of instructions, ending with the first one that can jump: a branch, JMP, JSR, TRAP, or RTI.
+type Block<Input, Output> = Rc<[Decoded<Input, Output>]>;
+
+/// A cache of predecoded blocks of instructions, so that running code that's already been seen skips fetching each
+/// instruction from memory, dispatching on its opcode, and checking it can be executed.
+///
+/// Any write to a word in a block throws the whole block away, so self-modifying code still works.
+pub(super) struct BlockCache<Input: KeySource, Output: Write> {
+    /// The block starting at each address, if it's been decoded
+    blocks: Vec<Option<Block<Input, Output>>>,
+    /// Whether each address might be in a block, so that writes to data don't have to look for blocks to throw away
+    covered: Vec<bool>,
+}
+
+impl<Input: KeySource, Output: Write> BlockCache<Input, Output> {
+    pub fn new() -> Self {
+        BlockCache {
+            blocks: vec![None; MEMORY_SIZE],
+            covered: vec![false; MEMORY_SIZE],
+        }
+    }
+
+    /// Forget every block that includes `addr`, because it's about to change. Returns whether there were any.
+    pub fn invalidate(&mut self, addr: u16) -> bool {
+        if !self.covered[addr as usize] {
+            return false;
+        }
+        self.covered[addr as usize] = false;
+        let mut invalidated = false;
+        // Blocks don't reach into the device registers, so these can't overflow
+        for start in addr.saturating_sub(MAX_BLOCK_LEN - 1)..=addr {
+            let block = &mut self.blocks[start as usize];
+            if matches!(block, Some(instructions) if start + instructions.len() as u16 > addr) {
+                *block = None;
+                invalidated = true;
+            }
+        }
+        invalidated
+    }
+}
+
+/// The specialization of `execute_instruction` for an instruction
+fn handler<Input: KeySource, Output: Write>(
+    edition: Edition,
+    instruction: u16,
+) -> Handler<Input, Output> {
+    match edition {
+        Edition::Second => handler_for_edition::<Input, Output, false>(instruction),
+        Edition::Third => handler_for_edition::<Input, Output, true>(instruction),
+    }
+}
+
+fn handler_for_edition<Input: KeySource, Output: Write, const THIRD_EDITION: bool>(
+    instruction: u16,
+) -> Handler<Input, Output> {
+    match get_bits::<12, 15>(instruction) {
+        0 => Cpu::execute_instruction::<0, THIRD_EDITION>,
+        1 => Cpu::execute_instruction::<1, THIRD_EDITION>,
+        2 => Cpu::execute_instruction::<2, THIRD_EDITION>,
+        3 => Cpu::execute_instruction::<3, THIRD_EDITION>,
+        4 => Cpu::execute_instruction::<4, THIRD_EDITION>,
+        5 => Cpu::execute_instruction::<5, THIRD_EDITION>,
+        6 => Cpu::execute_instruction::<6, THIRD_EDITION>,
+        7 => Cpu::execute_instruction::<7, THIRD_EDITION>,
+        8 => Cpu::execute_instruction::<8, THIRD_EDITION>,
+        9 => Cpu::execute_instruction::<9, THIRD_EDITION>,
+        10 => Cpu::execute_instruction::<10, THIRD_EDITION>,
+        11 => Cpu::execute_instruction::<11, THIRD_EDITION>,
+        12 => Cpu::execute_instruction::<12, THIRD_EDITION>,
+        13 => Cpu::execute_instruction::<13, THIRD_EDITION>,
+        14 => Cpu::execute_instruction::<14, THIRD_EDITION>,
+        15 => Cpu::execute_instruction::<15, THIRD_EDITION>,
+        _ => unreachable!(),
+    }
+}
+
+impl<Input: KeySource, Output: Write> Cpu<Input, Output> {
+    /// Choose whether to cache predecoded blocks of instructions for [`run_block`](Self::run_block), which makes
+    /// long-running programs run faster. Programs behave the same either way, down to which instruction a timer
+    /// interrupt arrives before.
+    pub fn set_block_cache_enabled(&mut self, enabled: bool) {
+        self.memory.blocks = if enabled {
+            Some(BlockCache::new())
+        } else {
+            None
+        };
+    }
+
+    pub fn block_cache_enabled(&self) -> bool {
+        self.memory.blocks.is_some()
+    }
+
+    /// Throw away every cached block, because something they were decoded for has changed.
+    pub(super) fn clear_block_cache(&mut self) {
+        if self.memory.blocks.is_some() {
+            self.memory.blocks = Some(BlockCache::new());
+        }
+    }
+
+    /// The block starting at `start`, decoding it if it hasn't been already. The block cache must be enabled, and the
+    /// instruction at `start` must be initialized.
+    fn block(&mut self, start: u16) -> Block<Input, Output> {
+        if let Some(block) = &self.memory.blocks.as_ref().unwrap().blocks[start as usize] {
+            return block.clone();
+        }
+
+        // Every instruction in a block can be executed if the first one can
+        let permissions = self.protection.permissions(start);
+        let mut instructions = Vec::new();
+        let mut addr = start;
+        loop {
+            let instruction = self.memory.memory[addr as usize];
+            let opcode = Opcode::from_int(get_bits::<12, 15>(instruction) as u8);
+            let ends_block = matches!(
+                opcode,
+                Opcode::Br
+                    | Opcode::Jmp
+                    | Opcode::Jsr
+                    | Opcode::Rti
+                    | Opcode::Trap
+                    | Opcode::Reserved
+            );
+            instructions.push(Decoded {
+                instruction,
+                execute: handler(self.edition, instruction),
+                cycles: self.timing.cycles(opcode) as u64,
+            });
+            addr += 1;
+            if ends_block
+                || addr - start == MAX_BLOCK_LEN
+                || addr >= DEVICE_REGISTERS
+                || self.memory.state[addr as usize] == WordState::Uninitialized
+                || self.protection.permissions(addr) != permissions
+            {
+                break;
+            }
+        }
+
+        let block: Block<Input, Output> = Rc::from(instructions);
+        let cache = self.memory.blocks.as_mut().unwrap();
+        cache.covered[start as usize..addr as usize].fill(true);
+        cache.blocks[start as usize] = Some(block.clone());
+        block
+    }
+
+    /// Run the block of straight-line code at the PC through the block cache, stopping after at most
+    /// `max_instructions` (but always at least one), and report what the last instruction did. Blocks end at the next
+    /// branch, JMP, JSR, TRAP, or RTI, and also stop early if an instruction raises an exception, stores to a device
+    /// register, or overwrites cached code.
+    ///
+    /// Interrupts are only accepted, and the instruction at the PC only checked for whether it can be executed, between
+    /// blocks. That doesn't change anything a program can see: every instruction in a block has the same permissions,
+    /// and a block ends early on the instruction that brings the timer to its next deadline, so a timer interrupt is
+    /// accepted before the same instruction it would be step by step.
+    ///
+    /// Without the block cache, with history or observers, or while checking instructions with the sanitizer, this
+    /// falls back to [`step`](Self::step).
+    pub fn run_block(&mut self, max_instructions: u64) -> Result<StepEvent, EmulatorError> {
+        if self.memory.blocks.is_none()
+            || self.history.is_some()
+            || !self.observers.is_empty()
+            || self.checks_instructions()
+            || self.pending_interrupt.is_some()
+            || self.pc >= DEVICE_REGISTERS
+            || !self.memory.is_initialized(self.pc)
+            || !self.address_accessible(self.pc, Access::Execute)
+        {
+            return self.step();
+        }
+
+        let block = self.block(self.pc);
+        let len = block.len().min(max_instructions.max(1) as usize);
+        let deadline = self.memory.timer.deadline().unwrap_or(u64::MAX);
+        let was_running = !self.should_halt();
+        self.event = StepEvent::Executed;
+        self.memory.leave_block = false;
+        for (i, decoded) in block[..len].iter().enumerate() {
+            let next_pc = self.pc.wrapping_add(1);
+            self.pc = next_pc;
+            (decoded.execute)(self, decoded.instruction);
+            if let Some(error) = self.pending_error.take() {
+                return Err(error);
+            }
+
+            if i + 1 < len
+                && self.pc == next_pc
+                && matches!(self.event, StepEvent::Executed)
+                && !self.memory.leave_block
+                && self.memory.cycles + decoded.cycles < deadline
+            {
+                self.memory.cycles += decoded.cycles;
+                self.memory.instructions_retired += 1;
+            } else {
+                self.advance_clock(Some(decoded.instruction));
+                self.memory.instructions_retired += 1;
+                break;
+            }
+        }
+
+        if was_running && self.should_halt() {
+            self.event = StepEvent::Halted;
+        }
+        Ok(self.event.clone())
+    }
+}
@@ -0,0 +1,119 @@
+use super::{StepEvent, StepRecord};
+use crate::bit_twiddling::get_bits;
+use crate::opcode::Opcode;
+
+/// RET is JMP R7
+const RET: u16 = 0xC1C0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FrameKind {
+    /// Wherever execution started, which is never returned from
+    Root,
+    /// JSR or JSRR, returned from with RET
+    Subroutine,
+    /// A trap routine run from the OS, returned from with RET (second edition) or RTI (third edition)
+    Trap,
+    /// An exception or interrupt handler, returned from with RTI
+    Handler,
+}
+
+/// How a step moved the call stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum CallStep {
+    /// Entered a routine at the step's `next_pc`
+    Call(FrameKind),
+    /// RET
+    Return,
+    /// RTI
+    ReturnFromInterrupt,
+}
+
+impl CallStep {
+    /// Work out whether a step called or returned from anything.
+    pub fn of(record: &StepRecord) -> Option<CallStep> {
+        match (&record.event, record.instruction) {
+            (StepEvent::BranchTaken { .. }, Some(RET)) => Some(CallStep::Return),
+            (StepEvent::BranchTaken { .. }, Some(instruction))
+                if Opcode::from_int(get_bits::<12, 15>(instruction) as u8) == Opcode::Jsr =>
+            {
+                Some(CallStep::Call(FrameKind::Subroutine))
+            }
+            // Natively serviced traps finish within the step, leaving the PC on or just after the TRAP
+            (StepEvent::TrapEntered { .. }, _)
+                if record.next_pc != record.pc && record.next_pc != record.pc.wrapping_add(1) =>
+            {
+                Some(CallStep::Call(FrameKind::Trap))
+            }
+            (
+                StepEvent::Exception { .. }
+                | StepEvent::InterruptAccepted { .. }
+                | StepEvent::FaultRecovered(_)
+                | StepEvent::StackFault(_),
+                _,
+            ) => Some(CallStep::Call(FrameKind::Handler)),
+            (StepEvent::Rti, _) => Some(CallStep::ReturnFromInterrupt),
+            _ => None,
+        }
+    }
+}
+
+/// The routines that are running, innermost last, following JSR/RET along with traps, exceptions, and interrupts. Each
+/// frame holds whatever the observer wants to remember about the call.
+pub(super) struct CallStack<T> {
+    kinds: Vec<FrameKind>,
+    frames: Vec<T>,
+}
+
+impl<T> CallStack<T> {
+    pub fn new() -> Self {
+        CallStack {
+            kinds: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Every frame, outermost first
+    pub fn frames(&self) -> &[T] {
+        &self.frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn call(&mut self, kind: FrameKind, frame: T) {
+        self.kinds.push(kind);
+        self.frames.push(frame);
+    }
+
+    /// Pop the frame a RET returns from, or return `None` if the innermost frame isn't a subroutine or trap.
+    pub fn ret(&mut self) -> Option<(FrameKind, T)> {
+        match self.kinds.last() {
+            Some(FrameKind::Subroutine | FrameKind::Trap) => {
+                Some((self.kinds.pop().unwrap(), self.frames.pop().unwrap()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Pop the frames an RTI returns from: anything the handler called and never returned from, along with the handler
+    /// itself.
+    pub fn rti(&mut self) {
+        while let Some(&kind) = self.kinds.last() {
+            if kind == FrameKind::Root {
+                break;
+            }
+            self.kinds.pop();
+            self.frames.pop();
+            if kind != FrameKind::Subroutine {
+                break;
+            }
+        }
+    }
+}
+
+impl<T> Default for CallStack<T> {
+    fn default() -> Self {
+        CallStack::new()
+    }
+}
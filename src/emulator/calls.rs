@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::mem::{discriminant, Discriminant};
+
+use super::call_stack::{CallStack, CallStep, FrameKind};
+use super::{Observer, StepRecord};
+
+/// Which registers a subroutine is expected to leave the way it found them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallingConvention {
+    /// Bitmask of registers the callee has to save and restore if it uses them
+    pub callee_saved: u8,
+    /// Whether the callee has to pop everything it pushes, leaving R6 where it was
+    pub balanced_stack: bool,
+}
+
+/// The textbook's convention: R0 carries the return value, R1-R5 are callee-saved, R6 is the stack pointer, and R7
+/// holds the return address.
+impl Default for CallingConvention {
+    fn default() -> Self {
+        CallingConvention {
+            callee_saved: 0b0011_1110,
+            balanced_stack: true,
+        }
+    }
+}
+
+/// A way a subroutine broke the calling convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// RET didn't go back to the instruction after the call, most likely because R7 was overwritten (say, by a nested
+    /// call or trap) without being saved
+    ClobberedR7 { expected: u16, actual: u16 },
+    /// A callee-saved register had a different value on return than at the call
+    RegisterNotRestored { reg: u8, before: u16, after: u16 },
+    /// R6 was different on return than at the call
+    StackImbalance { before: u16, after: u16 },
+    /// RET with no call to return from
+    UnmatchedReturn,
+}
+
+/// A calling convention violation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    /// Address of the JSR, JSRR, or TRAP that made the call, if there was one
+    pub call_site: Option<u16>,
+    /// Address of the RET
+    pub return_site: u16,
+    pub kind: ViolationKind,
+}
+
+impl Display for Violation {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "RET at {:#06x}", self.return_site)?;
+        if let Some(call_site) = self.call_site {
+            write!(formatter, " (called from {:#06x})", call_site)?;
+        }
+        formatter.write_str(": ")?;
+        match self.kind {
+            ViolationKind::ClobberedR7 { expected, actual } => write!(
+                formatter,
+                "went to {:#06x} instead of {:#06x}; R7 was overwritten without being saved",
+                actual, expected
+            )?,
+            ViolationKind::RegisterNotRestored { reg, before, after } => write!(
+                formatter,
+                "R{} was not restored (was {:#06x} at the call, {:#06x} on return)",
+                reg, before, after
+            )?,
+            ViolationKind::StackImbalance { before, after } => write!(
+                formatter,
+                "stack pointer R6 was {:#06x} at the call but {:#06x} on return",
+                before, after
+            )?,
+            ViolationKind::UnmatchedReturn => formatter.write_str("no matching call")?,
+        }
+        Ok(())
+    }
+}
+
+struct Frame {
+    call_site: u16,
+    /// Registers just after the call
+    registers: [u16; 8],
+}
+
+/// An observer that tracks subroutine calls and returns, flagging common mistakes: R7 overwritten before RET, callee-saved
+/// registers not restored, R6 not balanced on return, and RET with no matching call.
+pub struct CallChecker {
+    convention: CallingConvention,
+    frames: CallStack<Frame>,
+    violations: Vec<Violation>,
+    /// Every kind of violation reported at each call and return site, so a loop doesn't report the same one forever
+    reported: HashSet<(Option<u16>, u16, Discriminant<ViolationKind>)>,
+}
+
+impl CallChecker {
+    pub fn new(convention: CallingConvention) -> Self {
+        CallChecker {
+            convention,
+            frames: CallStack::new(),
+            violations: Vec::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    /// Take the violations found since the last call.
+    pub fn take_violations(&mut self) -> Vec<Violation> {
+        std::mem::take(&mut self.violations)
+    }
+
+    fn call(&mut self, kind: FrameKind, record: &StepRecord) {
+        self.frames.call(
+            kind,
+            Frame {
+                call_site: record.pc,
+                registers: record.registers,
+            },
+        );
+    }
+
+    fn report(&mut self, violation: Violation) {
+        if self.reported.insert((
+            violation.call_site,
+            violation.return_site,
+            discriminant(&violation.kind),
+        )) {
+            self.violations.push(violation);
+        }
+    }
+
+    fn ret(&mut self, record: &StepRecord) {
+        let (kind, frame) = match self.frames.ret() {
+            Some(popped) => popped,
+            None => {
+                self.report(Violation {
+                    call_site: None,
+                    return_site: record.pc,
+                    kind: ViolationKind::UnmatchedReturn,
+                });
+                return;
+            }
+        };
+
+        let mut violations = Vec::new();
+        let expected = frame.call_site.wrapping_add(1);
+        if record.next_pc != expected {
+            violations.push(ViolationKind::ClobberedR7 {
+                expected,
+                actual: record.next_pc,
+            });
+        }
+
+        // Trap routines belong to the OS, which has its own conventions
+        if kind == FrameKind::Subroutine {
+            for reg in 0..8 {
+                let (before, after) = (frame.registers[reg], record.registers[reg]);
+                if self.convention.callee_saved & (1 << reg) != 0 && before != after {
+                    violations.push(ViolationKind::RegisterNotRestored {
+                        reg: reg as u8,
+                        before,
+                        after,
+                    });
+                }
+            }
+            if self.convention.balanced_stack && frame.registers[6] != record.registers[6] {
+                violations.push(ViolationKind::StackImbalance {
+                    before: frame.registers[6],
+                    after: record.registers[6],
+                });
+            }
+        }
+
+        for kind in violations {
+            self.report(Violation {
+                call_site: Some(frame.call_site),
+                return_site: record.pc,
+                kind,
+            });
+        }
+    }
+}
+
+impl Default for CallChecker {
+    fn default() -> Self {
+        CallChecker::new(CallingConvention::default())
+    }
+}
+
+impl Observer for CallChecker {
+    fn on_step(&mut self, record: &StepRecord) {
+        match CallStep::of(record) {
+            Some(CallStep::Call(kind)) => self.call(kind, record),
+            Some(CallStep::Return) => self.ret(record),
+            Some(CallStep::ReturnFromInterrupt) => self.frames.rti(),
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Clocks for programs to read, each 64 bits wide and read 16 bits at a time from four registers, least significant
+/// first:
+///
+/// - ICR0-ICR3 (xFE20-xFE26) count the instructions executed so far.
+/// - WCR0-WCR3 (xFE28-xFE2E) hold the host's wall-clock time, in milliseconds since the Unix epoch. Timing something
+///   only needs the low two words, which wrap around every 49 days.
+///
+/// Reading the first register of a clock takes a snapshot, which the other three then report, so that the four chunks
+/// belong together even though reading them takes several instructions. The registers are read-only.
+#[derive(Default, Clone)]
+pub(super) struct Clock {
+    instructions: Cell<u64>,
+    wall: Cell<u64>,
+}
+
+impl Clock {
+    /// Read the register at `offset` words past ICR0. `instructions` is how many instructions have been executed.
+    pub fn read(&self, offset: u16, instructions: u64) -> u16 {
+        // Registers are at even addresses only
+        if offset % 2 == 1 {
+            return 0;
+        }
+        let (chunk, snapshot) = match offset / 2 {
+            chunk @ 0..=3 => (chunk, &self.instructions),
+            chunk => (chunk - 4, &self.wall),
+        };
+        if offset == 0 {
+            snapshot.set(instructions);
+        } else if offset == 8 {
+            snapshot.set(wall_time());
+        }
+        (snapshot.get() >> (16 * chunk)) as u16
+    }
+}
+
+/// Milliseconds since the Unix epoch
+fn wall_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
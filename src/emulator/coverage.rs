@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use super::{Cpu, KeySource, Observer, StepEvent, StepRecord};
+use crate::bit_twiddling::get_bits;
+use crate::debug_info::DebugInfo;
+use crate::opcode::Opcode;
+
+/// How many times a conditional branch went each way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BranchCounts {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+impl BranchCounts {
+    fn executed(&self) -> bool {
+        self.taken + self.not_taken > 0
+    }
+}
+
+/// Whether an instruction is a BR that can go either way (not BRnzp, which always branches, or a BR with no condition
+/// codes, which never does)
+fn is_conditional_branch(instruction: u16) -> bool {
+    Opcode::from_int(get_bits::<12, 15>(instruction) as u8) == Opcode::Br
+        && !matches!(get_bits::<9, 11>(instruction), 0b000 | 0b111)
+}
+
+/// An observer that records how many times each instruction executed, and which ways each conditional branch went, for
+/// coverage reports.
+#[derive(Default)]
+pub struct Coverage {
+    counts: HashMap<u16, u64>,
+    branches: BTreeMap<u16, BranchCounts>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage::default()
+    }
+
+    /// Find the conditional branches in a loaded program, so that branches that never execute are reported too.
+    pub fn add_program<Input: KeySource, Output: Write>(
+        &mut self,
+        cpu: &Cpu<Input, Output>,
+        debug_info: &DebugInfo,
+    ) {
+        for (addr, _) in debug_info.lines() {
+            if !debug_info.is_data(addr) && is_conditional_branch(cpu.peek(addr)) {
+                self.branches.entry(addr).or_default();
+            }
+        }
+    }
+
+    /// How many times the instruction at `addr` was executed
+    pub fn count_at(&self, addr: u16) -> u64 {
+        self.counts.get(&addr).copied().unwrap_or(0)
+    }
+
+    /// Which ways the conditional branch at `addr` went, if there's one there that was found or executed
+    pub fn branch_at(&self, addr: u16) -> Option<BranchCounts> {
+        self.branches.get(&addr).copied()
+    }
+
+    /// Execution counts for each source line with an instruction on it
+    fn line_counts(&self, debug_info: &DebugInfo) -> BTreeMap<usize, u64> {
+        let mut lines = BTreeMap::new();
+        for (addr, line) in debug_info.lines() {
+            if !debug_info.is_data(addr) {
+                *lines.entry(line).or_default() += self.count_at(addr);
+            }
+        }
+        lines
+    }
+
+    /// The conditional branches on each source line
+    fn line_branches(&self, debug_info: &DebugInfo) -> BTreeMap<usize, Vec<BranchCounts>> {
+        let mut lines = BTreeMap::<usize, Vec<BranchCounts>>::new();
+        for (&addr, &counts) in &self.branches {
+            if let Some(line) = debug_info.line(addr) {
+                lines.entry(line).or_default().push(counts);
+            }
+        }
+        lines
+    }
+
+    /// Write `source` with each line prefixed by how many times it executed, in the style of `gcov`: `-` for lines
+    /// without instructions, and `#####` for lines that never executed. Conditional branches are followed by how many
+    /// times they were taken and not taken.
+    pub fn write_annotated<W: Write>(
+        &self,
+        debug_info: &DebugInfo,
+        source: &str,
+        mut writer: W,
+    ) -> io::Result<()> {
+        let line_counts = self.line_counts(debug_info);
+        let line_branches = self.line_branches(debug_info);
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            match line_counts.get(&line) {
+                Some(0) => write!(writer, "{:>9}:{:>5}:{}", "#####", line, text)?,
+                Some(count) => write!(writer, "{:>9}:{:>5}:{}", count, line, text)?,
+                None => write!(writer, "{:>9}:{:>5}:{}", "-", line, text)?,
+            }
+            for branch in line_branches.get(&line).into_iter().flatten() {
+                write!(
+                    writer,
+                    "  [taken {}, not taken {}]",
+                    branch.taken, branch.not_taken
+                )?;
+            }
+            writeln!(writer)?;
+        }
+
+        let lines_hit = line_counts.values().filter(|&&count| count > 0).count();
+        let directions = self.branches.len() * 2;
+        let directions_hit = self
+            .branches
+            .values()
+            .map(|branch| (branch.taken > 0) as usize + (branch.not_taken > 0) as usize)
+            .sum::<usize>();
+        writeln!(
+            writer,
+            "\nLines executed: {} of {}\nBranch directions taken: {} of {}",
+            lines_hit,
+            line_counts.len(),
+            directions_hit,
+            directions
+        )
+    }
+
+    /// Write an LCOV tracefile, which coverage viewers like `genhtml` can read.
+    pub fn write_lcov<W: Write>(&self, debug_info: &DebugInfo, mut writer: W) -> io::Result<()> {
+        let line_counts = self.line_counts(debug_info);
+        writeln!(writer, "TN:")?;
+        writeln!(writer, "SF:{}", debug_info.source().unwrap_or_default())?;
+
+        let (mut branches, mut branches_hit) = (0, 0);
+        for (line, counts) in self.line_branches(debug_info) {
+            for (block, branch) in counts.iter().enumerate() {
+                for (direction, count) in [branch.taken, branch.not_taken].into_iter().enumerate() {
+                    branches += 1;
+                    if !branch.executed() {
+                        writeln!(writer, "BRDA:{},{},{},-", line, block, direction)?;
+                        continue;
+                    }
+                    if count > 0 {
+                        branches_hit += 1;
+                    }
+                    writeln!(writer, "BRDA:{},{},{},{}", line, block, direction, count)?;
+                }
+            }
+        }
+        writeln!(writer, "BRF:{}", branches)?;
+        writeln!(writer, "BRH:{}", branches_hit)?;
+
+        for (line, count) in &line_counts {
+            writeln!(writer, "DA:{},{}", line, count)?;
+        }
+        writeln!(writer, "LF:{}", line_counts.len())?;
+        writeln!(
+            writer,
+            "LH:{}",
+            line_counts.values().filter(|&&count| count > 0).count()
+        )?;
+        writeln!(writer, "end_of_record")
+    }
+}
+
+impl Observer for Coverage {
+    fn on_step(&mut self, record: &StepRecord) {
+        let instruction = match record.instruction {
+            Some(instruction) => instruction,
+            None => return,
+        };
+        *self.counts.entry(record.pc).or_default() += 1;
+        if is_conditional_branch(instruction) {
+            let branch = self.branches.entry(record.pc).or_default();
+            if let StepEvent::BranchTaken { .. } = record.event {
+                branch.taken += 1;
+            } else {
+                branch.not_taken += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::Write;
+
+use super::{Cpu, KeySource};
+
+/// Bytes to hold before writing them out regardless
+const BUFFER_SIZE: usize = 4096;
+
+const BACKSPACE: u8 = 0x08;
+const BELL: u8 = 0x07;
+const ESCAPE: u8 = 0x1B;
+const DELETE: u8 = 0x7F;
+
+/// How characters written to the display reach its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayMode {
+    /// The output is a terminal in raw mode, so it needs some help to act like the LC-3's display: a line feed also
+    /// returns the cursor to the start of the line, backspace and delete erase the previous character, and other control
+    /// characters are shown in caret notation (`^C`) rather than sent to the terminal. Tabs, carriage returns, the bell,
+    /// and escape (to allow ANSI escape sequences) pass through. Output is line buffered.
+    #[default]
+    Terminal,
+    /// The output is a file or pipe, so every byte is written verbatim. Output is fully buffered.
+    Verbatim,
+}
+
+/// The display's side of DSR and DDR. Characters are buffered, and only written out at the end of a line, when the
+/// buffer fills, when the program polls the keyboard (so that prompts show up before waiting for input), and when it
+/// halts.
+///
+/// The display is always ready unless given a delay, in which case DSR reads as not ready for that many cycles after
+/// each write to DDR, like a real (slow) display.
+pub(super) struct Display<Output: Write> {
+    output: Output,
+    mode: DisplayMode,
+    buffer: Vec<u8>,
+    /// Cycles the display is busy for after each character
+    delay: u64,
+    /// Cycle count at which the display is next ready
+    ready_at: u64,
+}
+
+impl<Output: Write> Display<Output> {
+    pub fn new(output: Output) -> Self {
+        Display {
+            output,
+            mode: DisplayMode::default(),
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            delay: 0,
+            ready_at: 0,
+        }
+    }
+
+    pub fn set_delay(&mut self, delay: u64) {
+        self.delay = delay;
+    }
+
+    pub fn ready(&self, now: u64) -> bool {
+        now >= self.ready_at
+    }
+
+    /// The cycle count at which the display is next ready for a character.
+    pub fn ready_at(&self) -> u64 {
+        self.ready_at
+    }
+
+    pub fn read_dsr(&self, now: u64) -> u16 {
+        (self.ready(now) as u16) << 15
+    }
+
+    pub fn set_mode(&mut self, mode: DisplayMode) {
+        self.flush();
+        self.mode = mode;
+    }
+
+    /// Display a character written to DDR, ready or not.
+    pub fn write(&mut self, value: u16, now: u64) {
+        self.ready_at = now + self.delay;
+        let char = (value & 0xFF) as u8;
+        match (self.mode, char) {
+            (DisplayMode::Verbatim, _) => self.buffer.push(char),
+            (DisplayMode::Terminal, b'\n') => self.buffer.extend_from_slice(b"\r\n"),
+            (DisplayMode::Terminal, BACKSPACE | DELETE) => {
+                self.buffer.extend_from_slice(&[BACKSPACE, b' ', BACKSPACE])
+            }
+            (DisplayMode::Terminal, b'\t' | b'\r' | BELL | ESCAPE) => self.buffer.push(char),
+            (DisplayMode::Terminal, 0..=0x1F) => {
+                self.buffer.extend_from_slice(&[b'^', char + b'@'])
+            }
+            (DisplayMode::Terminal, _) => self.buffer.push(char),
+        }
+
+        if self.buffer.len() >= BUFFER_SIZE || (self.mode == DisplayMode::Terminal && char == b'\n')
+        {
+            self.flush();
+        }
+    }
+
+    /// Write out everything buffered so far.
+    pub fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        // Ignore potential errors writing to the output
+        let _ = self.output.write_all(&self.buffer);
+        let _ = self.output.flush();
+        self.buffer.clear();
+    }
+}
+
+impl<Input: KeySource, Output: Write> Cpu<Input, Output> {
+    /// Make the display take `cycles` cycles to print each character, during which DSR reads as not ready. By default,
+    /// it's always ready. Use [`set_display_checker`](Self::set_display_checker) to catch writes that don't wait.
+    /// Natively serviced traps print regardless.
+    pub fn set_display_delay(&mut self, cycles: u64) {
+        self.memory.display.get_mut().set_delay(cycles);
+    }
+}
+
+impl<Output: Write> Drop for Display<Output> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
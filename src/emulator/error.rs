@@ -0,0 +1,122 @@
+use std::fmt::{self, Display};
+
+use thiserror::Error;
+
+use super::{Diagnostic, StackFault};
+
+/// Exceptions raised by the processor itself, as opposed to interrupts raised by devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    /// RTI (or another privileged operation) executed in user mode
+    PrivilegeViolation,
+    /// The reserved opcode was executed
+    IllegalOpcode,
+    /// User mode code accessed memory it isn't allowed to
+    AccessControlViolation,
+}
+
+impl Exception {
+    /// The entry in the interrupt vector table that this exception's handler address is read from.
+    pub const fn vector(self) -> u8 {
+        match self {
+            Self::PrivilegeViolation => 0x00,
+            Self::IllegalOpcode => 0x01,
+            Self::AccessControlViolation => 0x02,
+        }
+    }
+}
+
+impl Display for Exception {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match self {
+            Self::PrivilegeViolation => "privilege mode violation",
+            Self::IllegalOpcode => "illegal opcode",
+            Self::AccessControlViolation => "access control violation",
+        })
+    }
+}
+
+/// Conditions that stop the emulator because the program can't meaningfully continue.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorError {
+    #[error("unhandled {exception} at {pc:#06x}")]
+    UnhandledException { exception: Exception, pc: u16 },
+    #[error("PC ran into uninitialized memory at {pc:#06x}")]
+    UninitializedExecution { pc: u16 },
+    #[error("unrecoverable nested fault {0}")]
+    NestedFault(FaultChain),
+    #[error("{0}")]
+    Sanitizer(Diagnostic),
+    #[error("{0}")]
+    StackFault(StackFault),
+}
+
+impl EmulatorError {
+    /// Address of the instruction the emulator stopped at
+    pub fn pc(&self) -> u16 {
+        match self {
+            Self::UnhandledException { pc, .. } | Self::UninitializedExecution { pc } => *pc,
+            Self::NestedFault(chain) => chain.pc,
+            Self::Sanitizer(diagnostic) => diagnostic.pc,
+            Self::StackFault(fault) => fault.pc,
+        }
+    }
+}
+
+/// Why an exception or interrupt couldn't be delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultReason {
+    /// Pushing the PSR or PC onto the supervisor stack would have written outside of ordinary memory
+    StackPush { addr: u16 },
+    /// The vector table entry was never initialized, so there's no handler to jump to
+    NoHandler,
+}
+
+/// An exception or interrupt that couldn't be delivered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub vector: u8,
+    pub reason: FaultReason,
+}
+
+impl Display for Fault {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "vector {:#04x}: ", self.vector)?;
+        match self.reason {
+            FaultReason::StackPush { addr } => {
+                write!(formatter, "supervisor stack push to {:#06x}", addr)
+            }
+            FaultReason::NoHandler => formatter.write_str("no handler installed"),
+        }
+    }
+}
+
+/// A sequence of faults, each raised while trying to deliver the one before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultChain {
+    /// Address of the instruction that raised the original exception
+    pub pc: u16,
+    pub faults: Vec<Fault>,
+}
+
+impl Display for FaultChain {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "at {:#06x}", self.pc)?;
+        for fault in &self.faults {
+            write!(formatter, "; {}", fault)?;
+        }
+        Ok(())
+    }
+}
+
+/// What to do when an exception can't be delivered because delivering it faults
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FaultPolicy {
+    /// Stop the emulator and report the fault chain
+    #[default]
+    Halt,
+    /// Reset the processor and restart the OS
+    Reset,
+    /// Deliver a double fault exception through the given vector. If that faults too, halt.
+    Vector(u8),
+}
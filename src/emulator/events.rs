@@ -0,0 +1,56 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{Exception, FaultChain, StackFault};
+
+/// What happened during a single step of the emulator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepEvent {
+    /// An instruction executed and control fell through to the next one
+    Executed,
+    /// A taken conditional branch, a jump, or a subroutine call or return transferred control to `target`
+    BranchTaken { target: u16 },
+    /// A TRAP instruction entered a service routine, or had it serviced natively
+    TrapEntered { vector: u8 },
+    /// The instruction raised an exception and its handler was entered
+    Exception { cause: Exception },
+    /// Raising an exception faulted, but the fault policy recovered by resetting or entering the double fault handler
+    FaultRecovered(FaultChain),
+    /// A stack left its bounds, and the stack policy entered the stack fault handler
+    StackFault(StackFault),
+    /// A device interrupt was accepted and its handler entered. No instruction was executed.
+    InterruptAccepted { vector: u8, priority: u16 },
+    /// An RTI instruction returned from an interrupt, exception, or (in the third edition) trap
+    Rti,
+    /// The machine control register was cleared, halting the machine
+    Halted,
+}
+
+/// The effects of a single step, passed to observers.
+pub struct StepRecord {
+    /// Address of the instruction that was executed, or where execution was interrupted
+    pub pc: u16,
+    /// Where execution continues after the step
+    pub next_pc: u16,
+    /// The instruction that was executed, or `None` if an interrupt was accepted or the fetch faulted instead
+    pub instruction: Option<u16>,
+    /// Register contents before and after the step
+    pub registers_before: [u16; 8],
+    pub registers: [u16; 8],
+    /// The processor status register after the step
+    pub psr: u16,
+    pub event: StepEvent,
+}
+
+/// Something that wants to watch the emulator run, such as a tracer, profiler, or UI.
+pub trait Observer {
+    /// Called after every successful step.
+    fn on_step(&mut self, record: &StepRecord);
+}
+
+/// Lets callers hold onto an observer after handing it to the CPU, so they can read back whatever it collected.
+impl<T: Observer> Observer for Rc<RefCell<T>> {
+    fn on_step(&mut self, record: &StepRecord) {
+        self.borrow_mut().on_step(record);
+    }
+}
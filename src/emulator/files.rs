@@ -0,0 +1,213 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use super::{Cpu, KeySource, MemRegisters, Memory};
+use crate::bit_twiddling::get_bits;
+
+/// How many files a program can have open at once
+pub const FILE_HANDLES: usize = 8;
+
+/// Commands written to FCR, in its low byte. The high byte holds the handle to act on.
+pub mod file_command {
+    /// Open the file named by the string FDR points to for reading
+    pub const OPEN_READ: u16 = 1;
+    /// Open the file named by the string FDR points to for writing, creating it or emptying it first
+    pub const OPEN_WRITE: u16 = 2;
+    /// Open the file named by the string FDR points to for writing at its end, creating it if need be
+    pub const OPEN_APPEND: u16 = 3;
+    pub const CLOSE: u16 = 4;
+    /// Read the next byte into FDR, or set the end of file bit if there isn't one
+    pub const READ: u16 = 5;
+    /// Write the low byte of FDR
+    pub const WRITE: u16 = 6;
+}
+
+/// Why the last file command failed, as reported in FSR's low bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileError {
+    /// The file doesn't exist, or the host couldn't read or write it
+    Io = 1,
+    /// The name is empty or leads outside the directory
+    BadName = 2,
+    /// The handle isn't open, is already open, or is open the other way
+    BadHandle = 3,
+    UnknownCommand = 4,
+}
+
+/// An open file
+enum OpenFile {
+    Reading(BufReader<File>),
+    Writing(BufWriter<File>),
+}
+
+/// A device that gives programs files in one host directory, and nowhere else.
+///
+/// - FSR (xFE30) is the status: `[15]` is always set, since commands finish immediately, `[14]` is set if the last
+///   READ found the end of the file, and `[2:0]` holds a [`FileError`] if the last command failed, or 0.
+/// - FCR (xFE32) takes commands: the [`file_command`] in `[7:0]`, and which handle to act on in `[10:8]`.
+/// - FDR (xFE34) holds a command's argument or result: the address of a file name when opening (one character per
+///   word, ending with x0000, like PUTS), and the byte read or to write.
+///
+/// What the device does isn't undone by stepping back.
+pub(super) struct FileDevice {
+    root: PathBuf,
+    files: [Option<OpenFile>; FILE_HANDLES],
+    end_of_file: bool,
+    error: Option<FileError>,
+    data: u16,
+}
+
+impl FileDevice {
+    fn new(root: PathBuf) -> Self {
+        FileDevice {
+            root,
+            files: Default::default(),
+            end_of_file: false,
+            error: None,
+            data: 0,
+        }
+    }
+
+    pub fn read_fsr(&self) -> u16 {
+        let error = self.error.map_or(0, |error| error as u16);
+        1 << 15 | (self.end_of_file as u16) << 14 | error
+    }
+
+    pub fn read_fdr(&self) -> u16 {
+        self.data
+    }
+
+    pub fn write_fdr(&mut self, value: u16) {
+        self.data = value;
+    }
+
+    /// Carry out a command written to FCR. `name` is the string FDR points to, for opening.
+    fn command(&mut self, value: u16, name: impl FnOnce(u16) -> String) {
+        let command = get_bits::<0, 7>(value);
+        let handle = get_bits::<8, 10>(value) as usize;
+        self.end_of_file = false;
+        self.error = match command {
+            file_command::OPEN_READ | file_command::OPEN_WRITE | file_command::OPEN_APPEND => {
+                let name = name(self.data);
+                self.open(handle, command, &name).err()
+            }
+            file_command::CLOSE => match self.files[handle].take() {
+                Some(OpenFile::Writing(mut file)) => file.flush().err().map(|_| FileError::Io),
+                Some(OpenFile::Reading(_)) => None,
+                None => Some(FileError::BadHandle),
+            },
+            file_command::READ => self.read(handle).err(),
+            file_command::WRITE => match &mut self.files[handle] {
+                Some(OpenFile::Writing(file)) => file
+                    .write_all(&[self.data as u8])
+                    .err()
+                    .map(|_| FileError::Io),
+                _ => Some(FileError::BadHandle),
+            },
+            _ => Some(FileError::UnknownCommand),
+        };
+    }
+
+    fn open(&mut self, handle: usize, command: u16, name: &str) -> Result<(), FileError> {
+        if self.files[handle].is_some() {
+            return Err(FileError::BadHandle);
+        }
+        let path = self.resolve(name)?;
+        let mut options = OpenOptions::new();
+        match command {
+            file_command::OPEN_READ => options.read(true),
+            file_command::OPEN_WRITE => options.write(true).create(true).truncate(true),
+            _ => options.append(true).create(true),
+        };
+        let file = options.open(path).map_err(|_| FileError::Io)?;
+        self.files[handle] = Some(if command == file_command::OPEN_READ {
+            OpenFile::Reading(BufReader::new(file))
+        } else {
+            OpenFile::Writing(BufWriter::new(file))
+        });
+        Ok(())
+    }
+
+    fn read(&mut self, handle: usize) -> Result<(), FileError> {
+        let file = match &mut self.files[handle] {
+            Some(OpenFile::Reading(file)) => file,
+            _ => return Err(FileError::BadHandle),
+        };
+        let mut byte = [0];
+        let read = loop {
+            match file.read(&mut byte) {
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                result => break result,
+            }
+        };
+        match read {
+            Ok(0) => {
+                self.end_of_file = true;
+                self.data = 0;
+                Ok(())
+            }
+            Ok(_) => {
+                self.data = byte[0] as u16;
+                Ok(())
+            }
+            Err(_) => Err(FileError::Io),
+        }
+    }
+
+    /// Where a file name leads on the host, as long as that's inside the directory. Names can include
+    /// subdirectories, but not `..` or absolute paths, and can't follow symbolic links out of the directory.
+    fn resolve(&self, name: &str) -> Result<PathBuf, FileError> {
+        let relative = Path::new(name);
+        let within = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if name.is_empty() || !within {
+            return Err(FileError::BadName);
+        }
+        let path = self.root.join(relative);
+        // The file might not exist yet, but the directory it goes in has to, and has to really be inside the root
+        let parent = path.parent().ok_or(FileError::BadName)?;
+        let root = fs::canonicalize(&self.root).map_err(|_| FileError::Io)?;
+        let parent = fs::canonicalize(parent).map_err(|_| FileError::Io)?;
+        if !parent.starts_with(&root) {
+            return Err(FileError::BadName);
+        }
+        match fs::canonicalize(&path) {
+            Ok(target) if !target.starts_with(&root) => return Err(FileError::BadName),
+            // A link to nowhere, which opening for writing would follow
+            Err(_) if fs::symlink_metadata(&path).is_ok() => return Err(FileError::BadName),
+            _ => {}
+        }
+        Ok(path)
+    }
+}
+
+impl<Input: KeySource, Output: Write> Memory<Input, Output> {
+    /// Handle a write to FSR, FCR, or FDR while the file device is enabled.
+    pub(super) fn write_file_register(&mut self, addr: u16, value: u16) {
+        let memory = &self.memory;
+        if let Some(files) = &mut self.files {
+            match addr {
+                MemRegisters::FCR => files.command(value, |addr| {
+                    (0..=u16::MAX)
+                        .map(|offset| memory[addr.wrapping_add(offset) as usize])
+                        .take_while(|&char| char != 0)
+                        .map(|char| (char & 0xFF) as u8 as char)
+                        .collect()
+                }),
+                MemRegisters::FDR => files.write_fdr(value),
+                // FSR is read-only
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<Input: KeySource, Output: Write> Cpu<Input, Output> {
+    /// Give the program the file device (FSR, FCR, and FDR at xFE30-xFE34), with access to the files in `root` and its
+    /// subdirectories.
+    pub fn enable_files(&mut self, root: impl Into<PathBuf>) {
+        self.memory.files = Some(FileDevice::new(root.into()));
+    }
+}
@@ -0,0 +1,149 @@
+use std::io::{self, Write};
+
+use super::{Cpu, KeySource, Memory, WordState, DEVICE_REGISTERS};
+use crate::bit_twiddling::get_bits;
+
+/// Start of video memory. The picture runs row by row from here up to the device registers.
+pub const FRAMEBUFFER_START: u16 = 0xC000;
+/// Width of the picture in pixels
+pub const FRAMEBUFFER_WIDTH: usize = 128;
+/// Height of the picture in pixels
+pub const FRAMEBUFFER_HEIGHT: usize = 124;
+
+/// Video memory, along with what's happened to it since the last time anyone looked.
+pub(super) struct Framebuffer {
+    /// Whether any pixel changed since the last call to `Cpu::take_frame_changed`
+    changed: bool,
+    /// Whether the program wrote to VCR since the last call to `Cpu::take_frame_request`
+    requested: bool,
+}
+
+/// A copy of video memory. Each pixel is a word holding a 15-bit color: `[14:10]` red, `[9:5]` green, and `[4:0]`
+/// blue. The top bit is ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pixels: Vec<u16>,
+}
+
+impl Frame {
+    /// The raw word for the pixel at column `x` of row `y`.
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        self.pixels[y * FRAMEBUFFER_WIDTH + x]
+    }
+
+    /// The pixel at column `x` of row `y` as 8-bit red, green, and blue.
+    pub fn rgb(&self, x: usize, y: usize) -> [u8; 3] {
+        let pixel = self.pixel(x, y);
+        [
+            expand(get_bits::<10, 14>(pixel)),
+            expand(get_bits::<5, 9>(pixel)),
+            expand(get_bits::<0, 4>(pixel)),
+        ]
+    }
+
+    /// Every pixel as 8-bit red, green, and blue, row by row.
+    pub fn to_rgb(&self) -> Vec<u8> {
+        (0..FRAMEBUFFER_HEIGHT)
+            .flat_map(|y| (0..FRAMEBUFFER_WIDTH).flat_map(move |x| self.rgb(x, y)))
+            .collect()
+    }
+
+    /// Write the picture as a binary PPM (P6) image.
+    pub fn write_ppm(&self, mut output: impl Write) -> io::Result<()> {
+        write!(
+            output,
+            "P6\n{} {}\n255\n",
+            FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT
+        )?;
+        output.write_all(&self.to_rgb())
+    }
+
+    /// Write the picture as a PNG image.
+    pub fn write_png(&self, output: impl Write) -> io::Result<()> {
+        let mut encoder =
+            png::Encoder::new(output, FRAMEBUFFER_WIDTH as u32, FRAMEBUFFER_HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgb())?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+/// Scale a 5-bit color channel to 8 bits, so that full intensity stays full.
+fn expand(channel: u16) -> u8 {
+    ((channel << 3) | (channel >> 2)) as u8
+}
+
+impl<Input: KeySource, Output: Write> Memory<Input, Output> {
+    /// Note a change to the word at `addr`, in case it's a pixel.
+    pub(super) fn touch_framebuffer(&mut self, addr: u16) {
+        if let Some(framebuffer) = &mut self.framebuffer {
+            if (FRAMEBUFFER_START..DEVICE_REGISTERS).contains(&addr) {
+                framebuffer.changed = true;
+            }
+        }
+    }
+
+    /// Handle a write to VCR by asking for the picture to be saved.
+    pub(super) fn request_frame(&mut self) {
+        if let Some(framebuffer) = &mut self.framebuffer {
+            framebuffer.requested = true;
+        }
+    }
+}
+
+impl<Input: KeySource, Output: Write> Cpu<Input, Output> {
+    /// Treat xC000 up to the device registers as video memory: a 128x124 picture of 15-bit pixels, all black to begin
+    /// with. Writing anything to VCR (xFE0C) asks for the picture to be saved; see
+    /// [`take_frame_request`](Self::take_frame_request).
+    pub fn set_framebuffer_enabled(&mut self, enabled: bool) {
+        if enabled == self.memory.framebuffer.is_some() {
+            return;
+        }
+        self.memory.framebuffer = if enabled {
+            // The screen starts out black rather than uninitialized, so programs can read back what they drew
+            let region = FRAMEBUFFER_START as usize..DEVICE_REGISTERS as usize;
+            for state in &mut self.memory.state[region] {
+                if *state == WordState::Uninitialized {
+                    *state = WordState::Loaded;
+                }
+            }
+            Some(Framebuffer {
+                changed: true,
+                requested: false,
+            })
+        } else {
+            None
+        };
+    }
+
+    pub fn framebuffer_enabled(&self) -> bool {
+        self.memory.framebuffer.is_some()
+    }
+
+    /// A copy of what's in video memory right now.
+    pub fn frame(&self) -> Frame {
+        let region = FRAMEBUFFER_START as usize..DEVICE_REGISTERS as usize;
+        Frame {
+            pixels: self.memory.memory[region].to_vec(),
+        }
+    }
+
+    /// Whether any pixel has changed since this was last called, for redrawing only when needed.
+    pub fn take_frame_changed(&mut self) -> bool {
+        match &mut self.memory.framebuffer {
+            Some(framebuffer) => std::mem::take(&mut framebuffer.changed),
+            None => false,
+        }
+    }
+
+    /// Whether the program has asked for the picture to be saved since this was last called.
+    pub fn take_frame_request(&mut self) -> bool {
+        match &mut self.memory.framebuffer {
+            Some(framebuffer) => std::mem::take(&mut framebuffer.requested),
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::VecDeque;
+
+use super::{Clock, Random, WordState};
+
+/// Everything needed to undo a single step: the state that was overwritten, and where it lived.
+pub(super) struct UndoRecord {
+    /// Program counter before the step
+    pub pc: u16,
+    /// Registers that were changed by the step, along with their previous values
+    pub registers: Vec<(u8, u16)>,
+    /// Memory words that were written by the step, along with their previous values and states, in the order they were
+    /// written
+    pub memory: Vec<(u16, u16, WordState)>,
+    /// Saved stack pointers before the step. These change when switching privilege modes.
+    pub saved_usp: u16,
+    pub saved_ssp: u16,
+    /// Which registers were initialized before the step
+    pub registers_initialized: u8,
+    /// Instruction count before the step. Steps that accept an interrupt don't execute an instruction.
+    pub instructions_retired: u64,
+    /// Cycle count before the step
+    pub cycles: u64,
+    /// The random number generator and clock snapshots before the step, since reading their registers changes them
+    pub random: Random,
+    pub clock: Clock,
+    /// Whether a natively serviced IN had already printed its prompt before the step
+    pub hle_prompted: bool,
+}
+
+impl UndoRecord {
+    pub fn wrote_to(&self, addr: u16) -> bool {
+        self.memory.iter().any(|(written, _, _)| *written == addr)
+    }
+}
+
+/// A bounded log of undo records, oldest first. Once full, the oldest records are discarded to make room.
+pub(super) struct History {
+    records: VecDeque<UndoRecord>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            // Grow as records arrive, so a generous limit doesn't allocate it all up front
+            records: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, record: UndoRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn pop(&mut self) -> Option<UndoRecord> {
+        self.records.pop_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+}
@@ -0,0 +1,224 @@
+use std::io::Write;
+
+use super::{Cpu, Edition, KeySource, MemRegisters, DEVICE_REGISTERS};
+use crate::bit_twiddling::get_bits;
+use crate::opcode::Opcode;
+
+/// Message printed by the reference OS's IN routine before it waits for a key
+const IN_PROMPT: &[u8] = b"\nInput a character> ";
+/// Message printed by the reference OS's HALT routine before it stops the machine
+const HALT_MESSAGE: &[u8] = b"\n\n--- halting the LC-3 ---\n\n";
+
+/// The standard trap service routines, which can be emulated natively instead of run from an OS image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapRoutine {
+    Getc,
+    Out,
+    Puts,
+    In,
+    Putsp,
+    Halt,
+}
+
+impl TrapRoutine {
+    pub const ALL: [TrapRoutine; 6] = [
+        Self::Getc,
+        Self::Out,
+        Self::Puts,
+        Self::In,
+        Self::Putsp,
+        Self::Halt,
+    ];
+
+    pub const fn vector(self) -> u8 {
+        match self {
+            Self::Getc => 0x20,
+            Self::Out => 0x21,
+            Self::Puts => 0x22,
+            Self::In => 0x23,
+            Self::Putsp => 0x24,
+            Self::Halt => 0x25,
+        }
+    }
+
+    pub fn from_vector(vector: u8) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|routine| routine.vector() == vector)
+    }
+
+    /// This routine's bit in the CPU's set of natively serviced traps
+    pub(super) const fn mask(self) -> u8 {
+        1 << (self.vector() - 0x20)
+    }
+}
+
+impl<Input: KeySource, Output: Write> Cpu<Input, Output> {
+    /// Choose whether a trap routine is serviced natively, bypassing the trap vector table and OS code entirely.
+    pub fn set_trap_hle(&mut self, routine: TrapRoutine, enabled: bool) {
+        if enabled {
+            self.hle_traps |= routine.mask();
+        } else {
+            self.hle_traps &= !routine.mask();
+        }
+    }
+
+    /// The natively serviced routine that an instruction calls, if it's a TRAP to one.
+    pub(super) fn hle_routine(&self, instruction: u16) -> Option<TrapRoutine> {
+        if Opcode::from_int(get_bits::<12, 15>(instruction) as u8) != Opcode::Trap {
+            return None;
+        }
+        TrapRoutine::from_vector(get_bits::<0, 7>(instruction) as u8)
+            .filter(|routine| self.hle_traps & routine.mask() != 0)
+    }
+
+    /// Display a character the way the OS routine would: wait for the display to be ready, then store to DDR through
+    /// the same checked path as the program's own STI, so protection and the display checker apply. Returns `None` if
+    /// the store faulted, in which case the routine must stop.
+    fn output(&mut self, char: u16) -> Option<()> {
+        // The routine's polling loop would spin on DSR until then
+        let ready_at = self.memory.display.get_mut().ready_at();
+        self.memory.cycles = self.memory.cycles.max(ready_at);
+        self.write(MemRegisters::DDR, char)
+    }
+
+    fn print(&mut self, message: &[u8]) -> Option<()> {
+        for char in message {
+            self.output(*char as u16)?;
+        }
+        Some(())
+    }
+
+    /// Read the pending key from the keyboard, if there is one.
+    fn poll_keyboard(&mut self) -> Option<u16> {
+        if self.memory.get(MemRegisters::KBSR) & 0x8000 == 0 {
+            return None;
+        }
+        Some(self.memory.get(MemRegisters::KBDR))
+    }
+
+    /// Leave the PC pointing at the TRAP instruction so it runs again, just like the OS's polling loop would spin.
+    fn retry_trap(&mut self) {
+        self.pc = self.pc.wrapping_sub(1);
+    }
+
+    /// Return from a natively serviced trap with the registers the way the reference OS routine leaves them.
+    /// `cc_register` is the register the routine last loaded, which determines the condition codes on return.
+    fn return_from_trap(&mut self, cc_register: usize) {
+        // In the second-edition LC-3, TRAP sets R7 to the previous PC and the OS routine returns with RET, leaving the
+        // condition codes from its last load. In the third edition, RTI restores the PSR and with it the old condition
+        // codes, so there's nothing to do.
+        if self.edition == Edition::Second {
+            self.set_register(7, self.pc);
+            self.set_condition_codes(self.registers[cc_register]);
+        }
+    }
+
+    /// Service a trap natively, with the same register side effects as the reference OS routine. Routines that wait on
+    /// the keyboard leave the PC on the TRAP instruction until a key is available.
+    ///
+    /// PUTS and PUTSP read their strings like any other load by the program, so protected memory raises an access
+    /// control violation and the sanitizer sees every word. A string that runs into the device registers ends there,
+    /// rather than reading them as characters. Output is stored to DDR the same way, after waiting for the display to
+    /// be ready.
+    pub(super) fn service_trap(&mut self, routine: TrapRoutine) {
+        match routine {
+            TrapRoutine::Getc => {
+                let key = match self.poll_keyboard() {
+                    Some(key) => key,
+                    None => {
+                        self.retry_trap();
+                        return;
+                    }
+                };
+                self.set_register(0, key);
+                self.return_from_trap(0);
+            }
+
+            TrapRoutine::Out => {
+                if self.output(self.registers[0]).is_none() {
+                    return;
+                }
+                self.return_from_trap(1);
+            }
+
+            TrapRoutine::Puts => {
+                // One character per word
+                let mut addr = self.registers[0];
+                while addr < DEVICE_REGISTERS {
+                    let char = match self.read(addr) {
+                        Some(char) => char,
+                        None => return,
+                    };
+                    if char == 0 {
+                        break;
+                    }
+                    if self.output(char).is_none() {
+                        return;
+                    }
+                    addr = addr.wrapping_add(1);
+                }
+                self.return_from_trap(7);
+            }
+
+            TrapRoutine::Putsp => {
+                // Two characters per word, low byte first. A NUL in either half ends the string.
+                let mut addr = self.registers[0];
+                while addr < DEVICE_REGISTERS {
+                    let word = match self.read(addr) {
+                        Some(word) => word,
+                        None => return,
+                    };
+                    if word & 0xFF == 0 {
+                        break;
+                    }
+                    if self.output(word & 0xFF).is_none() {
+                        return;
+                    }
+                    if word >> 8 == 0 {
+                        break;
+                    }
+                    if self.output(word >> 8).is_none() {
+                        return;
+                    }
+                    addr = addr.wrapping_add(1);
+                }
+                self.return_from_trap(7);
+            }
+
+            TrapRoutine::In => {
+                // Only prompt once, even if we have to wait around for a key
+                if !self.hle_prompted {
+                    if self.print(IN_PROMPT).is_none() {
+                        return;
+                    }
+                    self.hle_prompted = true;
+                }
+                let key = match self.poll_keyboard() {
+                    Some(key) => key,
+                    None => {
+                        self.retry_trap();
+                        return;
+                    }
+                };
+                self.hle_prompted = false;
+                self.set_register(0, key);
+                // Echo the character back, followed by a newline
+                if self.output(key).and_then(|_| self.print(b"\n")).is_none() {
+                    return;
+                }
+                self.return_from_trap(7);
+            }
+
+            TrapRoutine::Halt => {
+                if self.print(HALT_MESSAGE).is_none() {
+                    return;
+                }
+                self.memory.set(
+                    MemRegisters::MCR,
+                    self.memory.get(MemRegisters::MCR) & !(1 << 15),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use byteorder::ReadBytesExt;
+
+/// A source of keypresses for the keyboard device.
+pub trait KeySource {
+    /// Return the next key, if one is available. `now` is the number of instructions executed so far.
+    fn poll_key(&mut self, now: u64) -> Option<u8>;
+}
+
+/// Any byte stream can be used as a keyboard. Reads block until a byte is available, so a program polling KBSR waits
+/// right along with it; wrap the stream in a [`ThreadedInput`] to keep running instead.
+impl<R: Read> KeySource for R {
+    fn poll_key(&mut self, _now: u64) -> Option<u8> {
+        self.read_u8().ok()
+    }
+}
+
+/// Keyboard input fed from a predetermined sequence of keys, for automated runs.
+pub struct ScriptedInput {
+    keys: VecDeque<u8>,
+    /// How many instructions to wait after a key is read before making the next one available
+    delay: u64,
+    /// The instruction count at which the next key becomes available
+    next_ready: u64,
+}
+
+impl ScriptedInput {
+    /// Create a script that makes each key available `delay` instructions after the previous one was read. The first
+    /// key is available `delay` instructions after the program starts.
+    pub fn new(keys: impl Into<Vec<u8>>, delay: u64) -> Self {
+        ScriptedInput {
+            keys: keys.into().into(),
+            delay,
+            next_ready: delay,
+        }
+    }
+}
+
+impl KeySource for ScriptedInput {
+    fn poll_key(&mut self, now: u64) -> Option<u8> {
+        if now < self.next_ready {
+            return None;
+        }
+        let key = self.keys.pop_front()?;
+        self.next_ready = now + self.delay;
+        Some(key)
+    }
+}
+
+/// Keyboard input read from a byte stream, such as stdin, on a background thread. Polling never blocks: KBSR reads 0
+/// until a key arrives, and keys typed before the program asks for them queue up until it does.
+pub struct ThreadedInput {
+    keys: Receiver<u8>,
+}
+
+impl ThreadedInput {
+    /// Start reading keys from `stream`. The thread stops at the end of the stream, or once the `ThreadedInput` is
+    /// dropped and another key arrives.
+    pub fn new(mut stream: impl Read + Send + 'static) -> Self {
+        let (sender, keys) = channel();
+        thread::spawn(move || {
+            let mut key = [0];
+            loop {
+                match stream.read(&mut key) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        if sender.send(key[0]).is_err() {
+                            break;
+                        }
+                    }
+                    Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                    Err(_) => break,
+                }
+            }
+        });
+        ThreadedInput { keys }
+    }
+}
+
+impl KeySource for ThreadedInput {
+    fn poll_key(&mut self, _now: u64) -> Option<u8> {
+        self.keys.try_recv().ok()
+    }
+}
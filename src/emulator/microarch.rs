@@ -0,0 +1,822 @@
+use std::io::Write;
+
+use super::{
+    Access, Cpu, Edition, EmulatorError, Exception, KeySource, MemRegisters, Stack, StepEvent,
+    INTERRUPT_VECTOR_TABLE,
+};
+use crate::bit_twiddling::{get_bits, sign_extend};
+
+/// What drives the bus during a state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gate {
+    Pc,
+    Mdr,
+    Alu,
+    Marmux,
+    Psr,
+    /// The stack pointer adder, which computes R6 plus or minus 1
+    Sp,
+    /// The table and vector registers, forming the address of a vector table entry
+    Vector,
+}
+
+/// A register loaded at the end of a state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latch {
+    Mar,
+    Mdr,
+    Ir,
+    Ben,
+    /// The general purpose register chosen by DRMUX
+    Reg,
+    Cc,
+    Pc,
+    /// PSR[15], the privilege mode
+    Priv,
+    /// PSR[10:8], the priority level
+    Priority,
+    /// The whole PSR, popped by RTI
+    Psr,
+    SavedSsp,
+    SavedUsp,
+    /// Whether the address in MAR is off limits to the current privilege mode
+    Acv,
+    /// The table and vector registers
+    Vector,
+}
+
+/// One state of the microsequencer: the register transfers it performed and the control signals behind them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MicroStep {
+    /// The state's number in the textbook's state diagram
+    pub state: u8,
+    /// The state's register transfers, as written in the state diagram
+    pub rtl: &'static str,
+    /// What drove the bus, and the value on it
+    pub bus: Option<(Gate, u16)>,
+    pub latches: &'static [Latch],
+    /// Whether memory was enabled, and for what. Memory reads and writes at MAR, through MDR.
+    pub memory: Option<Access>,
+    /// Datapath registers at the end of the state
+    pub mar: u16,
+    pub mdr: u16,
+    pub ir: u16,
+    pub ben: bool,
+    pub pc: u16,
+}
+
+impl MicroStep {
+    fn bus(mut self, gate: Gate, value: u16) -> Self {
+        self.bus = Some((gate, value));
+        self
+    }
+
+    fn latches(mut self, latches: &'static [Latch]) -> Self {
+        self.latches = latches;
+        self
+    }
+
+    fn memory(mut self, access: Access) -> Self {
+        self.memory = Some(access);
+        self
+    }
+}
+
+/// An alternative to [`Cpu::step`] that runs each instruction through the states of the textbook's finite state
+/// machine (appendix C), one register transfer at a time, so that the control signals and bus values of every state can
+/// be watched.
+///
+/// The end result of each step is the same as `Cpu::step`'s. To match it, interrupts are checked before state 18
+/// increments the PC, so state 43 pushes the PC as is, and exceptions return past the instruction that raised them. The
+/// sanitizer, observers, and history aren't supported; natively serviced traps run in a single state.
+#[derive(Debug, Clone, Default)]
+pub struct Microsequencer {
+    mar: u16,
+    mdr: u16,
+    ir: u16,
+    ben: bool,
+    acv: bool,
+    /// Address of the vector table entry to jump through, from the table and vector registers
+    vector: u16,
+    /// Priority level of the interrupt being accepted
+    priority: u16,
+}
+
+impl Microsequencer {
+    pub fn new() -> Self {
+        Microsequencer::default()
+    }
+
+    pub fn mar(&self) -> u16 {
+        self.mar
+    }
+
+    pub fn mdr(&self) -> u16 {
+        self.mdr
+    }
+
+    pub fn ir(&self) -> u16 {
+        self.ir
+    }
+
+    pub fn ben(&self) -> bool {
+        self.ben
+    }
+
+    /// Execute a single instruction, or accept a pending interrupt, calling `on_state` after every state. Returns the
+    /// same as `Cpu::step` would.
+    pub fn step<Input: KeySource, Output: Write>(
+        &mut self,
+        cpu: &mut Cpu<Input, Output>,
+        mut on_state: impl FnMut(&MicroStep),
+    ) -> Result<StepEvent, EmulatorError> {
+        cpu.event = StepEvent::Executed;
+        let was_running = !cpu.should_halt();
+        let instruction = self.run(cpu, &mut on_state)?;
+        if let Some(error) = cpu.pending_error.take() {
+            return Err(error);
+        }
+
+        cpu.advance_clock(instruction);
+        if instruction.is_some() {
+            cpu.memory.instructions_retired += 1;
+            if was_running && cpu.should_halt() {
+                cpu.event = StepEvent::Halted;
+            }
+        }
+        Ok(cpu.event.clone())
+    }
+
+    fn trace(&self, pc: u16, state: u8, rtl: &'static str) -> MicroStep {
+        MicroStep {
+            state,
+            rtl,
+            bus: None,
+            latches: &[],
+            memory: None,
+            mar: self.mar,
+            mdr: self.mdr,
+            ir: self.ir,
+            ben: self.ben,
+            pc,
+        }
+    }
+
+    fn load_mar<Input: KeySource, Output: Write>(
+        &mut self,
+        cpu: &Cpu<Input, Output>,
+        addr: u16,
+        access: Access,
+    ) {
+        self.mar = addr;
+        self.acv = !cpu.address_accessible(addr, access);
+    }
+
+    /// Run states from 18 until the next return to 18, returning the instruction executed, if any.
+    fn run<Input: KeySource, Output: Write>(
+        &mut self,
+        cpu: &mut Cpu<Input, Output>,
+        on_state: &mut dyn FnMut(&MicroStep),
+    ) -> Result<Option<u16>, EmulatorError> {
+        let third_edition = cpu.edition == Edition::Third;
+        let mut executed = None;
+        let mut state = 18;
+        loop {
+            let ir = self.ir;
+            let sr1 = cpu.registers[get_bits::<6, 8>(ir) as usize];
+            let next = match state {
+                18 => match cpu.pending_interrupt {
+                    Some((vector, priority)) if priority > cpu.get_priority_level() => {
+                        on_state(&self.trace(cpu.pc, 18, "[INT]"));
+                        self.interrupt(cpu, on_state, vector, priority)
+                    }
+                    _ => {
+                        if cpu.address_accessible(cpu.pc, Access::Execute)
+                            && !cpu.memory.is_initialized(cpu.pc)
+                        {
+                            return Err(EmulatorError::UninitializedExecution { pc: cpu.pc });
+                        }
+                        self.load_mar(cpu, cpu.pc, Access::Execute);
+                        cpu.pc = cpu.pc.wrapping_add(1);
+                        on_state(
+                            &self
+                                .trace(cpu.pc, 18, "MAR<-PC, PC<-PC+1, set ACV, [INT]")
+                                .bus(Gate::Pc, self.mar)
+                                .latches(&[Latch::Mar, Latch::Pc, Latch::Acv]),
+                        );
+                        Some(33)
+                    }
+                },
+                33 => self.read(cpu, on_state, 33, "[ACV] MDR<-M", 35),
+                35 => {
+                    self.ir = self.mdr;
+                    executed = Some(self.ir);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 35, "IR<-MDR")
+                            .bus(Gate::Mdr, self.mdr)
+                            .latches(&[Latch::Ir]),
+                    );
+                    Some(32)
+                }
+                32 => {
+                    let nzp = get_bits::<9, 11>(self.ir) & cpu.memory.get(MemRegisters::PSR);
+                    self.ben = nzp != 0;
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 32, "BEN<-IR[11]&N+IR[10]&Z+IR[9]&P, [IR[15:12]]")
+                            .latches(&[Latch::Ben]),
+                    );
+                    // Each opcode's first state is numbered after it
+                    Some(get_bits::<12, 15>(self.ir) as u8)
+                }
+
+                // BR
+                0 => {
+                    on_state(&self.trace(cpu.pc, 0, "[BEN]"));
+                    if self.ben {
+                        Some(22)
+                    } else {
+                        None
+                    }
+                }
+                22 => {
+                    cpu.pc = cpu.pc.wrapping_add(offset9(ir));
+                    cpu.event = StepEvent::BranchTaken { target: cpu.pc };
+                    on_state(&self.trace(cpu.pc, 22, "PC<-PC+off9").latches(&[Latch::Pc]));
+                    None
+                }
+
+                // ADD, AND, and NOT
+                1 | 5 | 9 => {
+                    let sr2 = if get_bits::<5, 5>(ir) == 1 {
+                        sign_extend::<5>(get_bits::<0, 4>(ir) as i16) as u16
+                    } else {
+                        cpu.registers[get_bits::<0, 2>(ir) as usize]
+                    };
+                    let (result, rtl) = match state {
+                        1 => (sr1.wrapping_add(sr2), "DR<-SR1+OP2, set CC"),
+                        5 => (sr1 & sr2, "DR<-SR1&OP2, set CC"),
+                        _ => (!sr1, "DR<-NOT(SR), set CC"),
+                    };
+                    if self.set_dr(cpu, result, true).is_some() {
+                        on_state(
+                            &self
+                                .trace(cpu.pc, state, rtl)
+                                .bus(Gate::Alu, result)
+                                .latches(&[Latch::Reg, Latch::Cc]),
+                        );
+                    }
+                    None
+                }
+                // LEA
+                14 => {
+                    let addr = cpu.pc.wrapping_add(offset9(ir));
+                    if self.set_dr(cpu, addr, !third_edition).is_some() {
+                        let step = if third_edition {
+                            self.trace(cpu.pc, 14, "DR<-PC+off9").latches(&[Latch::Reg])
+                        } else {
+                            self.trace(cpu.pc, 14, "DR<-PC+off9, set CC")
+                                .latches(&[Latch::Reg, Latch::Cc])
+                        };
+                        on_state(&step.bus(Gate::Marmux, addr));
+                    }
+                    None
+                }
+
+                // LD, LDR, and LDI
+                2 | 10 => {
+                    self.load_mar(cpu, cpu.pc.wrapping_add(offset9(ir)), Access::Read);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, state, "MAR<-PC+off9, set ACV")
+                            .bus(Gate::Marmux, self.mar)
+                            .latches(&[Latch::Mar, Latch::Acv]),
+                    );
+                    Some(if state == 2 { 25 } else { 24 })
+                }
+                6 => {
+                    self.load_mar(cpu, sr1.wrapping_add(offset6(ir)), Access::Read);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 6, "MAR<-B+off6, set ACV")
+                            .bus(Gate::Marmux, self.mar)
+                            .latches(&[Latch::Mar, Latch::Acv]),
+                    );
+                    Some(25)
+                }
+                24 => self.read(cpu, on_state, 24, "[ACV] MDR<-M", 26),
+                26 => {
+                    self.load_mar(cpu, self.mdr, Access::Read);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 26, "MAR<-MDR, set ACV")
+                            .bus(Gate::Mdr, self.mdr)
+                            .latches(&[Latch::Mar, Latch::Acv]),
+                    );
+                    Some(25)
+                }
+                25 => self.read(cpu, on_state, 25, "[ACV] MDR<-M", 27),
+                27 => {
+                    if self.set_dr(cpu, self.mdr, true).is_some() {
+                        on_state(
+                            &self
+                                .trace(cpu.pc, 27, "DR<-MDR, set CC")
+                                .bus(Gate::Mdr, self.mdr)
+                                .latches(&[Latch::Reg, Latch::Cc]),
+                        );
+                    }
+                    None
+                }
+
+                // ST, STR, and STI
+                3 => {
+                    self.load_mar(cpu, cpu.pc.wrapping_add(offset9(ir)), Access::Write);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 3, "MAR<-PC+off9, set ACV")
+                            .bus(Gate::Marmux, self.mar)
+                            .latches(&[Latch::Mar, Latch::Acv]),
+                    );
+                    Some(23)
+                }
+                7 => {
+                    self.load_mar(cpu, sr1.wrapping_add(offset6(ir)), Access::Write);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 7, "MAR<-B+off6, set ACV")
+                            .bus(Gate::Marmux, self.mar)
+                            .latches(&[Latch::Mar, Latch::Acv]),
+                    );
+                    Some(23)
+                }
+                11 => {
+                    self.load_mar(cpu, cpu.pc.wrapping_add(offset9(ir)), Access::Read);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 11, "MAR<-PC+off9, set ACV")
+                            .bus(Gate::Marmux, self.mar)
+                            .latches(&[Latch::Mar, Latch::Acv]),
+                    );
+                    Some(29)
+                }
+                29 => self.read(cpu, on_state, 29, "[ACV] MDR<-M", 31),
+                31 => {
+                    self.load_mar(cpu, self.mdr, Access::Write);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 31, "MAR<-MDR, set ACV")
+                            .bus(Gate::Mdr, self.mdr)
+                            .latches(&[Latch::Mar, Latch::Acv]),
+                    );
+                    Some(23)
+                }
+                23 => {
+                    self.mdr = cpu.registers[get_bits::<9, 11>(ir) as usize];
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 23, "MDR<-SR")
+                            .bus(Gate::Alu, self.mdr)
+                            .latches(&[Latch::Mdr]),
+                    );
+                    Some(16)
+                }
+                16 => {
+                    if self.acv {
+                        Some(60)
+                    } else {
+                        // The interpreter's store, so the display checker and devices see it too
+                        if cpu.write(self.mar, self.mdr).is_none() {
+                            return Ok(executed);
+                        }
+                        on_state(
+                            &self
+                                .trace(cpu.pc, 16, "[ACV] M[MAR]<-MDR")
+                                .memory(Access::Write),
+                        );
+                        None
+                    }
+                }
+
+                // JSR and JSRR
+                4 => {
+                    on_state(&self.trace(cpu.pc, 4, "[IR[11]]"));
+                    Some(if get_bits::<11, 11>(ir) == 1 { 21 } else { 20 })
+                }
+                21 | 20 => {
+                    let return_addr = cpu.pc;
+                    let (target, rtl) = if state == 21 {
+                        let offset = sign_extend::<11>(get_bits::<0, 10>(ir) as i16) as u16;
+                        (cpu.pc.wrapping_add(offset), "R7<-PC, PC<-PC+off11")
+                    } else {
+                        // Read BaseR before R7 is overwritten, in case they're the same
+                        (sr1, "TEMP<-BaseR, R7<-PC, PC<-TEMP")
+                    };
+                    cpu.pc = target;
+                    cpu.set_register(7, return_addr);
+                    cpu.event = StepEvent::BranchTaken { target };
+                    on_state(
+                        &self
+                            .trace(cpu.pc, state, rtl)
+                            .bus(Gate::Pc, return_addr)
+                            .latches(&[Latch::Reg, Latch::Pc]),
+                    );
+                    None
+                }
+
+                // JMP, and JMPT if enabled
+                12 => {
+                    let jmpt = cpu.jmpt_enabled && get_bits::<0, 0>(ir) == 1;
+                    if jmpt && get_bits::<15, 15>(cpu.memory.get(MemRegisters::PSR)) == 1 {
+                        Some(44)
+                    } else {
+                        cpu.pc = sr1;
+                        cpu.event = StepEvent::BranchTaken { target: sr1 };
+                        let step = if jmpt {
+                            cpu.saved_ssp = cpu.registers[6];
+                            cpu.set_register(6, cpu.saved_usp);
+                            let psr = cpu.memory.get(MemRegisters::PSR);
+                            cpu.memory.set(MemRegisters::PSR, psr | (1 << 15));
+                            self.trace(
+                                cpu.pc,
+                                12,
+                                "PC<-BaseR, Saved_SSP<-R6, R6<-Saved_USP, PSR[15]<-1",
+                            )
+                            .latches(&[
+                                Latch::Pc,
+                                Latch::SavedSsp,
+                                Latch::Reg,
+                                Latch::Priv,
+                            ])
+                        } else {
+                            self.trace(cpu.pc, 12, "PC<-BaseR").latches(&[Latch::Pc])
+                        };
+                        on_state(&step);
+                        None
+                    }
+                }
+
+                // RTI
+                8 => {
+                    let r6 = cpu.registers[6];
+                    if get_bits::<15, 15>(cpu.memory.get(MemRegisters::PSR)) == 1 {
+                        on_state(&self.trace(cpu.pc, 8, "[PSR[15]]"));
+                        Some(44)
+                    } else if cpu
+                        .check_stack_pointer(Stack::Supervisor, r6, r6.wrapping_add(2))
+                        .is_none()
+                    {
+                        None
+                    } else {
+                        self.load_mar(cpu, r6, Access::Read);
+                        on_state(
+                            &self
+                                .trace(cpu.pc, 8, "MAR<-R6, [PSR[15]]")
+                                .bus(Gate::Alu, r6)
+                                .latches(&[Latch::Mar]),
+                        );
+                        Some(36)
+                    }
+                }
+                36 => self.read(cpu, on_state, 36, "MDR<-M", 38),
+                38 => {
+                    cpu.pc = self.mdr;
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 38, "PC<-MDR")
+                            .bus(Gate::Mdr, self.mdr)
+                            .latches(&[Latch::Pc]),
+                    );
+                    Some(39)
+                }
+                39 => {
+                    let r6 = cpu.registers[6].wrapping_add(1);
+                    self.load_mar(cpu, r6, Access::Read);
+                    cpu.set_register(6, r6);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 39, "MAR<-R6+1, R6<-R6+1")
+                            .bus(Gate::Sp, r6)
+                            .latches(&[Latch::Mar, Latch::Reg]),
+                    );
+                    Some(40)
+                }
+                40 => self.read(cpu, on_state, 40, "MDR<-M", 42),
+                42 => {
+                    cpu.memory.set(MemRegisters::PSR, self.mdr);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 42, "PSR<-MDR")
+                            .bus(Gate::Mdr, self.mdr)
+                            .latches(&[Latch::Psr]),
+                    );
+                    Some(34)
+                }
+                34 => {
+                    let r6 = cpu.registers[6].wrapping_add(1);
+                    cpu.set_register(6, r6);
+                    cpu.event = StepEvent::Rti;
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 34, "R6<-R6+1, [PSR[15]]")
+                            .bus(Gate::Sp, r6)
+                            .latches(&[Latch::Reg]),
+                    );
+                    if get_bits::<15, 15>(cpu.memory.get(MemRegisters::PSR)) == 1 {
+                        Some(59)
+                    } else {
+                        None
+                    }
+                }
+                59 => {
+                    cpu.saved_ssp = cpu.registers[6];
+                    cpu.set_register(6, cpu.saved_usp);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 59, "Saved_SSP<-R6, R6<-Saved_USP")
+                            .latches(&[Latch::SavedSsp, Latch::Reg]),
+                    );
+                    None
+                }
+
+                // TRAP
+                15 => {
+                    let trap_vector = get_bits::<0, 7>(ir);
+                    cpu.event = StepEvent::TrapEntered {
+                        vector: trap_vector as u8,
+                    };
+                    if let Some(routine) = cpu.hle_routine(ir) {
+                        cpu.service_trap(routine);
+                        on_state(&self.trace(cpu.pc, 15, "(serviced natively)"));
+                        None
+                    } else if third_edition {
+                        let ssp = if get_bits::<15, 15>(cpu.memory.get(MemRegisters::PSR)) == 1 {
+                            cpu.saved_ssp
+                        } else {
+                            cpu.registers[6]
+                        };
+                        if cpu
+                            .check_stack_pointer(Stack::Supervisor, ssp, ssp.wrapping_sub(2))
+                            .is_none()
+                        {
+                            None
+                        } else {
+                            self.vector = trap_vector;
+                            Some(self.enter_supervisor_mode(
+                                cpu,
+                                on_state,
+                                15,
+                                "Table<-x00, Vector<-IR[7:0], MDR<-PSR, PSR[15]<-0, [PSR[15]]",
+                            ))
+                        }
+                    } else {
+                        self.load_mar(cpu, trap_vector, Access::Read);
+                        on_state(
+                            &self
+                                .trace(cpu.pc, 15, "MAR<-ZEXT(IR[7:0])")
+                                .bus(Gate::Marmux, self.mar)
+                                .latches(&[Latch::Mar]),
+                        );
+                        Some(28)
+                    }
+                }
+                28 => {
+                    self.mdr = cpu.memory.get(self.mar);
+                    cpu.set_register(7, cpu.pc);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 28, "MDR<-M, R7<-PC")
+                            .bus(Gate::Pc, cpu.pc)
+                            .latches(&[Latch::Mdr, Latch::Reg])
+                            .memory(Access::Read),
+                    );
+                    Some(30)
+                }
+                30 => {
+                    cpu.pc = self.mdr;
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 30, "PC<-MDR")
+                            .bus(Gate::Mdr, self.mdr)
+                            .latches(&[Latch::Pc]),
+                    );
+                    None
+                }
+
+                // Exceptions
+                13 => self.exception(cpu, on_state, 13, Exception::IllegalOpcode),
+                44 => self.exception(cpu, on_state, 44, Exception::PrivilegeViolation),
+                60 => self.exception(cpu, on_state, 60, Exception::AccessControlViolation),
+
+                // Entering a handler: push the PSR and PC onto the supervisor stack, then jump through the vector table
+                45 => {
+                    cpu.saved_usp = cpu.registers[6];
+                    cpu.set_register(6, cpu.saved_ssp);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 45, "Saved_USP<-R6, R6<-Saved_SSP")
+                            .latches(&[Latch::SavedUsp, Latch::Reg]),
+                    );
+                    Some(37)
+                }
+                37 | 47 => {
+                    let r6 = cpu.registers[6].wrapping_sub(1);
+                    self.mar = r6;
+                    cpu.set_register(6, r6);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, state, "MAR<-R6-1, R6<-R6-1")
+                            .bus(Gate::Sp, r6)
+                            .latches(&[Latch::Mar, Latch::Reg]),
+                    );
+                    Some(if state == 37 { 41 } else { 48 })
+                }
+                41 | 48 => {
+                    if cpu.write(self.mar, self.mdr).is_none() {
+                        return Ok(executed);
+                    }
+                    on_state(
+                        &self
+                            .trace(cpu.pc, state, "M[MAR]<-MDR")
+                            .memory(Access::Write),
+                    );
+                    Some(if state == 41 { 43 } else { 50 })
+                }
+                43 => {
+                    self.mdr = cpu.pc;
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 43, "MDR<-PC")
+                            .bus(Gate::Pc, cpu.pc)
+                            .latches(&[Latch::Mdr]),
+                    );
+                    Some(47)
+                }
+                50 => {
+                    self.mar = self.vector;
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 50, "MAR<-Table'Vector")
+                            .bus(Gate::Vector, self.mar)
+                            .latches(&[Latch::Mar]),
+                    );
+                    Some(52)
+                }
+                52 => {
+                    self.mdr = cpu.memory.get(self.mar);
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 52, "MDR<-M")
+                            .latches(&[Latch::Mdr])
+                            .memory(Access::Read),
+                    );
+                    Some(54)
+                }
+                54 => {
+                    cpu.pc = self.mdr;
+                    on_state(
+                        &self
+                            .trace(cpu.pc, 54, "PC<-MDR")
+                            .bus(Gate::Mdr, self.mdr)
+                            .latches(&[Latch::Pc]),
+                    );
+                    None
+                }
+                _ => unreachable!("no state {}", state),
+            };
+
+            match next {
+                Some(next) => state = next,
+                None => return Ok(executed),
+            }
+        }
+    }
+
+    /// A state that reads memory at MAR into MDR, or raises an access control violation if MAR is off limits
+    fn read<Input: KeySource, Output: Write>(
+        &mut self,
+        cpu: &mut Cpu<Input, Output>,
+        on_state: &mut dyn FnMut(&MicroStep),
+        state: u8,
+        rtl: &'static str,
+        next: u8,
+    ) -> Option<u8> {
+        if self.acv {
+            // The fetch's violation is raised by state 60 too
+            return Some(60);
+        }
+        self.mdr = cpu.memory.get(self.mar);
+        on_state(
+            &self
+                .trace(cpu.pc, state, rtl)
+                .latches(&[Latch::Mdr])
+                .memory(Access::Read),
+        );
+        Some(next)
+    }
+
+    /// Write the result of an instruction to its destination register, like the interpreter: checking the stack
+    /// pointer's bounds if it's R6, and stopping the instruction if that fails.
+    fn set_dr<Input: KeySource, Output: Write>(
+        &mut self,
+        cpu: &mut Cpu<Input, Output>,
+        value: u16,
+        set_cc: bool,
+    ) -> Option<()> {
+        let dr = get_bits::<9, 11>(self.ir) as usize;
+        if dr == 6 {
+            cpu.check_program_stack_pointer(value)?;
+        }
+        cpu.set_register(dr, value);
+        if set_cc {
+            cpu.set_condition_codes(value);
+        }
+        Some(())
+    }
+
+    /// The first state of every handler entry: latch the old PSR into MDR and switch to supervisor mode. Returns the
+    /// next state, which switches stacks if coming from user mode.
+    fn enter_supervisor_mode<Input: KeySource, Output: Write>(
+        &mut self,
+        cpu: &mut Cpu<Input, Output>,
+        on_state: &mut dyn FnMut(&MicroStep),
+        state: u8,
+        rtl: &'static str,
+    ) -> u8 {
+        let psr = cpu.memory.get(MemRegisters::PSR);
+        self.mdr = psr;
+        cpu.memory.set(MemRegisters::PSR, psr & !(1 << 15));
+        if state == 49 {
+            cpu.set_priority_level(self.priority);
+        }
+        on_state(
+            &self
+                .trace(cpu.pc, state, rtl)
+                .bus(Gate::Psr, psr)
+                .latches(if state == 49 {
+                    &[Latch::Vector, Latch::Mdr, Latch::Priv, Latch::Priority]
+                } else {
+                    &[Latch::Vector, Latch::Mdr, Latch::Priv]
+                }),
+        );
+        if get_bits::<15, 15>(psr) == 1 {
+            45
+        } else {
+            37
+        }
+    }
+
+    fn exception<Input: KeySource, Output: Write>(
+        &mut self,
+        cpu: &mut Cpu<Input, Output>,
+        on_state: &mut dyn FnMut(&MicroStep),
+        state: u8,
+        exception: Exception,
+    ) -> Option<u8> {
+        if cpu.check_delivery(exception.vector()).is_err() {
+            // Let the interpreter deal with stopping or recovering, which isn't part of the state machine
+            cpu.raise(exception);
+            return None;
+        }
+        self.vector = INTERRUPT_VECTOR_TABLE | exception.vector() as u16;
+        cpu.event = StepEvent::Exception { cause: exception };
+        Some(self.enter_supervisor_mode(
+            cpu,
+            on_state,
+            state,
+            "Table<-x01, Vector<-exception, MDR<-PSR, PSR[15]<-0, [PSR[15]]",
+        ))
+    }
+
+    /// Accept an interrupt, running state 49
+    fn interrupt<Input: KeySource, Output: Write>(
+        &mut self,
+        cpu: &mut Cpu<Input, Output>,
+        on_state: &mut dyn FnMut(&MicroStep),
+        vector: u8,
+        priority: u16,
+    ) -> Option<u8> {
+        cpu.pending_interrupt = None;
+        if cpu.check_delivery(vector).is_err() {
+            cpu.handle_interrupt(vector, priority);
+            return None;
+        }
+        self.vector = INTERRUPT_VECTOR_TABLE | vector as u16;
+        self.priority = priority;
+        cpu.event = StepEvent::InterruptAccepted { vector, priority };
+        Some(self.enter_supervisor_mode(
+            cpu,
+            on_state,
+            49,
+            "Table<-x01, Vector<-INTV, MDR<-PSR, PSR[15]<-0, PSR[10:8]<-Priority, [PSR[15]]",
+        ))
+    }
+}
+
+fn offset9(instruction: u16) -> u16 {
+    sign_extend::<9>(get_bits::<0, 8>(instruction) as i16) as u16
+}
+
+fn offset6(instruction: u16) -> u16 {
+    sign_extend::<6>(get_bits::<0, 5>(instruction) as i16) as u16
+}
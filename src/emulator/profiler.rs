@@ -0,0 +1,202 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use super::call_stack::{CallStack, CallStep, FrameKind};
+use super::{Observer, StepEvent, StepRecord};
+use crate::bit_twiddling::get_bits;
+use crate::debug_info::DebugInfo;
+use crate::opcode::Opcode;
+
+/// An observer that counts executed instructions by address and opcode, along with calls to each subroutine and trap.
+///
+/// It also follows JSR/RET (and traps, exceptions, and interrupts) to attribute every instruction to the call stack it
+/// ran under, for [flame graphs](https://github.com/brendangregg/FlameGraph).
+#[derive(Default)]
+pub struct Profiler {
+    instructions: u64,
+    by_address: HashMap<u16, u64>,
+    by_opcode: [u64; 16],
+    calls: HashMap<u16, u64>,
+    traps: HashMap<u8, u64>,
+    /// Entry address of every routine on the call stack
+    stack: CallStack<u16>,
+    /// Instructions executed under each call stack
+    stacks: HashMap<Vec<u16>, u64>,
+}
+
+/// Sort counts from most to least, breaking ties by key.
+fn sorted<K: Ord + Copy>(counts: impl IntoIterator<Item = (K, u64)>) -> Vec<(K, u64)> {
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by_key(|&(key, count)| (Reverse(count), key));
+    counts
+}
+
+/// The name of a routine: its label if it has one, or else its address
+fn routine_name(debug_info: &DebugInfo, addr: u16) -> String {
+    match debug_info.label(addr) {
+        Some(label) => label.to_string(),
+        None => format!("x{:04X}", addr),
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// The total number of instructions executed
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// How many times the instruction at `addr` was executed
+    pub fn count_at(&self, addr: u16) -> u64 {
+        self.by_address.get(&addr).copied().unwrap_or(0)
+    }
+
+    /// How many instructions with the given opcode were executed
+    pub fn opcode_count(&self, opcode: Opcode) -> u64 {
+        self.by_opcode[opcode.to_int() as usize]
+    }
+
+    /// How many times JSR or JSRR called the subroutine at `addr`
+    pub fn calls_to(&self, addr: u16) -> u64 {
+        self.calls.get(&addr).copied().unwrap_or(0)
+    }
+
+    /// How many times a TRAP went through `vector`
+    pub fn trap_calls(&self, vector: u8) -> u64 {
+        self.traps.get(&vector).copied().unwrap_or(0)
+    }
+
+    /// Instruction counts for each routine, from most to least. A routine runs from one label to the next; instructions
+    /// before the first label are counted under their own address.
+    pub fn routine_counts(&self, debug_info: &DebugInfo) -> Vec<(String, u64)> {
+        let mut routines = HashMap::<String, u64>::new();
+        for (&addr, &count) in &self.by_address {
+            let name = match debug_info.enclosing_label(addr) {
+                Some((_, label)) => label.to_string(),
+                None => format!("x{:04X}", addr),
+            };
+            *routines.entry(name).or_default() += count;
+        }
+        let mut routines = routines.into_iter().collect::<Vec<_>>();
+        routines.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        routines
+    }
+
+    /// Write a human-readable report, naming routines and giving source lines from `debug_info`.
+    pub fn write_report<W: Write>(&self, debug_info: &DebugInfo, mut writer: W) -> io::Result<()> {
+        let total = self.instructions.max(1) as f64;
+        let percent = |count: u64| 100.0 * count as f64 / total;
+        writeln!(writer, "{} instructions executed", self.instructions)?;
+
+        writeln!(writer, "\nBy routine:")?;
+        for (name, count) in self.routine_counts(debug_info) {
+            writeln!(writer, "{:>12} {:>6.2}%  {}", count, percent(count), name)?;
+        }
+
+        writeln!(writer, "\nBy opcode:")?;
+        for (opcode, count) in sorted((0..16).map(|opcode| (opcode, self.by_opcode[opcode]))) {
+            if count > 0 {
+                writeln!(
+                    writer,
+                    "{:>12} {:>6.2}%  {}",
+                    count,
+                    percent(count),
+                    Opcode::from_int(opcode as u8).name()
+                )?;
+            }
+        }
+
+        writeln!(writer, "\nSubroutine calls:")?;
+        for (addr, count) in sorted(self.calls.iter().map(|(&addr, &count)| (addr, count))) {
+            writeln!(writer, "{:>12}  {}", count, routine_name(debug_info, addr))?;
+        }
+
+        writeln!(writer, "\nTrap calls:")?;
+        for (vector, count) in sorted(self.traps.iter().map(|(&vector, &count)| (vector, count))) {
+            writeln!(writer, "{:>12}  TRAP x{:02X}", count, vector)?;
+        }
+
+        writeln!(writer, "\nBy address:")?;
+        for (addr, count) in sorted(self.by_address.iter().map(|(&addr, &count)| (addr, count))) {
+            write!(
+                writer,
+                "{:>12} {:>6.2}%  x{:04X}",
+                count,
+                percent(count),
+                addr
+            )?;
+            if let Some((start, label)) = debug_info.enclosing_label(addr) {
+                write!(writer, " {}+{}", label, addr - start)?;
+            }
+            if let Some(line) = debug_info.line(addr) {
+                write!(writer, " (line {})", line)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write instruction counts for each call stack in the folded format taken by `flamegraph.pl` and `inferno`, one
+    /// stack per line: routine names separated by semicolons, outermost first, then the count.
+    pub fn write_folded<W: Write>(&self, debug_info: &DebugInfo, mut writer: W) -> io::Result<()> {
+        let mut stacks = self
+            .stacks
+            .iter()
+            .map(|(stack, &count)| {
+                let names = stack
+                    .iter()
+                    .map(|&addr| routine_name(debug_info, addr))
+                    .collect::<Vec<_>>();
+                (names.join(";"), count)
+            })
+            .collect::<Vec<_>>();
+        stacks.sort();
+        for (stack, count) in stacks {
+            writeln!(writer, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl Observer for Profiler {
+    fn on_step(&mut self, record: &StepRecord) {
+        if self.stack.is_empty() {
+            self.stack.call(FrameKind::Root, record.pc);
+        }
+
+        if let Some(instruction) = record.instruction {
+            self.instructions += 1;
+            *self.by_address.entry(record.pc).or_default() += 1;
+            self.by_opcode[get_bits::<12, 15>(instruction) as usize] += 1;
+            match self.stacks.get_mut(self.stack.frames()) {
+                Some(count) => *count += 1,
+                None => {
+                    self.stacks.insert(self.stack.frames().to_vec(), 1);
+                }
+            }
+        }
+
+        // Natively serviced traps are counted too, even though they don't go on the call stack
+        if let StepEvent::TrapEntered { vector } = record.event {
+            *self.traps.entry(vector).or_default() += 1;
+        }
+        match CallStep::of(record) {
+            Some(CallStep::Call(kind)) => {
+                if kind == FrameKind::Subroutine {
+                    *self.calls.entry(record.next_pc).or_default() += 1;
+                }
+                self.stack.call(kind, record.next_pc);
+            }
+            // A RET with nothing to return from is left for the call checker to report
+            Some(CallStep::Return) => {
+                self.stack.ret();
+            }
+            Some(CallStep::ReturnFromInterrupt) => self.stack.rti(),
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt::{self, Display};
+use std::ops::RangeInclusive;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::debug_info::parse_address;
+
+/// The ways a program can access memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    /// Fetching an instruction
+    Execute,
+}
+
+/// What user mode code may do with a region of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const NONE: Permissions = Permissions {
+        read: false,
+        write: false,
+        execute: false,
+    };
+    pub const ALL: Permissions = Permissions {
+        read: true,
+        write: true,
+        execute: true,
+    };
+
+    pub const fn allows(self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
+    }
+
+    /// Parse permissions in the style of `ls -l`, e.g. `rw-` or `r-x`.
+    fn parse(string: &str) -> Result<Self> {
+        match string.as_bytes() {
+            &[read, write, execute]
+                if matches!(read, b'r' | b'-')
+                    && matches!(write, b'w' | b'-')
+                    && matches!(execute, b'x' | b'-') =>
+            {
+                Ok(Permissions {
+                    read: read == b'r',
+                    write: write == b'w',
+                    execute: execute == b'x',
+                })
+            }
+            _ => Err(anyhow!(
+                "Invalid permissions {:?} (expected something like \"rw-\")",
+                string
+            )),
+        }
+    }
+}
+
+impl Display for Permissions {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |allowed, char| if allowed { char } else { '-' };
+        write!(
+            formatter,
+            "{}{}{}",
+            flag(self.read, 'r'),
+            flag(self.write, 'w'),
+            flag(self.execute, 'x')
+        )
+    }
+}
+
+/// Which memory user mode code may access, and how. Supervisor mode can always access everything.
+///
+/// The map is a list of address ranges with permissions. Where ranges overlap, the one added last wins, so a general
+/// region can be carved up by more specific ones added after it. Memory outside every region is off-limits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtectionMap {
+    regions: Vec<(RangeInclusive<u16>, Permissions)>,
+}
+
+impl ProtectionMap {
+    /// A map that gives user mode no access to anything.
+    pub fn empty() -> Self {
+        ProtectionMap {
+            regions: Vec::new(),
+        }
+    }
+
+    /// Give user mode the given permissions over a range of addresses, overriding any earlier regions that overlap it.
+    pub fn add_region(&mut self, range: RangeInclusive<u16>, permissions: Permissions) {
+        self.regions.push((range, permissions));
+    }
+
+    pub fn permissions(&self, addr: u16) -> Permissions {
+        self.regions
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&addr))
+            .map_or(Permissions::NONE, |&(_, permissions)| permissions)
+    }
+
+    /// Parse a protection map from a config file. Each line gives an inclusive address range and its permissions:
+    ///
+    /// ```text
+    /// # User space, with a write-protected code segment and execute-never data
+    /// x3000-xFDFF rw-
+    /// x3000-x3FFF r-x
+    /// ```
+    ///
+    /// Later lines override earlier ones. Blank lines and anything after a `#` are ignored.
+    pub fn parse(config: &str) -> Result<Self> {
+        let mut map = ProtectionMap::empty();
+        for (line_number, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            map.parse_line(line)
+                .with_context(|| format!("Line {} of protection map", line_number + 1))?;
+        }
+        Ok(map)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<()> {
+        let (range, permissions) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| anyhow!("Expected an address range followed by permissions"))?;
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_address(start)?, parse_address(end)?),
+            // A single address
+            None => (parse_address(range)?, parse_address(range)?),
+        };
+        if start > end {
+            return Err(anyhow!("Range {} ends before it starts", range));
+        }
+        self.add_region(start..=end, Permissions::parse(permissions.trim())?);
+        Ok(())
+    }
+}
+
+/// The standard layout: user mode may use x3000-xFDFF however it likes, but can't touch the OS or device registers.
+impl Default for ProtectionMap {
+    fn default() -> Self {
+        let mut map = ProtectionMap::empty();
+        map.add_region(0x3000..=0xFDFF, Permissions::ALL);
+        map
+    }
+}
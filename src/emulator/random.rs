@@ -0,0 +1,53 @@
+use std::cell::Cell;
+use std::io::Write;
+
+use super::{Cpu, KeySource};
+
+/// What the generator starts from unless seeded, so runs are repeatable by default
+const DEFAULT_SEED: u64 = 0x4C43_3321;
+
+/// A pseudo-random number generator behind RNG (xFE18). Each read gives the next 16-bit number, and writing a value
+/// seeds the generator with it. It's xorshift64*, which is plenty for games and simulations but not for anything
+/// needing real unpredictability.
+#[derive(Clone)]
+pub(super) struct Random {
+    state: Cell<u64>,
+}
+
+impl Random {
+    pub fn new() -> Self {
+        let mut random = Random {
+            state: Cell::new(0),
+        };
+        random.seed(DEFAULT_SEED);
+        random
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        // Scramble the seed (splitmix64's finalizer) so that nearby seeds give unrelated sequences
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+        // xorshift gets stuck at 0
+        self.state
+            .set(if state == 0 { DEFAULT_SEED } else { state });
+    }
+
+    pub fn read_rng(&self) -> u16 {
+        let mut state = self.state.get();
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        self.state.set(state);
+        (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 48) as u16
+    }
+}
+
+impl<Input: KeySource, Output: Write> Cpu<Input, Output> {
+    /// Restart the sequence of numbers read from RNG (xFE18) from `seed`. The same seed always gives the same numbers;
+    /// without one, a fixed default seed is used.
+    pub fn seed_random(&mut self, seed: u64) {
+        self.memory.random.seed(seed);
+    }
+}
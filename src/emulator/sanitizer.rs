@@ -0,0 +1,241 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+use super::{Cpu, EmulatorError, KeySource, MemRegisters, TrapRoutine, WordState};
+use crate::bit_twiddling::get_bits;
+use crate::opcode::Opcode;
+
+/// What to do when one of the sanitizer's checks finds something.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SanitizerMode {
+    /// Don't check
+    #[default]
+    Off,
+    /// Record a diagnostic, once per instruction and location, and carry on
+    Warn,
+    /// Stop with an error before the instruction executes
+    Stop,
+}
+
+/// What the sanitizer found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    /// A register read before it was ever written
+    UninitializedRegister(u8),
+    /// A memory word read before it was ever loaded or written
+    UninitializedMemory(u16),
+    /// An instruction fetched from a word reserved as data by `.FILL`, `.BLKW`, or `.STRINGZ`
+    ExecutedData,
+    /// An instruction fetched from a word the program overwrote after it was loaded
+    ExecutedModifiedCode,
+    /// A write to DDR without waiting for DSR to say the display was ready
+    DisplayNotReady,
+}
+
+/// Something suspicious done by the instruction at `pc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    /// Address of the instruction
+    pub pc: u16,
+    pub kind: DiagnosticKind,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            DiagnosticKind::UninitializedRegister(reg) => write!(
+                formatter,
+                "read of uninitialized R{} by instruction at {:#06x}",
+                reg, self.pc
+            ),
+            DiagnosticKind::UninitializedMemory(addr) => write!(
+                formatter,
+                "read of uninitialized memory at {:#06x} by instruction at {:#06x}",
+                addr, self.pc
+            ),
+            DiagnosticKind::ExecutedData => {
+                write!(formatter, "executed data at {:#06x}", self.pc)
+            }
+            DiagnosticKind::ExecutedModifiedCode => write!(
+                formatter,
+                "executed code at {:#06x} that was modified after it was loaded",
+                self.pc
+            ),
+            DiagnosticKind::DisplayNotReady => write!(
+                formatter,
+                "write to DDR by instruction at {:#06x} while the display wasn't ready",
+                self.pc
+            ),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Sanitizer {
+    /// Checks for reads of uninitialized registers and memory
+    reads: SanitizerMode,
+    /// Checks for fetches from data and modified code
+    execution: SanitizerMode,
+    /// Checks for writes to the display while it's busy
+    display: SanitizerMode,
+    /// Diagnostics the caller hasn't taken yet
+    diagnostics: Vec<Diagnostic>,
+    /// Everything ever reported, so that a loop over the same bug doesn't report it over and over
+    reported: HashSet<Diagnostic>,
+}
+
+/// The registers an instruction reads, as a bitmask.
+fn registers_read(instruction: u16) -> u8 {
+    let hi = 1 << get_bits::<9, 11>(instruction);
+    let lo = 1 << get_bits::<6, 8>(instruction);
+    match Opcode::from_int(get_bits::<12, 15>(instruction) as u8) {
+        Opcode::Add | Opcode::And => {
+            if get_bits::<5, 5>(instruction) == 1 {
+                lo
+            } else {
+                lo | 1 << get_bits::<0, 2>(instruction)
+            }
+        }
+        Opcode::Not | Opcode::Ldr | Opcode::Jmp => lo,
+        Opcode::Jsr if get_bits::<11, 11>(instruction) == 0 => lo,
+        Opcode::St | Opcode::Sti => hi,
+        Opcode::Str => hi | lo,
+        Opcode::Rti => 1 << 6,
+        _ => 0,
+    }
+}
+
+impl<Input: KeySource, Output: Write> Cpu<Input, Output> {
+    /// Choose whether to check for reads of uninitialized registers and memory. Memory counts as initialized once a
+    /// program is loaded into it or it's written; registers count as initialized once they're written.
+    pub fn set_sanitizer(&mut self, mode: SanitizerMode) {
+        self.sanitizer.reads = mode;
+    }
+
+    /// Choose whether to check where instructions are fetched from: words marked as data with
+    /// [`mark_data`](Self::mark_data), and words the program wrote over after they were loaded (self-modifying code).
+    pub fn set_execution_checker(&mut self, mode: SanitizerMode) {
+        self.sanitizer.execution = mode;
+    }
+
+    /// Choose whether to check that the program waits for the display to be ready (DSR bit 15 set) before writing to
+    /// DDR. This only finds anything if the display is given a delay with
+    /// [`set_display_delay`](Self::set_display_delay).
+    pub fn set_display_checker(&mut self, mode: SanitizerMode) {
+        self.sanitizer.display = mode;
+    }
+
+    /// Mark words as reserved for data, e.g. from a program's [`DebugInfo`](crate::debug_info::DebugInfo), for the
+    /// execution checker.
+    pub fn mark_data(&mut self, range: RangeInclusive<u16>) {
+        for addr in range {
+            self.memory.data[addr as usize] = true;
+        }
+    }
+
+    /// Take the diagnostics reported in `SanitizerMode::Warn` since the last call.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.sanitizer.diagnostics)
+    }
+
+    /// Set a register, marking it as initialized.
+    pub(super) fn set_register(&mut self, reg: usize, value: u16) {
+        self.registers[reg] = value;
+        self.registers_initialized |= 1 << reg;
+    }
+
+    /// Report something found in the instruction currently executing. Returns `None` if the sanitizer is stopping, in
+    /// which case the instruction must stop without any further effects.
+    fn report(&mut self, mode: SanitizerMode, kind: DiagnosticKind) -> Option<()> {
+        let diagnostic = Diagnostic {
+            pc: self.pc.wrapping_sub(1),
+            kind,
+        };
+        match mode {
+            SanitizerMode::Off => {}
+            SanitizerMode::Warn => {
+                if self.sanitizer.reported.insert(diagnostic) {
+                    self.sanitizer.diagnostics.push(diagnostic);
+                }
+            }
+            SanitizerMode::Stop => {
+                self.pc = diagnostic.pc;
+                self.pending_error = Some(EmulatorError::Sanitizer(diagnostic));
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    /// Whether every instruction has to be checked before it executes
+    pub(super) fn checks_instructions(&self) -> bool {
+        self.sanitizer.reads != SanitizerMode::Off || self.sanitizer.execution != SanitizerMode::Off
+    }
+
+    /// Check an instruction that was just fetched from `addr`, before it executes.
+    pub(super) fn sanitize_instruction(&mut self, addr: u16, instruction: u16) -> Option<()> {
+        self.sanitize_fetch(addr)?;
+        self.sanitize_registers(instruction)
+    }
+
+    fn sanitize_fetch(&mut self, addr: u16) -> Option<()> {
+        let mode = self.sanitizer.execution;
+        if mode == SanitizerMode::Off {
+            return Some(());
+        }
+        if self.memory.data[addr as usize] {
+            self.report(mode, DiagnosticKind::ExecutedData)
+        } else if self.memory.state[addr as usize] == WordState::Written {
+            self.report(mode, DiagnosticKind::ExecutedModifiedCode)
+        } else {
+            Some(())
+        }
+    }
+
+    /// Check the registers an instruction is about to read.
+    fn sanitize_registers(&mut self, instruction: u16) -> Option<()> {
+        let mode = self.sanitizer.reads;
+        if mode == SanitizerMode::Off {
+            return Some(());
+        }
+
+        let mut read = registers_read(instruction);
+        // Natively serviced traps read their arguments directly
+        if let Some(TrapRoutine::Out | TrapRoutine::Puts | TrapRoutine::Putsp) =
+            self.hle_routine(instruction)
+        {
+            read |= 1;
+        }
+
+        let uninitialized = read & !self.registers_initialized;
+        for reg in 0..8 {
+            if uninitialized & (1 << reg) != 0 {
+                self.report(mode, DiagnosticKind::UninitializedRegister(reg))?;
+            }
+        }
+        Some(())
+    }
+
+    /// Check a memory word the current instruction is about to read.
+    pub(super) fn sanitize_memory(&mut self, addr: u16) -> Option<()> {
+        let mode = self.sanitizer.reads;
+        if mode == SanitizerMode::Off || self.memory.is_initialized(addr) {
+            return Some(());
+        }
+        self.report(mode, DiagnosticKind::UninitializedMemory(addr))
+    }
+
+    /// Check a memory word the current instruction is about to write.
+    pub(super) fn sanitize_store(&mut self, addr: u16) -> Option<()> {
+        let mode = self.sanitizer.display;
+        if mode == SanitizerMode::Off
+            || addr != MemRegisters::DDR
+            || self.memory.display.get_mut().ready(self.memory.cycles)
+        {
+            return Some(());
+        }
+        self.report(mode, DiagnosticKind::DisplayNotReady)
+    }
+}
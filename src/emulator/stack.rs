@@ -0,0 +1,188 @@
+use std::fmt::{self, Display};
+use std::io::Write;
+use std::ops::RangeInclusive;
+
+use super::{
+    Cpu, EmulatorError, Fault, FaultChain, KeySource, MemRegisters, StepEvent, INITIAL_SSP,
+};
+use crate::bit_twiddling::get_bits;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stack {
+    Supervisor,
+    User,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackFaultKind {
+    /// Pushed past the bottom of the stack's region (stacks grow downwards)
+    Overflow,
+    /// Popped past the top of the stack's region
+    Underflow,
+}
+
+/// A push or pop that would have left the stack's region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackFault {
+    pub stack: Stack,
+    pub kind: StackFaultKind,
+    /// Address of the instruction that moved the stack pointer, or for an interrupt, the instruction it arrived before
+    pub pc: u16,
+    /// The stack pointer the instruction would have left behind
+    pub sp: u16,
+}
+
+impl Display for StackFault {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{} stack {} at {:#06x} (R6 would be {:#06x})",
+            match self.stack {
+                Stack::Supervisor => "supervisor",
+                Stack::User => "user",
+            },
+            match self.kind {
+                StackFaultKind::Overflow => "overflow",
+                StackFaultKind::Underflow => "underflow",
+            },
+            self.pc,
+            self.sp
+        )
+    }
+}
+
+/// The regions of memory the supervisor and user stacks may occupy. A stack without bounds isn't checked.
+///
+/// The stack pointer may range from the start of the region (full) to one past its end (empty).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StackBounds {
+    pub supervisor: Option<RangeInclusive<u16>>,
+    pub user: Option<RangeInclusive<u16>>,
+}
+
+impl StackBounds {
+    fn get(&self, stack: Stack) -> Option<&RangeInclusive<u16>> {
+        match stack {
+            Stack::Supervisor => self.supervisor.as_ref(),
+            Stack::User => self.user.as_ref(),
+        }
+    }
+
+    /// Whether a stack may hold the word at `addr`.
+    pub fn contains(&self, stack: Stack, addr: u16) -> bool {
+        match self.get(stack) {
+            Some(bounds) => bounds.contains(&addr),
+            None => true,
+        }
+    }
+
+    /// Check a stack pointer moving from `from` to `sp`, returning how it left the stack's region if it did. Which way
+    /// it left is judged by which way it moved, so a push that wraps around from x0000 to xFFFF is still an overflow.
+    pub fn check(&self, stack: Stack, from: u16, sp: u16) -> Option<StackFaultKind> {
+        let bounds = self.get(stack)?;
+        if (*bounds.start() as u32..=*bounds.end() as u32 + 1).contains(&(sp as u32)) {
+            return None;
+        }
+        let moved_down = match (sp.wrapping_sub(from) as i16).signum() {
+            -1 => true,
+            1 => false,
+            // Not moved at all, so go by which side of the region it's on
+            _ => sp < *bounds.start(),
+        };
+        Some(if moved_down {
+            StackFaultKind::Overflow
+        } else {
+            StackFaultKind::Underflow
+        })
+    }
+}
+
+/// What to do when a stack leaves its bounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StackPolicy {
+    /// Stop the emulator with an error
+    #[default]
+    Error,
+    /// Deliver an exception through the given vector, on a fresh supervisor stack
+    Vector(u8),
+}
+
+impl<Input: KeySource, Output: Write> Cpu<Input, Output> {
+    /// Set the regions the stacks may occupy. Stacks are unbounded by default.
+    pub fn set_stack_bounds(&mut self, bounds: StackBounds) {
+        self.stack_bounds = bounds;
+    }
+
+    pub fn set_stack_policy(&mut self, policy: StackPolicy) {
+        self.stack_policy = policy;
+    }
+
+    /// The stack that R6 currently points into
+    fn current_stack(&self) -> Stack {
+        if get_bits::<15, 15>(self.memory.get(MemRegisters::PSR)) == 1 {
+            Stack::User
+        } else {
+            Stack::Supervisor
+        }
+    }
+
+    /// Check that an instruction can move a stack pointer from `from` to `sp`. If not, deal with it according to the
+    /// stack policy and return `None`, in which case the instruction must stop without any further effects.
+    pub(super) fn check_stack_pointer(&mut self, stack: Stack, from: u16, sp: u16) -> Option<()> {
+        match self.stack_bounds.check(stack, from, sp) {
+            None => Some(()),
+            Some(kind) => {
+                self.stack_fault(StackFault {
+                    stack,
+                    kind,
+                    pc: self.pc.wrapping_sub(1),
+                    sp,
+                });
+                None
+            }
+        }
+    }
+
+    /// Check a write to R6 by the program itself.
+    pub(super) fn check_program_stack_pointer(&mut self, sp: u16) -> Option<()> {
+        self.check_stack_pointer(self.current_stack(), self.registers[6], sp)
+    }
+
+    pub(super) fn stack_fault(&mut self, fault: StackFault) {
+        match self.stack_policy {
+            StackPolicy::Error => {
+                self.pc = fault.pc;
+                self.pending_error = Some(EmulatorError::StackFault(fault));
+            }
+            StackPolicy::Vector(vector) => {
+                // The old supervisor stack can't be trusted, so start the handler off with an empty one
+                let top = self
+                    .stack_bounds
+                    .supervisor
+                    .as_ref()
+                    .map_or(INITIAL_SSP, |bounds| bounds.end().wrapping_add(1));
+                let old_ssp = match self.current_stack() {
+                    Stack::User => std::mem::replace(&mut self.saved_ssp, top),
+                    Stack::Supervisor => std::mem::replace(&mut self.registers[6], top),
+                };
+
+                match self.check_delivery(vector) {
+                    Ok(()) => {
+                        self.handle_exception(vector);
+                        self.event = StepEvent::StackFault(fault);
+                    }
+                    Err(reason) => {
+                        match self.current_stack() {
+                            Stack::User => self.saved_ssp = old_ssp,
+                            Stack::Supervisor => self.registers[6] = old_ssp,
+                        }
+                        self.handle_nested_fault(FaultChain {
+                            pc: fault.pc,
+                            faults: vec![Fault { vector, reason }],
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
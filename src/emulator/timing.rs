@@ -0,0 +1,197 @@
+use std::cell::Cell;
+use std::io::Write;
+
+use anyhow::{anyhow, Context, Result};
+
+use super::{Cpu, KeySource, StepEvent};
+use crate::bit_twiddling::get_bits;
+use crate::opcode::Opcode;
+
+/// Cycles each memory access takes unless configured otherwise
+pub const DEFAULT_MEMORY_LATENCY: u32 = 5;
+
+/// Interrupt vector and priority level of the interval timer
+const TIMER_VECTOR: u8 = 0x81;
+const TIMER_PRIORITY: u16 = 4;
+
+/// How many clock cycles each instruction takes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimingModel {
+    /// Cycles for each opcode, including fetching and decoding it, indexed by opcode
+    cycles: [u32; 16],
+    /// Extra cycles for a BR that's taken
+    pub branch_taken: u32,
+    /// Cycles to enter an exception or interrupt handler: saving the PSR and PC and reading the vector table
+    pub handler_entry: u32,
+}
+
+impl TimingModel {
+    /// Count the states each instruction passes through in the textbook's state machine (appendix C of the third
+    /// edition), where each state takes one cycle except for memory accesses, which take `memory_latency` cycles.
+    pub fn textbook(memory_latency: u32) -> Self {
+        let memory = memory_latency;
+        // States 18, 33, 35, and 32, with 33 reading memory
+        let fetch = 3 + memory;
+        let mut cycles = [0; 16];
+        for opcode in 0..16u8 {
+            cycles[opcode as usize] = fetch
+                + match Opcode::from_int(opcode) {
+                    Opcode::Br
+                    | Opcode::Add
+                    | Opcode::And
+                    | Opcode::Not
+                    | Opcode::Lea
+                    | Opcode::Jmp
+                    | Opcode::Reserved => 1,
+                    Opcode::Jsr => 2,
+                    Opcode::Ld | Opcode::Ldr | Opcode::St | Opcode::Str => 2 + memory,
+                    Opcode::Ldi | Opcode::Sti => 3 + 2 * memory,
+                    // Pops the PC and PSR, checking privilege and switching stacks along the way
+                    Opcode::Rti => 7 + 2 * memory,
+                    // Pushes the PSR and PC and reads the trap vector table
+                    Opcode::Trap => 6 + 3 * memory,
+                };
+        }
+        TimingModel {
+            cycles,
+            branch_taken: 1,
+            handler_entry: 4 + 3 * memory,
+        }
+    }
+
+    /// Cycles taken by an instruction with the given opcode, not counting a taken branch or entering a handler
+    pub fn cycles(&self, opcode: Opcode) -> u32 {
+        self.cycles[opcode.to_int() as usize]
+    }
+
+    pub fn set_cycles(&mut self, opcode: Opcode, cycles: u32) {
+        self.cycles[opcode.to_int() as usize] = cycles;
+    }
+
+    /// Override cycle counts from a comma-separated list like `ADD=4,LDI=20`.
+    pub fn parse_overrides(&mut self, list: &str) -> Result<()> {
+        for entry in list.split(',') {
+            let (name, cycles) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected OPCODE=CYCLES, got {}", entry))?;
+            let cycles = cycles
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid cycle count for {}", name))?;
+            match name.trim() {
+                name if name.eq_ignore_ascii_case("taken") => self.branch_taken = cycles,
+                name if name.eq_ignore_ascii_case("handler") => self.handler_entry = cycles,
+                name => {
+                    let opcode = Opcode::from_name(name)
+                        .ok_or_else(|| anyhow!("Unknown opcode {}", name))?;
+                    self.set_cycles(opcode, cycles);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Cycles taken by a step that executed `instruction` (if any) and ended with `event`
+    fn step_cycles(&self, instruction: Option<u16>, event: &StepEvent) -> u64 {
+        let mut cycles = 0;
+        if let Some(instruction) = instruction {
+            cycles += self.cycles[get_bits::<12, 15>(instruction) as usize];
+            if let (Opcode::Br, StepEvent::BranchTaken { .. }) = (
+                Opcode::from_int(get_bits::<12, 15>(instruction) as u8),
+                event,
+            ) {
+                cycles += self.branch_taken;
+            }
+        }
+        if let StepEvent::Exception { .. }
+        | StepEvent::InterruptAccepted { .. }
+        | StepEvent::FaultRecovered(_)
+        | StepEvent::StackFault(_) = event
+        {
+            cycles += self.handler_entry;
+        }
+        cycles as u64
+    }
+}
+
+impl Default for TimingModel {
+    fn default() -> Self {
+        TimingModel::textbook(DEFAULT_MEMORY_LATENCY)
+    }
+}
+
+/// An interval timer. TMR (xFE08) has bit 15 set once the interval has elapsed, cleared by reading it, and bit 14
+/// enabling the timer's interrupt. TMI (xFE0A) holds the interval in cycles; writing it restarts the timer, and 0 stops
+/// it.
+#[derive(Default)]
+pub(super) struct Timer {
+    interval: u16,
+    interrupt_enabled: bool,
+    expired: Cell<bool>,
+    /// Cycle count at which the interval next elapses
+    deadline: u64,
+}
+
+impl Timer {
+    pub fn read_tmr(&self) -> u16 {
+        (self.expired.replace(false) as u16) << 15 | (self.interrupt_enabled as u16) << 14
+    }
+
+    pub fn write_tmr(&mut self, value: u16) {
+        self.interrupt_enabled = get_bits::<14, 14>(value) == 1;
+    }
+
+    pub fn read_tmi(&self) -> u16 {
+        self.interval
+    }
+
+    pub fn write_tmi(&mut self, value: u16, now: u64) {
+        self.interval = value;
+        self.expired.set(false);
+        self.deadline = now + value as u64;
+    }
+
+    /// The cycle count at which the interval next elapses, if the timer is running.
+    pub fn deadline(&self) -> Option<u64> {
+        if self.interval == 0 {
+            None
+        } else {
+            Some(self.deadline)
+        }
+    }
+
+    /// Bring the timer up to `now`, returning whether it wants to interrupt.
+    fn update(&mut self, now: u64) -> bool {
+        if self.interval == 0 || now < self.deadline {
+            return false;
+        }
+        // A long instruction can overshoot several short intervals, which only count once
+        let interval = self.interval as u64;
+        self.deadline += (now - self.deadline) / interval * interval + interval;
+        self.expired.set(true);
+        self.interrupt_enabled
+    }
+}
+
+impl<Input: KeySource, Output: Write> Cpu<Input, Output> {
+    /// Choose how many cycles each instruction takes. By default, this is
+    /// [`TimingModel::textbook(DEFAULT_MEMORY_LATENCY)`](TimingModel::textbook).
+    pub fn set_timing_model(&mut self, timing: TimingModel) {
+        self.timing = timing;
+        // Cached instructions know how long they take
+        self.clear_block_cache();
+    }
+
+    /// The number of clock cycles elapsed so far. Devices use this to keep time.
+    pub fn cycles(&self) -> u64 {
+        self.memory.cycles
+    }
+
+    /// Advance the clock past a step that executed `instruction` (if any), and let the timer catch up.
+    pub(super) fn advance_clock(&mut self, instruction: Option<u16>) {
+        self.memory.cycles += self.timing.step_cycles(instruction, &self.event);
+        if self.memory.timer.update(self.memory.cycles) {
+            self.request_interrupt(TIMER_VECTOR, TIMER_PRIORITY);
+        }
+    }
+}
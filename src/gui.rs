@@ -1,122 +1,213 @@
-#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
-
-fn create_display(
-    event_loop: &glutin::event_loop::EventLoop<()>,
-) -> (
-    glutin::WindowedContext<glutin::PossiblyCurrent>,
-    glow::Context,
-) {
-    let window_builder = glutin::window::WindowBuilder::new()
-        .with_resizable(true)
-        .with_inner_size(glutin::dpi::LogicalSize {
-            width: 800.0,
-            height: 600.0,
-        })
-        .with_title("egui_glow example");
-
-    let gl_window = unsafe {
-        glutin::ContextBuilder::new()
-            .with_depth_buffer(0)
-            .with_srgb(true)
-            .with_stencil_buffer(0)
-            .with_vsync(true)
-            .build_windowed(window_builder, event_loop)
-            .unwrap()
-            .make_current()
-            .unwrap()
-    };
-
-    let gl = unsafe { glow::Context::from_loader_function(|s| gl_window.get_proc_address(s)) };
-
-    unsafe {
-        use glow::HasContext as _;
-        gl.enable(glow::FRAMEBUFFER_SRGB);
-    }
-
-    (gl_window, gl)
-}
-
-pub fn main() {
-    let mut clear_color = [0.1, 0.1, 0.1];
-
-    let event_loop = glutin::event_loop::EventLoop::with_user_event();
-    let (gl_window, gl) = create_display(&event_loop);
-
-    let mut egui_glow = egui_glow::EguiGlow::new(&gl_window, &gl);
-
-    event_loop.run(move |event, _, control_flow| {
-        let mut redraw = || {
-            let mut quit = false;
-
-            let (needs_repaint, shapes) = egui_glow.run(gl_window.window(), |egui_ctx| {
-                egui::TopBottomPanel::top("menu_bar").show(egui_ctx, |ui| {
-                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
-                        ui.heading("alic3");
-                        ui.menu_button("File", |ui| {
-                            if ui.button("Quit").clicked() {
-                                quit = true;
-                            }
-                        })
-                    });
-                });
-
-                egui::CentralPanel::default().show(egui_ctx, |ui| {
-                    // TODO
-                });
-            });
-
-            *control_flow = if quit {
-                glutin::event_loop::ControlFlow::Exit
-            } else if needs_repaint {
-                gl_window.window().request_redraw();
-                glutin::event_loop::ControlFlow::Poll
-            } else {
-                glutin::event_loop::ControlFlow::Wait
-            };
-
-            {
-                /*unsafe {
-                    use glow::HasContext as _;
-                    gl.clear_color(clear_color[0], clear_color[1], clear_color[2], 1.0);
-                    gl.clear(glow::COLOR_BUFFER_BIT);
-                }
-
-                // draw things behind egui here*/
-
-                egui_glow.paint(&gl_window, &gl, shapes);
-
-                // draw things on top of egui here
-
-                gl_window.swap_buffers().unwrap();
-            }
-        };
-
-        match event {
-            // Platform-dependent event handlers to workaround a winit bug
-            // See: https://github.com/rust-windowing/winit/issues/987
-            // See: https://github.com/rust-windowing/winit/issues/1619
-            glutin::event::Event::RedrawEventsCleared if cfg!(windows) => redraw(),
-            glutin::event::Event::RedrawRequested(_) if !cfg!(windows) => redraw(),
-
-            glutin::event::Event::WindowEvent { event, .. } => {
-                use glutin::event::WindowEvent;
-                if matches!(event, WindowEvent::CloseRequested | WindowEvent::Destroyed) {
-                    *control_flow = glutin::event_loop::ControlFlow::Exit;
-                }
-
-                if let glutin::event::WindowEvent::Resized(physical_size) = event {
-                    gl_window.resize(physical_size);
-                }
-
-                egui_glow.on_event(&event);
-
-                gl_window.window().request_redraw();
-            }
-            glutin::event::Event::LoopDestroyed => {
-                egui_glow.destroy(&gl);
-            }
-
-            _ => (),
-        }
-    });
-}
+#![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
+
+use std::fs::File;
+use std::io::{stdin, stdout, Stdout};
+use std::process::exit;
+
+use anyhow::anyhow;
+
+use crate::emulator::{Cpu, DisplayMode, ThreadedInput, FRAMEBUFFER_HEIGHT, FRAMEBUFFER_WIDTH};
+
+/// Instructions to run between redraws
+const INSTRUCTIONS_PER_FRAME: u32 = 50_000;
+/// How many screen pixels each framebuffer pixel takes up
+const SCALE: f32 = 4.0;
+/// User texture holding the framebuffer
+const FRAMEBUFFER_TEXTURE: u64 = 0;
+
+/// Load the OS (if given) and program named on the command line, with the framebuffer enabled. The keyboard and
+/// display are the terminal the GUI was started from.
+fn load_cpu() -> anyhow::Result<Option<Cpu<ThreadedInput, Stdout>>> {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    let mut cpu = Cpu::new(ThreadedInput::new(stdin()), stdout());
+    cpu.set_display_mode(DisplayMode::Verbatim);
+    cpu.set_framebuffer_enabled(true);
+    cpu.pc = match paths.as_slice() {
+        [] => return Ok(None),
+        [program] => cpu.load_program(File::open(program)?)?,
+        [os, program] => {
+            cpu.load_program(File::open(os)?)?;
+            cpu.load_program(File::open(program)?)?;
+            // The OS starts at x0200 and jumps to the user program from there
+            0x0200
+        }
+        _ => return Err(anyhow!("Usage: gui [[OS] PROGRAM]")),
+    };
+    Ok(Some(cpu))
+}
+
+/// Copy the framebuffer into its texture.
+fn upload_frame(
+    painter: &mut egui_glow::Painter,
+    gl: &glow::Context,
+    cpu: &Cpu<ThreadedInput, Stdout>,
+) {
+    let rgba: Vec<u8> = cpu
+        .frame()
+        .to_rgb()
+        .chunks_exact(3)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 0xFF])
+        .collect();
+    let image = epi::Image::from_rgba_unmultiplied([FRAMEBUFFER_WIDTH, FRAMEBUFFER_HEIGHT], &rgba);
+    painter.set_texture(gl, FRAMEBUFFER_TEXTURE, &image);
+}
+
+fn create_display(
+    event_loop: &glutin::event_loop::EventLoop<()>,
+) -> (
+    glutin::WindowedContext<glutin::PossiblyCurrent>,
+    glow::Context,
+) {
+    let window_builder = glutin::window::WindowBuilder::new()
+        .with_resizable(true)
+        .with_inner_size(glutin::dpi::LogicalSize {
+            width: 800.0,
+            height: 600.0,
+        })
+        .with_title("egui_glow example");
+
+    let gl_window = unsafe {
+        glutin::ContextBuilder::new()
+            .with_depth_buffer(0)
+            .with_srgb(true)
+            .with_stencil_buffer(0)
+            .with_vsync(true)
+            .build_windowed(window_builder, event_loop)
+            .unwrap()
+            .make_current()
+            .unwrap()
+    };
+
+    let gl = unsafe { glow::Context::from_loader_function(|s| gl_window.get_proc_address(s)) };
+
+    unsafe {
+        use glow::HasContext as _;
+        gl.enable(glow::FRAMEBUFFER_SRGB);
+    }
+
+    (gl_window, gl)
+}
+
+pub fn main() {
+    let mut clear_color = [0.1, 0.1, 0.1];
+
+    let mut cpu = match load_cpu() {
+        Ok(cpu) => cpu,
+        Err(error) => {
+            eprintln!("{:#}", error);
+            exit(1);
+        }
+    };
+    // What stopped the program, once it stops
+    let mut stopped: Option<String> = None;
+
+    let event_loop = glutin::event_loop::EventLoop::with_user_event();
+    let (gl_window, gl) = create_display(&event_loop);
+
+    let mut egui_glow = egui_glow::EguiGlow::new(&gl_window, &gl);
+
+    event_loop.run(move |event, _, control_flow| {
+        let mut redraw = || {
+            let mut quit = false;
+
+            if let Some(cpu) = &mut cpu {
+                for _ in 0..INSTRUCTIONS_PER_FRAME {
+                    if stopped.is_some() {
+                        break;
+                    }
+                    if let Err(error) = cpu.step() {
+                        stopped = Some(error.to_string());
+                    } else if cpu.should_halt() {
+                        stopped = Some("Halted".to_string());
+                    }
+                }
+                cpu.flush_display();
+                if cpu.take_frame_changed() {
+                    upload_frame(&mut egui_glow.painter, &gl, cpu);
+                }
+            }
+            let running = cpu.is_some() && stopped.is_none();
+
+            let (needs_repaint, shapes) = egui_glow.run(gl_window.window(), |egui_ctx| {
+                egui::TopBottomPanel::top("menu_bar").show(egui_ctx, |ui| {
+                    ui.with_layout(egui::Layout::left_to_right(), |ui| {
+                        ui.heading("alic3");
+                        ui.menu_button("File", |ui| {
+                            if ui.button("Quit").clicked() {
+                                quit = true;
+                            }
+                        })
+                    });
+                });
+
+                egui::CentralPanel::default().show(egui_ctx, |ui| {
+                    if cpu.is_none() {
+                        ui.label("Give an OS and program to run on the command line");
+                        return;
+                    }
+                    ui.image(
+                        egui::TextureId::User(FRAMEBUFFER_TEXTURE),
+                        egui::vec2(
+                            FRAMEBUFFER_WIDTH as f32 * SCALE,
+                            FRAMEBUFFER_HEIGHT as f32 * SCALE,
+                        ),
+                    );
+                    ui.label(stopped.as_deref().unwrap_or("Running"));
+                });
+            });
+
+            *control_flow = if quit {
+                glutin::event_loop::ControlFlow::Exit
+            } else if needs_repaint || running {
+                gl_window.window().request_redraw();
+                glutin::event_loop::ControlFlow::Poll
+            } else {
+                glutin::event_loop::ControlFlow::Wait
+            };
+
+            {
+                /*unsafe {
+                    use glow::HasContext as _;
+                    gl.clear_color(clear_color[0], clear_color[1], clear_color[2], 1.0);
+                    gl.clear(glow::COLOR_BUFFER_BIT);
+                }
+
+                // draw things behind egui here*/
+
+                egui_glow.paint(&gl_window, &gl, shapes);
+
+                // draw things on top of egui here
+
+                gl_window.swap_buffers().unwrap();
+            }
+        };
+
+        match event {
+            // Platform-dependent event handlers to workaround a winit bug
+            // See: https://github.com/rust-windowing/winit/issues/987
+            // See: https://github.com/rust-windowing/winit/issues/1619
+            glutin::event::Event::RedrawEventsCleared if cfg!(windows) => redraw(),
+            glutin::event::Event::RedrawRequested(_) if !cfg!(windows) => redraw(),
+
+            glutin::event::Event::WindowEvent { event, .. } => {
+                use glutin::event::WindowEvent;
+                if matches!(event, WindowEvent::CloseRequested | WindowEvent::Destroyed) {
+                    *control_flow = glutin::event_loop::ControlFlow::Exit;
+                }
+
+                if let glutin::event::WindowEvent::Resized(physical_size) = event {
+                    gl_window.resize(physical_size);
+                }
+
+                egui_glow.on_event(&event);
+
+                gl_window.window().request_redraw();
+            }
+            glutin::event::Event::LoopDestroyed => {
+                egui_glow.destroy(&gl);
+            }
+
+            _ => (),
+        }
+    });
+}
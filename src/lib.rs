@@ -1,6 +1,7 @@
 pub mod asm_parser;
 pub mod assembler;
 pub mod bit_twiddling;
+pub mod debug_info;
 pub mod emulator;
 pub mod opcode;
 #[cfg(feature = "gui")]
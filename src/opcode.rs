@@ -62,4 +62,33 @@ impl Opcode {
             Self::Trap => 15,
         }
     }
+
+    /// The opcode's mnemonic, e.g. `"LDI"`
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Br => "BR",
+            Self::Add => "ADD",
+            Self::Ld => "LD",
+            Self::St => "ST",
+            Self::Jsr => "JSR",
+            Self::And => "AND",
+            Self::Ldr => "LDR",
+            Self::Str => "STR",
+            Self::Rti => "RTI",
+            Self::Not => "NOT",
+            Self::Ldi => "LDI",
+            Self::Sti => "STI",
+            Self::Jmp => "JMP",
+            Self::Reserved => "reserved",
+            Self::Lea => "LEA",
+            Self::Trap => "TRAP",
+        }
+    }
+
+    /// Look up an opcode by its mnemonic, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        (0..16)
+            .map(Self::from_int)
+            .find(|opcode| opcode.name().eq_ignore_ascii_case(name))
+    }
 }
@@ -0,0 +1,299 @@
+mod common;
+
+use alic3::emulator::{EmulatorError, Exception, Permissions, ProtectionMap, StepEvent};
+use common::*;
+
+// Drops into user mode at x3000 with the supervisor stack just below it
+const SETUP: &str = "
+.ORIG x0200
+LD R6, SSP
+LD R0, USER
+JMPT R0
+SSP .FILL x3000
+USER .FILL x3000
+.END
+";
+
+const VECTOR_TABLE: &str = "
+.ORIG x0102
+.FILL x5000
+.END
+";
+
+const ACV_HANDLER: &str = "
+.ORIG x5000
+HALT
+.END
+";
+
+/// Load a user program at x3000 and run until it's about to execute in user mode.
+fn user_mode(program: &str) -> TestCpu {
+    user_mode_with(program, ProtectionMap::default())
+}
+
+fn user_mode_with(program: &str, map: ProtectionMap) -> TestCpu {
+    let mut cpu = cpu_with(&[SETUP, program, VECTOR_TABLE, ACV_HANDLER]);
+    cpu.set_jmpt_enabled(true);
+    cpu.set_protection_map(map);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.pc, 0x3000);
+    assert_eq!(cpu.psr() >> 15, 1);
+    cpu
+}
+
+/// Step, expecting the instruction at `pc` to raise an access control violation and enter the handler.
+fn expect_acv(cpu: &mut TestCpu, pc: u16) {
+    assert_eq!(
+        cpu.step(),
+        Ok(StepEvent::Exception {
+            cause: Exception::AccessControlViolation
+        })
+    );
+    assert_eq!(cpu.pc, 0x5000);
+    assert_eq!(cpu.psr() >> 15, 0);
+    // The pushed PC points after the faulting instruction, and the pushed PSR was in user mode
+    assert_eq!(cpu.registers()[6], 0x2FFE);
+    assert_eq!(cpu.peek(0x2FFE), pc.wrapping_add(1));
+    assert_eq!(cpu.peek(0x2FFF) >> 15, 1);
+}
+
+#[test]
+fn ld_from_system_space() {
+    let mut cpu = user_mode(
+        "
+        .ORIG x3000
+        LD R0, #-2
+        .END
+        ",
+    );
+    expect_acv(&mut cpu, 0x3000);
+}
+
+#[test]
+fn ldr_from_device_registers() {
+    let mut cpu = user_mode(
+        "
+        .ORIG x3000
+        LD R1, KBSR
+        LDR R2, R1, #0
+        KBSR .FILL xFE00
+        .END
+        ",
+    );
+    run(&mut cpu, 1);
+    expect_acv(&mut cpu, 0x3001);
+    assert_eq!(cpu.registers()[2], 0);
+}
+
+#[test]
+fn ldi_checks_the_indirect_address() {
+    let mut cpu = user_mode(
+        "
+        .ORIG x3000
+        LDI R2, PTR
+        PTR .FILL x0200
+        .END
+        ",
+    );
+    expect_acv(&mut cpu, 0x3000);
+    assert_eq!(cpu.registers()[2], 0);
+}
+
+#[test]
+fn ldi_within_user_space() {
+    let mut cpu = user_mode(
+        "
+        .ORIG x3000
+        LDI R0, PTR
+        PTR .FILL DATA
+        DATA .FILL #42
+        .END
+        ",
+    );
+    run(&mut cpu, 1);
+    assert_eq!(cpu.registers()[0], 42);
+}
+
+#[test]
+fn st_to_system_space() {
+    let mut cpu = user_mode(
+        "
+        .ORIG x3000
+        ST R0, #-4
+        .END
+        ",
+    );
+    expect_acv(&mut cpu, 0x3000);
+    assert_eq!(cpu.peek(0x2FFD), 0);
+}
+
+#[test]
+fn sti_does_not_store_after_violation() {
+    let mut cpu = user_mode(
+        "
+        .ORIG x3000
+        AND R0, R0, #0
+        STI R0, PTR
+        PTR .FILL x0102
+        .END
+        ",
+    );
+    run(&mut cpu, 1);
+    expect_acv(&mut cpu, 0x3001);
+    assert_eq!(cpu.peek(0x0102), 0x5000);
+}
+
+#[test]
+fn str_to_device_registers() {
+    let mut cpu = user_mode(
+        "
+        .ORIG x3000
+        LD R1, MCR
+        STR R0, R1, #0
+        MCR .FILL xFFFE
+        .END
+        ",
+    );
+    run(&mut cpu, 1);
+    expect_acv(&mut cpu, 0x3001);
+    assert!(!cpu.should_halt());
+}
+
+#[test]
+fn fetch_from_system_space() {
+    let mut cpu = user_mode(
+        "
+        .ORIG x3000
+        LD R0, OS
+        JMP R0
+        OS .FILL x0200
+        .END
+        ",
+    );
+    run(&mut cpu, 2);
+    assert_eq!(cpu.pc, 0x0200);
+    expect_acv(&mut cpu, 0x0200);
+}
+
+#[test]
+fn fetch_from_uninitialized_system_space_is_still_a_violation() {
+    let mut cpu = user_mode(
+        "
+        .ORIG x3000
+        LD R0, NOWHERE
+        JMP R0
+        NOWHERE .FILL x1000
+        .END
+        ",
+    );
+    run(&mut cpu, 2);
+    expect_acv(&mut cpu, 0x1000);
+}
+
+#[test]
+fn violation_without_handler_stops_at_instruction() {
+    let mut cpu = cpu_with(&[
+        SETUP,
+        "
+        .ORIG x3000
+        LDI R0, PTR
+        PTR .FILL xFE02
+        .END
+        ",
+    ]);
+    cpu.set_jmpt_enabled(true);
+    run(&mut cpu, 3);
+    assert_eq!(
+        cpu.step(),
+        Err(EmulatorError::UnhandledException {
+            exception: Exception::AccessControlViolation,
+            pc: 0x3000
+        })
+    );
+    assert_eq!(cpu.pc, 0x3000);
+}
+
+#[test]
+fn supervisor_mode_is_unrestricted() {
+    let mut cpu = cpu_with(&[
+        "
+        .ORIG x3000
+        LDI R0, PTR
+        STI R0, PTR
+        PTR .FILL x0102
+        .END
+        ",
+        VECTOR_TABLE,
+    ]);
+    run(&mut cpu, 2);
+    assert_eq!(cpu.registers()[0], 0x5000);
+}
+
+// Code in x3000-x30FF, data above it
+const SPLIT_MAP: &str = "
+# User space
+x3000-xFDFF rw-   # data can't be executed
+x3000-x30FF r-x   # code can't be written
+";
+
+#[test]
+fn write_protected_code() {
+    let mut cpu = user_mode_with(
+        "
+        .ORIG x3000
+        ST R0, TARGET
+        TARGET .FILL #0
+        .END
+        ",
+        ProtectionMap::parse(SPLIT_MAP).unwrap(),
+    );
+    expect_acv(&mut cpu, 0x3000);
+    assert_eq!(cpu.peek(0x3001), 0);
+}
+
+#[test]
+fn execute_never_data() {
+    let mut cpu = user_mode_with(
+        "
+        .ORIG x3000
+        LD R1, DATA
+        LDR R2, R1, #0
+        JMP R1
+        DATA .FILL x3100
+        .END
+        ",
+        ProtectionMap::parse(SPLIT_MAP).unwrap(),
+    );
+    cpu.load_program(std::io::Cursor::new(object(
+        ".ORIG x3100\nADD R0, R0, #1\n.END",
+    )))
+    .unwrap();
+    // Reading the data is fine, but jumping into it isn't
+    run(&mut cpu, 3);
+    assert_eq!(cpu.registers()[2], 0x1021);
+    assert_eq!(cpu.pc, 0x3100);
+    expect_acv(&mut cpu, 0x3100);
+}
+
+#[test]
+fn later_regions_take_precedence() {
+    let mut map = ProtectionMap::default();
+    map.add_region(0x4000..=0x40FF, Permissions::NONE);
+    assert_eq!(map.permissions(0x3FFF), Permissions::ALL);
+    assert_eq!(map.permissions(0x4000), Permissions::NONE);
+    assert_eq!(map.permissions(0x40FF), Permissions::NONE);
+    assert_eq!(map.permissions(0x4100), Permissions::ALL);
+    assert_eq!(map.permissions(0xFE00), Permissions::NONE);
+}
+
+#[test]
+fn invalid_protection_maps() {
+    assert!(ProtectionMap::parse("x3000-x2000 rwx").is_err());
+    assert!(ProtectionMap::parse("x3000-xFDFF").is_err());
+    assert!(ProtectionMap::parse("x3000-xFDFF rwz").is_err());
+    assert!(ProtectionMap::parse("x3000-x1FFFF rwx").is_err());
+    assert_eq!(
+        ProtectionMap::parse("\n# nothing\n").unwrap(),
+        ProtectionMap::empty()
+    );
+}
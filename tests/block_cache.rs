@@ -0,0 +1,224 @@
+mod common;
+
+use alic3::emulator::{Edition, StepEvent};
+use common::*;
+
+const PROGRAM: &str = ".ORIG x3000
+LD R6, STACK
+LEA R0, ARRAY
+LD R1, LENGTH
+FILL STR R1, R0, #0
+ADD R0, R0, #1
+ADD R1, R1, #-1
+BRp FILL
+LEA R0, ARRAY
+LD R1, LENGTH
+SUM LDR R2, R0, #0
+JSR ACCUMULATE
+ADD R0, R0, #1
+ADD R1, R1, #-1
+BRp SUM
+DONE BRnzp DONE
+ACCUMULATE ADD R3, R3, R2
+RET
+STACK .FILL x3000
+LENGTH .FILL #10
+ARRAY .BLKW #10
+.END";
+
+/// Run blocks until `instructions` instructions have been executed in all.
+fn run_blocks(cpu: &mut TestCpu, instructions: u64) {
+    while cpu.instructions_retired() < instructions {
+        cpu.run_block(instructions - cpu.instructions_retired())
+            .unwrap();
+    }
+}
+
+#[test]
+fn matches_uncached_execution() {
+    let mut reference = cpu_with(&[PROGRAM]);
+    let mut cpu = cpu_with(&[PROGRAM]);
+    cpu.set_block_cache_enabled(true);
+    for block in 0..60 {
+        let event = cpu.run_block(u64::MAX).unwrap();
+        let mut reference_event = StepEvent::Executed;
+        while reference.instructions_retired() < cpu.instructions_retired() {
+            reference_event = reference.step().unwrap();
+        }
+        assert_eq!(event, reference_event, "block {}", block);
+        assert_eq!(cpu.registers(), reference.registers(), "block {}", block);
+        assert_eq!(cpu.pc, reference.pc, "block {}", block);
+        assert_eq!(cpu.psr(), reference.psr(), "block {}", block);
+        assert_eq!(cpu.cycles(), reference.cycles(), "block {}", block);
+        assert_eq!(cpu.peek(0x3016), reference.peek(0x3016), "block {}", block);
+    }
+    // 1 + 2 + ... + 10
+    assert_eq!(cpu.registers()[3], 55);
+}
+
+#[test]
+fn runs_a_block_at_a_time() {
+    let mut cpu = cpu_with(&[PROGRAM]);
+    cpu.set_block_cache_enabled(true);
+    // Everything up to and including the first BRp
+    assert_eq!(
+        cpu.run_block(u64::MAX),
+        Ok(StepEvent::BranchTaken { target: 0x3003 })
+    );
+    assert_eq!(cpu.instructions_retired(), 7);
+    // Then the loop body
+    cpu.run_block(u64::MAX).unwrap();
+    assert_eq!(cpu.instructions_retired(), 11);
+    // But no more than asked for
+    assert_eq!(cpu.run_block(2), Ok(StepEvent::Executed));
+    assert_eq!(cpu.instructions_retired(), 13);
+    assert_eq!(cpu.pc, 0x3005);
+}
+
+#[test]
+fn falls_back_to_stepping_without_the_cache() {
+    let mut cpu = cpu_with(&[PROGRAM]);
+    cpu.run_block(u64::MAX).unwrap();
+    assert_eq!(cpu.instructions_retired(), 1);
+}
+
+/// Each time around the loop, overwrite the ADD's immediate with a bigger one.
+const SELF_MODIFYING: &str = ".ORIG x3000
+LOOP ADD R1, R1, #1
+LD R2, INSTRUCTION
+ADD R2, R2, #1
+ST R2, INSTRUCTION
+ST R2, LOOP
+BRnzp LOOP
+INSTRUCTION ADD R1, R1, #1
+.END";
+
+#[test]
+fn sees_modified_code() {
+    let mut cpu = cpu_with(&[SELF_MODIFYING]);
+    cpu.set_block_cache_enabled(true);
+    run_blocks(&mut cpu, 18);
+    assert_eq!(cpu.registers()[1], 1 + 2 + 3);
+}
+
+#[test]
+fn writing_to_a_block_stops_and_discards_it() {
+    let program = ".ORIG x3000
+        LD R1, NEW
+        ST R1, TARGET
+        TARGET ADD R0, R0, #1
+        DONE BRnzp DONE
+        NEW ADD R0, R0, #2
+        .END";
+    let mut cpu = cpu_with(&[program]);
+    cpu.set_block_cache_enabled(true);
+    // The ST overwrites the rest of its own block, so the block stops there
+    assert_eq!(cpu.run_block(u64::MAX), Ok(StepEvent::Executed));
+    assert_eq!(cpu.pc, 0x3002);
+    cpu.run_block(u64::MAX).unwrap();
+    assert_eq!(cpu.registers()[0], 2);
+}
+
+#[test]
+fn stops_when_halted() {
+    let program = ".ORIG x3000
+        AND R0, R0, #0
+        STI R0, MCR
+        ADD R1, R1, #1
+        DONE BRnzp DONE
+        MCR .FILL xFFFE
+        .END";
+    let mut cpu = cpu_with(&[program]);
+    cpu.set_block_cache_enabled(true);
+    assert_eq!(cpu.run_block(u64::MAX), Ok(StepEvent::Halted));
+    assert!(cpu.should_halt());
+    assert_eq!(cpu.pc, 0x3002);
+    assert_eq!(cpu.registers()[1], 0);
+}
+
+#[test]
+fn undoing_a_write_restores_cached_code() {
+    let mut cpu = cpu_with(&[SELF_MODIFYING]);
+    cpu.set_block_cache_enabled(true);
+    run_blocks(&mut cpu, 6);
+    // The block at LOOP is cached with ADD #2. Modify it with history on, then undo that.
+    cpu.enable_history(16);
+    run(&mut cpu, 5);
+    assert_eq!(cpu.pc, 0x3005);
+    assert_eq!(cpu.peek(0x3000), 0x1263);
+    assert!(cpu.step_back());
+    assert_eq!(cpu.peek(0x3000), 0x1262);
+    cpu.disable_history();
+    cpu.pc = 0x3000;
+    let before = cpu.registers()[1];
+    cpu.run_block(1).unwrap();
+    assert_eq!(cpu.registers()[1], before + 2);
+}
+
+#[test]
+fn switching_editions_redecodes() {
+    let program = ".ORIG x3000
+        LOOP AND R1, R1, #0
+        LEA R0, LOOP
+        BRp POSITIVE
+        ADD R2, R2, #1
+        BRnzp LOOP
+        POSITIVE ADD R3, R3, #1
+        BRnzp LOOP
+        .END";
+    let mut cpu = cpu_with(&[program]);
+    cpu.set_block_cache_enabled(true);
+    // The second edition's LEA sets the condition codes
+    cpu.set_edition(Edition::Second);
+    run_blocks(&mut cpu, 5);
+    assert_eq!(cpu.registers()[3], 1);
+    // The third edition's doesn't
+    cpu.set_edition(Edition::Third);
+    run_blocks(&mut cpu, 9);
+    assert_eq!(cpu.registers()[2], 1);
+}
+
+#[test]
+fn timer_interrupts_arrive_at_the_same_instruction() {
+    let program = format!(
+        ".ORIG x3000
+        LD R6, STACK
+        LD R0, ENABLE
+        STI R0, TMR
+        LD R0, INTERVAL
+        STI R0, TMI
+        LOOP {}
+        BRnzp LOOP
+        STACK .FILL x3000
+        ENABLE .FILL x4000
+        INTERVAL .FILL #100
+        TMR .FILL xFE08
+        TMI .FILL xFE0A
+        .END",
+        ["ADD R0, R0, #1"; 20].join("\n")
+    );
+    let vector = ".ORIG x0181
+        .FILL x1000
+        .END";
+    let handler = ".ORIG x1000
+        ADD R1, R1, #1
+        RTI
+        .END";
+    let interrupts = |block_cache: bool| {
+        let mut cpu = cpu_with(&[&program, vector, handler]);
+        cpu.set_block_cache_enabled(block_cache);
+        let mut accepted = Vec::new();
+        while accepted.len() < 5 {
+            let event = if block_cache {
+                cpu.run_block(u64::MAX)
+            } else {
+                cpu.step()
+            };
+            if let Ok(StepEvent::InterruptAccepted { .. }) = event {
+                accepted.push((cpu.instructions_retired(), cpu.cycles(), cpu.registers()[0]));
+            }
+        }
+        accepted
+    };
+    assert_eq!(interrupts(true), interrupts(false));
+}
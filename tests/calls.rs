@@ -0,0 +1,160 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use alic3::emulator::{CallChecker, CallingConvention, Violation, ViolationKind};
+use common::*;
+
+fn checked(source: &str, convention: CallingConvention) -> (TestCpu, Rc<RefCell<CallChecker>>) {
+    let mut cpu = cpu_with(&[source]);
+    let checker = Rc::new(RefCell::new(CallChecker::new(convention)));
+    cpu.add_observer(Box::new(checker.clone()));
+    (cpu, checker)
+}
+
+#[test]
+fn well_behaved_subroutine() {
+    let (mut cpu, checker) = checked(
+        "
+        .ORIG x3000
+        LD R6, STACK
+        JSR SUB
+        BRnzp #-1
+        STACK .FILL x4000
+        SUB ADD R6, R6, #-1
+        STR R1, R6, #0
+        ADD R1, R1, #5
+        LDR R1, R6, #0
+        ADD R6, R6, #1
+        RET
+        .END
+        ",
+        CallingConvention::default(),
+    );
+    run(&mut cpu, 8);
+    assert_eq!(cpu.pc, 0x3002);
+    assert_eq!(checker.borrow_mut().take_violations(), vec![]);
+}
+
+#[test]
+fn nested_call_without_saving_r7() {
+    let (mut cpu, checker) = checked(
+        "
+        .ORIG x3000
+        JSR OUTER
+        HALT
+        OUTER JSR INNER
+        RET
+        INNER RET
+        .END
+        ",
+        CallingConvention::default(),
+    );
+    run(&mut cpu, 4);
+    assert_eq!(
+        checker.borrow_mut().take_violations(),
+        vec![Violation {
+            call_site: Some(0x3000),
+            return_site: 0x3003,
+            kind: ViolationKind::ClobberedR7 {
+                expected: 0x3001,
+                actual: 0x3003
+            }
+        }]
+    );
+}
+
+#[test]
+fn callee_saved_register_and_stack() {
+    let (mut cpu, checker) = checked(
+        "
+        .ORIG x3000
+        LD R6, STACK
+        JSR SUB
+        HALT
+        STACK .FILL x4000
+        SUB ADD R6, R6, #-1
+        ADD R2, R2, #1
+        ADD R0, R0, #1
+        RET
+        .END
+        ",
+        CallingConvention::default(),
+    );
+    run(&mut cpu, 6);
+    assert_eq!(
+        checker.borrow_mut().take_violations(),
+        vec![
+            Violation {
+                call_site: Some(0x3001),
+                return_site: 0x3007,
+                kind: ViolationKind::RegisterNotRestored {
+                    reg: 2,
+                    before: 0,
+                    after: 1
+                }
+            },
+            Violation {
+                call_site: Some(0x3001),
+                return_site: 0x3007,
+                kind: ViolationKind::StackImbalance {
+                    before: 0x4000,
+                    after: 0x3FFF
+                }
+            },
+        ]
+    );
+}
+
+#[test]
+fn convention_is_configurable() {
+    let (mut cpu, checker) = checked(
+        "
+        .ORIG x3000
+        JSR SUB
+        HALT
+        SUB ADD R2, R2, #1
+        ADD R0, R0, #1
+        RET
+        .END
+        ",
+        CallingConvention {
+            callee_saved: 0b0000_0001,
+            balanced_stack: false,
+        },
+    );
+    run(&mut cpu, 4);
+    let violations = checker.borrow_mut().take_violations();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(
+        violations[0].kind,
+        ViolationKind::RegisterNotRestored {
+            reg: 0,
+            before: 0,
+            after: 1
+        }
+    );
+}
+
+#[test]
+fn return_without_call() {
+    let (mut cpu, checker) = checked(
+        "
+        .ORIG x3000
+        LEA R7, #0
+        RET
+        .END
+        ",
+        CallingConvention::default(),
+    );
+    run(&mut cpu, 2);
+    assert_eq!(
+        checker.borrow_mut().take_violations(),
+        vec![Violation {
+            call_site: None,
+            return_site: 0x3001,
+            kind: ViolationKind::UnmatchedReturn
+        }]
+    );
+}
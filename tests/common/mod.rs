@@ -0,0 +1,79 @@
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use alic3::asm_parser::Parser;
+use alic3::assembler::assemble;
+use alic3::emulator::Cpu;
+
+pub type TestCpu = Cpu<Cursor<Vec<u8>>, Vec<u8>>;
+
+/// Output that can still be read while the CPU owns it
+#[derive(Clone, Default)]
+pub struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedOutput {
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+}
+
+/// Assemble LC-3 source into the bytes of an object file.
+pub fn object(source: &str) -> Vec<u8> {
+    let program = Parser::parse(source).unwrap_or_else(|err| panic!("{}", err));
+    assemble(program)
+        .unwrap()
+        .into_iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect()
+}
+
+/// An empty directory for one test to keep its files in.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("alic3-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Assemble LC-3 source into an object file at `path`.
+pub fn write_object(path: &Path, source: &str) {
+    fs::write(path, object(source)).unwrap();
+}
+
+/// Create a CPU with each of the given programs loaded, ready to start at the first one's origin.
+pub fn cpu_with(sources: &[&str]) -> TestCpu {
+    let mut cpu = Cpu::new(Cursor::new(Vec::new()), Vec::new());
+    let origins = sources
+        .iter()
+        .map(|source| cpu.load_program(Cursor::new(object(source))).unwrap())
+        .collect::<Vec<_>>();
+    cpu.pc = origins[0];
+    cpu
+}
+
+/// Execute the given number of instructions, panicking if any of them fail.
+pub fn run(cpu: &mut TestCpu, steps: usize) {
+    for _ in 0..steps {
+        cpu.step().unwrap();
+    }
+}
+
+/// The condition code bits of the PSR
+pub fn nzp(cpu: &TestCpu) -> u16 {
+    cpu.psr() & 0b111
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use alic3::asm_parser::Parser;
+use alic3::debug_info::DebugInfo;
+use alic3::emulator::{BranchCounts, Coverage};
+use common::*;
+
+const PROGRAM: &str = ".ORIG x3000
+AND R1, R1, #0
+ADD R1, R1, #2
+LOOP ADD R1, R1, #-1
+BRp LOOP
+BRn NEVER
+DONE BRnzp DONE
+NEVER ADD R2, R2, #1
+.END
+";
+
+fn cover(steps: usize) -> (Rc<RefCell<Coverage>>, DebugInfo) {
+    let mut cpu = cpu_with(&[PROGRAM]);
+    let mut debug_info = DebugInfo::from_program(&Parser::parse(PROGRAM).unwrap());
+    debug_info.set_source("loop.asm");
+    let mut coverage = Coverage::new();
+    coverage.add_program(&cpu, &debug_info);
+    let coverage = Rc::new(RefCell::new(coverage));
+    cpu.add_observer(Box::new(coverage.clone()));
+    run(&mut cpu, steps);
+    (coverage, debug_info)
+}
+
+#[test]
+fn counts_instructions_and_branch_directions() {
+    let (coverage, _) = cover(8);
+    let coverage = coverage.borrow();
+    assert_eq!(coverage.count_at(0x3002), 2);
+    assert_eq!(coverage.count_at(0x3006), 0);
+    assert_eq!(
+        coverage.branch_at(0x3003),
+        Some(BranchCounts {
+            taken: 1,
+            not_taken: 1
+        })
+    );
+    assert_eq!(
+        coverage.branch_at(0x3004),
+        Some(BranchCounts {
+            taken: 0,
+            not_taken: 1
+        })
+    );
+    // BRnzp always branches, so it has no directions to cover
+    assert_eq!(coverage.branch_at(0x3005), None);
+}
+
+#[test]
+fn finds_branches_that_never_executed() {
+    let (coverage, _) = cover(2);
+    assert_eq!(
+        coverage.borrow().branch_at(0x3004),
+        Some(BranchCounts::default())
+    );
+}
+
+#[test]
+fn annotates_source() {
+    let (coverage, debug_info) = cover(8);
+    let mut annotated = Vec::new();
+    coverage
+        .borrow()
+        .write_annotated(&debug_info, PROGRAM, &mut annotated)
+        .unwrap();
+    let annotated = String::from_utf8(annotated).unwrap();
+    let lines = annotated.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "        -:    1:.ORIG x3000");
+    assert_eq!(lines[4], "        2:    5:BRp LOOP  [taken 1, not taken 1]");
+    assert_eq!(lines[7], "    #####:    8:NEVER ADD R2, R2, #1");
+    assert!(annotated.ends_with("Lines executed: 6 of 7\nBranch directions taken: 3 of 4\n"));
+}
+
+#[test]
+fn writes_lcov() {
+    let (coverage, debug_info) = cover(8);
+    let mut lcov = Vec::new();
+    coverage
+        .borrow()
+        .write_lcov(&debug_info, &mut lcov)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(lcov).unwrap(),
+        "TN:
+SF:loop.asm
+BRDA:5,0,0,1
+BRDA:5,0,1,1
+BRDA:6,0,0,0
+BRDA:6,0,1,1
+BRF:4
+BRH:3
+DA:2,1
+DA:3,1
+DA:4,2
+DA:5,2
+DA:6,1
+DA:7,1
+DA:8,0
+LF:7
+LH:6
+end_of_record
+"
+    );
+}
@@ -0,0 +1,78 @@
+use alic3::asm_parser::Parser;
+use alic3::debug_info::{parse_address, DebugInfo};
+
+const SOURCE: &str = "; A comment
+
+.ORIG x3000
+MAIN    LEA R0, HELLO
+        PUTS
+
+        HALT
+HELLO   .STRINGZ \"Hi\"
+BUFFER  .BLKW #2
+.END
+";
+
+fn debug_info() -> DebugInfo {
+    DebugInfo::from_program(&Parser::parse(SOURCE).unwrap())
+}
+
+#[test]
+fn maps_addresses_to_lines() {
+    let info = debug_info();
+    assert_eq!(info.line(0x3000), Some(4));
+    assert_eq!(info.line(0x3001), Some(5));
+    assert_eq!(info.line(0x3002), Some(7));
+    // Every word of a string or block belongs to the line that reserved it
+    assert_eq!(info.line(0x3003), Some(8));
+    assert_eq!(info.line(0x3005), Some(8));
+    assert_eq!(info.line(0x3007), Some(9));
+    assert_eq!(info.line(0x3008), None);
+}
+
+#[test]
+fn labels_and_data() {
+    let info = debug_info();
+    assert_eq!(info.label(0x3000), Some("MAIN"));
+    assert_eq!(info.address_of("BUFFER"), Some(0x3006));
+    assert!(!info.is_data(0x3002));
+    assert!(info.is_data(0x3003));
+    assert!(info.is_data(0x3007));
+    assert!(!info.is_data(0x3008));
+}
+
+#[test]
+fn round_trips_through_text() {
+    let mut info = debug_info();
+    info.set_source("hello world.asm");
+    let mut text = Vec::new();
+    info.write(&mut text).unwrap();
+    assert_eq!(
+        DebugInfo::parse(std::str::from_utf8(&text).unwrap()).unwrap(),
+        info
+    );
+}
+
+#[test]
+fn rejects_garbage() {
+    assert!(DebugInfo::parse("line x3000").is_err());
+    assert!(DebugInfo::parse("frobnicate x3000 1").is_err());
+}
+
+#[test]
+fn rejects_empty_data() {
+    assert!(DebugInfo::parse("data x3000 0").is_err());
+    assert!(DebugInfo::parse("data x3000 1").is_ok());
+}
+
+#[test]
+fn parses_addresses_with_any_prefix() {
+    for address in ["x3000", "X3000", "0x3000", "0X3000", "3000"] {
+        assert_eq!(parse_address(address).unwrap(), 0x3000, "{}", address);
+    }
+    for address in ["", "x", "xx3000", "x10000", "#3000"] {
+        assert!(parse_address(address).is_err(), "{}", address);
+    }
+    let info = DebugInfo::parse("line X3000 4\n").unwrap();
+    assert_eq!(info.line(0x3000), Some(4));
+}
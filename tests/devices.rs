@@ -0,0 +1,140 @@
+mod common;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use common::*;
+
+/// Read RNG into R1-R4.
+const RANDOM: &str = ".ORIG x3000
+LDI R1, RNG
+LDI R2, RNG
+LDI R3, RNG
+LDI R4, RNG
+DONE BRnzp DONE
+RNG .FILL xFE18
+.END";
+
+fn random_numbers(seed: Option<u64>) -> [u16; 4] {
+    let mut cpu = cpu_with(&[RANDOM]);
+    if let Some(seed) = seed {
+        cpu.seed_random(seed);
+    }
+    run(&mut cpu, 4);
+    let registers = cpu.registers();
+    [registers[1], registers[2], registers[3], registers[4]]
+}
+
+#[test]
+fn random_numbers_repeat_for_a_seed() {
+    let numbers = random_numbers(Some(7));
+    assert_eq!(random_numbers(Some(7)), numbers);
+    assert_ne!(random_numbers(Some(8)), numbers);
+    // Unseeded runs are repeatable too
+    assert_eq!(random_numbers(None), random_numbers(None));
+    // And the numbers aren't all the same
+    assert!(numbers.iter().any(|&number| number != numbers[0]));
+}
+
+#[test]
+fn programs_can_seed_the_generator() {
+    let program = ".ORIG x3000
+        LD R0, SEED
+        STI R0, RNG
+        LDI R1, RNG
+        STI R0, RNG
+        LDI R2, RNG
+        DONE BRnzp DONE
+        SEED .FILL #1234
+        RNG .FILL xFE18
+        .END";
+    let mut cpu = cpu_with(&[program]);
+    run(&mut cpu, 5);
+    assert_eq!(cpu.registers()[1], cpu.registers()[2]);
+    assert_eq!(cpu.registers()[1], random_numbers(Some(1234))[0]);
+}
+
+#[test]
+fn stepping_back_rewinds_the_generator() {
+    let mut cpu = cpu_with(&[RANDOM]);
+    cpu.enable_history(8);
+    run(&mut cpu, 2);
+    let numbers = cpu.registers();
+    assert!(cpu.step_back());
+    assert!(cpu.step_back());
+    run(&mut cpu, 2);
+    assert_eq!(cpu.registers()[1..3], numbers[1..3]);
+}
+
+/// Read all four words of the clock starting at `first` into R1-R4, low word first, after some busy work.
+fn read_clock(first: u16) -> [u16; 4] {
+    let program = format!(
+        ".ORIG x3000
+        AND R0, R0, #0
+        ADD R0, R0, #10
+        LOOP ADD R0, R0, #-1
+        BRp LOOP
+        LD R0, CLOCK
+        LDR R1, R0, #0
+        LDR R2, R0, #2
+        LDR R3, R0, #4
+        LDR R4, R0, #6
+        DONE BRnzp DONE
+        CLOCK .FILL x{:04X}
+        .END",
+        first
+    );
+    let mut cpu = cpu_with(&[&program]);
+    run(&mut cpu, 2 + 2 * 10 + 5);
+    let registers = cpu.registers();
+    [registers[1], registers[2], registers[3], registers[4]]
+}
+
+fn milliseconds_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+fn combine(words: [u16; 4]) -> u64 {
+    words
+        .iter()
+        .rev()
+        .fold(0, |value, &word| value << 16 | word as u64)
+}
+
+#[test]
+fn clock_counts_instructions() {
+    // Two to set up, twenty around the loop, and the LD
+    assert_eq!(read_clock(0xFE20), [23, 0, 0, 0]);
+}
+
+#[test]
+fn clock_tells_the_time() {
+    let before = milliseconds_since_epoch();
+    let time = combine(read_clock(0xFE28));
+    let after = milliseconds_since_epoch();
+    assert!(
+        (before..=after).contains(&time),
+        "{} not in {}..={}",
+        time,
+        before,
+        after
+    );
+}
+
+#[test]
+fn clock_registers_are_read_only() {
+    let program = ".ORIG x3000
+        LD R0, VALUE
+        STI R0, WCR3
+        LDI R1, WCR3
+        DONE BRnzp DONE
+        VALUE .FILL xFFFF
+        WCR3 .FILL xFE2E
+        .END";
+    let mut cpu = cpu_with(&[program]);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.registers()[1], 0x0000);
+    assert_eq!(cpu.peek(0xFE2E), 0x0000);
+}
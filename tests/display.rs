@@ -0,0 +1,168 @@
+mod common;
+
+use std::io::Cursor;
+
+use alic3::emulator::{Cpu, Diagnostic, DiagnosticKind, DisplayMode, EmulatorError, SanitizerMode};
+use common::*;
+
+/// Print TEXT, poll the keyboard, print TAIL, then halt.
+fn program(text: &str, tail: &str) -> String {
+    format!(
+        ".ORIG x3000
+        LEA R1, TEXT
+        JSR PRINT
+        LDI R0, KBSR
+        LEA R1, TAIL
+        JSR PRINT
+        AND R0, R0, #0
+        STI R0, MCR
+        PRINT LDR R0, R1, #0
+        BRz RETURN
+        STI R0, DDR
+        ADD R1, R1, #1
+        BRnzp PRINT
+        RETURN RET
+        KBSR .FILL xFE00
+        DDR .FILL xFE06
+        MCR .FILL xFFFE
+        TEXT .STRINGZ \"{}\"
+        TAIL .STRINGZ \"{}\"
+        .END",
+        text, tail
+    )
+}
+
+fn cpu_printing(
+    text: &str,
+    tail: &str,
+    mode: DisplayMode,
+) -> (Cpu<Cursor<Vec<u8>>, SharedOutput>, SharedOutput) {
+    let output = SharedOutput::default();
+    let mut cpu = Cpu::new(Cursor::new(Vec::new()), output.clone());
+    cpu.set_display_mode(mode);
+    cpu.pc = cpu
+        .load_program(Cursor::new(object(&program(text, tail))))
+        .unwrap();
+    (cpu, output)
+}
+
+/// Run until the instruction at `addr` is next.
+fn run_to(cpu: &mut Cpu<Cursor<Vec<u8>>, SharedOutput>, addr: u16) {
+    while cpu.pc != addr {
+        cpu.step().unwrap();
+    }
+}
+
+#[test]
+fn buffers_until_keyboard_poll_and_halt() {
+    let (mut cpu, output) = cpu_printing("Name? ", "bye", DisplayMode::Verbatim);
+    // Just before polling KBSR
+    run_to(&mut cpu, 0x3002);
+    assert_eq!(output.contents(), b"");
+    cpu.step().unwrap();
+    assert_eq!(output.contents(), b"Name? ");
+
+    while !cpu.should_halt() {
+        cpu.step().unwrap();
+    }
+    assert_eq!(output.contents(), b"Name? bye");
+}
+
+#[test]
+fn terminal_mode_flushes_each_line() {
+    let (mut cpu, output) = cpu_printing("one\\ntwo", "", DisplayMode::Terminal);
+    run_to(&mut cpu, 0x3002);
+    assert_eq!(output.contents(), b"one\r\n");
+}
+
+#[test]
+fn terminal_mode_handles_control_characters() {
+    let (mut cpu, output) = cpu_printing("ab\\bc\\a\\t\\v\\r", "", DisplayMode::Terminal);
+    run_to(&mut cpu, 0x3003);
+    assert_eq!(output.contents(), b"ab\x08 \x08c\x07\t^K\r");
+}
+
+#[test]
+fn verbatim_mode_writes_bytes_unchanged() {
+    let (mut cpu, output) = cpu_printing("a\\nb\\bc\\v", "", DisplayMode::Verbatim);
+    run_to(&mut cpu, 0x3003);
+    assert_eq!(output.contents(), b"a\nb\x08c\x0B");
+}
+
+#[test]
+fn flushes_when_dropped() {
+    let (mut cpu, output) = cpu_printing("unfinished", "", DisplayMode::Verbatim);
+    run_to(&mut cpu, 0x3002);
+    assert_eq!(output.contents(), b"");
+    drop(cpu);
+    assert_eq!(output.contents(), b"unfinished");
+}
+
+/// Write two characters, the second one either straight away or after polling DSR.
+fn writer(poll: bool) -> String {
+    format!(
+        ".ORIG x3000
+        LD R0, CHAR
+        STI R0, DDR
+        {}
+        STI R0, DDR
+        DONE BRnzp DONE
+        CHAR .FILL x41
+        DSR .FILL xFE04
+        DDR .FILL xFE06
+        .END",
+        if poll {
+            "POLL LDI R1, DSR\nBRzp POLL"
+        } else {
+            ""
+        }
+    )
+}
+
+#[test]
+fn dsr_drops_after_each_write() {
+    let mut cpu = cpu_with(&[&writer(true)]);
+    cpu.set_display_delay(100);
+    cpu.set_display_checker(SanitizerMode::Warn);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.registers()[1], 0x0000);
+    // Each time around the polling loop takes 31 cycles
+    while cpu.pc != 0x3004 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.registers()[1], 0x8000);
+    assert_eq!(cpu.instructions_retired(), 2 + 4 * 2);
+    cpu.step().unwrap();
+    assert!(cpu.take_diagnostics().is_empty());
+}
+
+#[test]
+fn reports_writes_while_not_ready() {
+    let mut cpu = cpu_with(&[&writer(false)]);
+    cpu.set_display_delay(100);
+    cpu.set_display_checker(SanitizerMode::Warn);
+    run(&mut cpu, 3);
+    assert_eq!(
+        cpu.take_diagnostics(),
+        [Diagnostic {
+            pc: 0x3002,
+            kind: DiagnosticKind::DisplayNotReady
+        }]
+    );
+}
+
+#[test]
+fn can_stop_at_writes_while_not_ready() {
+    let mut cpu = cpu_with(&[&writer(false)]);
+    cpu.set_display_delay(100);
+    cpu.set_display_checker(SanitizerMode::Stop);
+    run(&mut cpu, 2);
+    assert_eq!(
+        cpu.step(),
+        Err(EmulatorError::Sanitizer(Diagnostic {
+            pc: 0x3002,
+            kind: DiagnosticKind::DisplayNotReady
+        }))
+    );
+    assert_eq!(cpu.pc, 0x3002);
+}
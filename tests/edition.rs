@@ -0,0 +1,97 @@
+mod common;
+
+use alic3::emulator::{Edition, TrapRoutine};
+use common::*;
+
+const LEA: &str = "
+.ORIG x3000
+AND R0, R0, #0
+LEA R1, DATA
+DATA .FILL #0
+.END
+";
+
+#[test]
+fn second_edition_lea_sets_condition_codes() {
+    let mut cpu = cpu_with(&[LEA]);
+    cpu.set_edition(Edition::Second);
+    run(&mut cpu, 2);
+    assert_eq!(cpu.registers()[1], 0x3002);
+    assert_eq!(nzp(&cpu), 0b001);
+}
+
+#[test]
+fn third_edition_lea_leaves_condition_codes() {
+    let mut cpu = cpu_with(&[LEA]);
+    cpu.set_edition(Edition::Third);
+    run(&mut cpu, 2);
+    assert_eq!(cpu.registers()[1], 0x3002);
+    // Still set from the AND
+    assert_eq!(nzp(&cpu), 0b010);
+}
+
+const TRAP: &str = "
+.ORIG x3000
+LD R6, STACK
+AND R7, R7, #0
+TRAP x30
+STACK .FILL x2000
+.END
+";
+
+const TRAP_TABLE: &str = "
+.ORIG x0030
+.FILL x4000
+.END
+";
+
+const HANDLER: &str = "
+.ORIG x4000
+RET
+.END
+";
+
+#[test]
+fn second_edition_trap_saves_return_address_in_r7() {
+    let mut cpu = cpu_with(&[TRAP, TRAP_TABLE, HANDLER]);
+    cpu.set_edition(Edition::Second);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.pc, 0x4000);
+    assert_eq!(cpu.registers()[7], 0x3003);
+    assert_eq!(cpu.registers()[6], 0x2000);
+}
+
+#[test]
+fn third_edition_trap_pushes_psr_and_pc() {
+    let mut cpu = cpu_with(&[TRAP, TRAP_TABLE, HANDLER]);
+    cpu.set_edition(Edition::Third);
+    run(&mut cpu, 2);
+    let psr = cpu.psr();
+    run(&mut cpu, 1);
+    assert_eq!(cpu.pc, 0x4000);
+    assert_eq!(cpu.registers()[7], 0);
+    assert_eq!(cpu.registers()[6], 0x1FFE);
+    assert_eq!(cpu.peek(0x1FFF), psr);
+    assert_eq!(cpu.peek(0x1FFE), 0x3003);
+}
+
+const OUT: &str = "
+.ORIG x3000
+AND R7, R7, #0
+LD R0, CHAR
+OUT
+CHAR .FILL x41
+.END
+";
+
+#[test]
+fn native_traps_follow_edition() {
+    for (edition, r7) in [(Edition::Second, 0x3003), (Edition::Third, 0)] {
+        let mut cpu = cpu_with(&[OUT]);
+        cpu.set_edition(edition);
+        cpu.set_trap_hle(TrapRoutine::Out, true);
+        run(&mut cpu, 3);
+        assert_eq!(cpu.pc, 0x3003);
+        assert_eq!(cpu.registers()[7], r7);
+    }
+}
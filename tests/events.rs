@@ -0,0 +1,137 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use alic3::emulator::{Exception, Observer, StepEvent, StepRecord};
+use common::*;
+
+const PROGRAM: &str = "
+.ORIG x3000
+LD R6, SSP
+BRnzp SKIP
+ADD R0, R0, #1
+SKIP JSR SUB
+.FILL xD000
+SSP .FILL x3000
+SUB RET
+.END
+";
+
+const VECTOR_TABLE: &str = "
+.ORIG x0101
+.FILL x5000
+.END
+";
+
+const INTERRUPT_VECTOR: &str = "
+.ORIG x0180
+.FILL x6000
+.END
+";
+
+#[derive(Default)]
+struct Recorder {
+    steps: Vec<(u16, Option<u16>, StepEvent)>,
+}
+
+impl Observer for Recorder {
+    fn on_step(&mut self, record: &StepRecord) {
+        self.steps
+            .push((record.pc, record.instruction, record.event.clone()));
+    }
+}
+
+#[test]
+fn step_reports_control_flow() {
+    let mut cpu = cpu_with(&[PROGRAM, VECTOR_TABLE]);
+    assert_eq!(cpu.step(), Ok(StepEvent::Executed));
+    assert_eq!(cpu.step(), Ok(StepEvent::BranchTaken { target: 0x3003 }));
+    assert_eq!(cpu.step(), Ok(StepEvent::BranchTaken { target: 0x3006 }));
+    assert_eq!(cpu.step(), Ok(StepEvent::BranchTaken { target: 0x3004 }));
+    assert_eq!(
+        cpu.step(),
+        Ok(StepEvent::Exception {
+            cause: Exception::IllegalOpcode
+        })
+    );
+    assert_eq!(cpu.pc, 0x5000);
+}
+
+#[test]
+fn interrupt_is_accepted_before_next_instruction() {
+    let mut cpu = cpu_with(&[PROGRAM, VECTOR_TABLE, INTERRUPT_VECTOR]);
+    run(&mut cpu, 1);
+    let retired = cpu.instructions_retired();
+    cpu.request_interrupt(0x80, 4);
+    assert_eq!(
+        cpu.step(),
+        Ok(StepEvent::InterruptAccepted {
+            vector: 0x80,
+            priority: 4
+        })
+    );
+    assert_eq!(cpu.pc, 0x6000);
+    assert_eq!((cpu.psr() >> 8) & 7, 4);
+    // The interrupted PC and PSR were pushed
+    assert_eq!(cpu.registers()[6], 0x2FFE);
+    assert_eq!(cpu.peek(0x2FFE), 0x3001);
+    assert_eq!(cpu.instructions_retired(), retired);
+}
+
+#[test]
+fn interrupt_waits_for_lower_priority() {
+    let mut cpu = cpu_with(&[PROGRAM, VECTOR_TABLE, INTERRUPT_VECTOR]);
+    run(&mut cpu, 1);
+    cpu.request_interrupt(0x80, 4);
+    run(&mut cpu, 1);
+    // A second interrupt at the same priority can't preempt the first one's handler
+    cpu.request_interrupt(0x80, 4);
+    assert_eq!(
+        cpu.step(),
+        Err(alic3::emulator::EmulatorError::UninitializedExecution { pc: 0x6000 })
+    );
+}
+
+#[test]
+fn observers_see_every_step() {
+    let mut cpu = cpu_with(&[PROGRAM, VECTOR_TABLE, INTERRUPT_VECTOR]);
+    let recorder = Rc::new(RefCell::new(Recorder::default()));
+    cpu.add_observer(Box::new(recorder.clone()));
+    run(&mut cpu, 2);
+    cpu.request_interrupt(0x80, 1);
+    run(&mut cpu, 1);
+    assert_eq!(
+        recorder.borrow().steps,
+        vec![
+            (0x3000, Some(0x2C04), StepEvent::Executed),
+            (
+                0x3001,
+                Some(0x0E01),
+                StepEvent::BranchTaken { target: 0x3003 }
+            ),
+            (
+                0x3003,
+                None,
+                StepEvent::InterruptAccepted {
+                    vector: 0x80,
+                    priority: 1
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn undoing_an_interrupt_keeps_instruction_count() {
+    let mut cpu = cpu_with(&[PROGRAM, VECTOR_TABLE, INTERRUPT_VECTOR]);
+    cpu.enable_history(8);
+    run(&mut cpu, 1);
+    cpu.request_interrupt(0x80, 4);
+    run(&mut cpu, 1);
+    assert!(cpu.step_back());
+    assert_eq!(cpu.instructions_retired(), 1);
+    assert_eq!(cpu.pc, 0x3001);
+    assert!(cpu.step_back());
+    assert_eq!(cpu.instructions_retired(), 0);
+}
@@ -0,0 +1,310 @@
+mod common;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use common::*;
+
+/// Run the `exec` binary, feeding it `stdin`.
+fn exec(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_exec"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Assemble a program into a fresh directory named after the test, returning the directory and the object file.
+fn assemble_into(test: &str, source: &str) -> (PathBuf, String) {
+    let dir = scratch_dir(&format!("exec-{}", test));
+    let path = dir.join("program.obj");
+    write_object(&path, source);
+    (dir, path.to_str().unwrap().to_string())
+}
+
+fn path_in(dir: &Path, name: &str) -> String {
+    dir.join(name).to_str().unwrap().to_string()
+}
+
+/// Echo keys to the display until a newline, then halt.
+const ECHO: &str = ".ORIG x3000
+LOOP LDI R0, KBSR
+BRzp LOOP
+LDI R0, KBDR
+STI R0, DDR
+ADD R0, R0, #-10
+BRnp LOOP
+STI R0, MCR
+KBSR .FILL xFE00
+KBDR .FILL xFE02
+DDR .FILL xFE06
+MCR .FILL xFFFE
+.END";
+
+#[test]
+fn scripted_input_from_a_string() {
+    let (dir, program) = assemble_into("input-string", ECHO);
+    let output = path_in(&dir, "output.txt");
+    let result = exec(
+        &["--input", "a\\tb\\\\\\n", "--output", &output, &program],
+        b"",
+    );
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(fs::read(&output).unwrap(), b"a\tb\\\n");
+    // Nothing goes to the terminal
+    assert_eq!(result.stdout, b"");
+}
+
+#[test]
+fn scripted_input_from_a_file() {
+    let (dir, program) = assemble_into("input-file", ECHO);
+    let input = path_in(&dir, "input.txt");
+    fs::write(&input, "from a file\nnot read").unwrap();
+    let output = path_in(&dir, "output.txt");
+    let result = exec(
+        &["--input-file", &input, "--output", &output, &program],
+        b"",
+    );
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&output).unwrap(), "from a file\n");
+}
+
+/// Print y if a key is ready straight away, or n if not.
+const KEY_READY: &str = ".ORIG x3000
+LDI R0, KBSR
+BRn READY
+LD R0, NO
+BRnzp PRINT
+READY LD R0, YES
+PRINT STI R0, DDR
+AND R0, R0, #0
+STI R0, MCR
+YES .FILL x79
+NO .FILL x6E
+KBSR .FILL xFE00
+DDR .FILL xFE06
+MCR .FILL xFFFE
+.END";
+
+#[test]
+fn input_delay_holds_keys_back() {
+    let (dir, program) = assemble_into("input-delay", KEY_READY);
+    let output = path_in(&dir, "output.txt");
+    exec(&["--input", "k", "--output", &output, &program], b"");
+    assert_eq!(fs::read(&output).unwrap(), b"y");
+    exec(
+        &[
+            "--input",
+            "k",
+            "--input-delay",
+            "1000",
+            "--output",
+            &output,
+            &program,
+        ],
+        b"",
+    );
+    assert_eq!(fs::read(&output).unwrap(), b"n");
+
+    // A program that waits for each key still gets all of them
+    let (dir, program) = assemble_into("input-delay-echo", ECHO);
+    let output = path_in(&dir, "output.txt");
+    let result = exec(
+        &[
+            "--input",
+            "slow\\n",
+            "--input-delay",
+            "500",
+            "--output",
+            &output,
+            &program,
+        ],
+        b"",
+    );
+    assert_eq!(result.status.code(), Some(0));
+    assert_eq!(fs::read(&output).unwrap(), b"slow\n");
+}
+
+#[test]
+fn non_interactive_reads_stdin_and_writes_verbatim() {
+    let (_dir, program) = assemble_into("non-interactive", ECHO);
+    let result = exec(&["--non-interactive", &program], b"piped\x08\n");
+    assert_eq!(result.status.code(), Some(0));
+    // No line ending or backspace translation when the output isn't a terminal
+    assert_eq!(result.stdout, b"piped\x08\n");
+}
+
+/// Run a program with a JSON summary, returning the exit code and the summary.
+fn run_with_summary(test: &str, source: &str, args: &[&str]) -> (Option<i32>, serde_json::Value) {
+    let (dir, program) = assemble_into(test, source);
+    let summary = path_in(&dir, "summary.json");
+    let mut all_args = vec!["--non-interactive", "--summary", &summary];
+    all_args.extend_from_slice(args);
+    all_args.push(&program);
+    let result = exec(&all_args, b"");
+    let summary = serde_json::from_str(&fs::read_to_string(&summary).unwrap()).unwrap();
+    (result.status.code(), summary)
+}
+
+const SPIN: &str = ".ORIG x3000
+AND R0, R0, #0
+LOOP BRz LOOP
+.END";
+
+const HALT_AFTER_TWO: &str = ".ORIG x3000
+AND R0, R0, #0
+STI R0, MCR
+MCR .FILL xFFFE
+.END";
+
+#[test]
+fn halting_exits_zero() {
+    let (code, summary) = run_with_summary("halt", HALT_AFTER_TWO, &[]);
+    assert_eq!(code, Some(0));
+    assert_eq!(summary["status"], "halted");
+    assert_eq!(summary["exit_code"], 0);
+    assert_eq!(summary["instructions"], 2);
+    assert_eq!(summary["pc"], 0x3002);
+    assert!(summary.get("message").is_none());
+}
+
+#[test]
+fn bad_arguments_exit_one() {
+    let result = exec(&["--no-such-option", "program.obj"], b"");
+    assert_eq!(result.status.code(), Some(1));
+    let result = exec(&["/nonexistent/program.obj"], b"");
+    assert_eq!(result.status.code(), Some(1));
+    for policy in ["vector:x100", "vector:zz", "vector"] {
+        let result = exec(&["--fault-policy", policy, "program.obj"], b"");
+        assert_eq!(result.status.code(), Some(1), "{}", policy);
+        let result = exec(&["--stack-policy", policy, "program.obj"], b"");
+        assert_eq!(result.status.code(), Some(1), "{}", policy);
+    }
+    // Missing files are named in the error
+    for option in ["--input-file", "--protection"] {
+        let result = exec(&[option, "/nonexistent/file.txt", "program.obj"], b"");
+        assert_eq!(result.status.code(), Some(1), "{}", option);
+        let error = String::from_utf8(result.stderr).unwrap();
+        assert!(error.contains("/nonexistent/file.txt"), "{}: {}", option, error);
+    }
+}
+
+#[test]
+fn stops_at_the_instruction_limit() {
+    let (code, summary) = run_with_summary("limit", SPIN, &["--max-instructions", "100"]);
+    assert_eq!(code, Some(2));
+    assert_eq!(summary["status"], "instruction_limit");
+    assert_eq!(summary["instructions"], 100);
+
+    let (code, summary) = run_with_summary("limit-zero", SPIN, &["--max-instructions", "0"]);
+    assert_eq!(code, Some(2));
+    assert_eq!(summary["instructions"], 0);
+
+    // Halting on the last instruction allowed still counts as halting
+    let (code, _) = run_with_summary("limit-exact", HALT_AFTER_TWO, &["--max-instructions", "2"]);
+    assert_eq!(code, Some(0));
+}
+
+#[test]
+fn stops_at_the_time_limit() {
+    let (code, summary) = run_with_summary("timeout", SPIN, &["--timeout", "0.2"]);
+    assert_eq!(code, Some(3));
+    assert_eq!(summary["status"], "time_limit");
+    let elapsed = summary["elapsed_ms"].as_u64().unwrap();
+    assert!((200..5000).contains(&elapsed), "ran for {} ms", elapsed);
+}
+
+#[test]
+fn unhandled_exceptions_exit_four() {
+    let program = ".ORIG x3000
+        LD R6, SSP
+        .FILL xD000
+        SSP .FILL x3000
+        .END";
+    let (code, summary) = run_with_summary("exception", program, &[]);
+    assert_eq!(code, Some(4));
+    assert_eq!(summary["status"], "unhandled_exception");
+    assert_eq!(summary["exception"], "illegal opcode");
+    assert_eq!(summary["message"], "unhandled illegal opcode at 0x3001");
+    assert_eq!(summary["pc"], 0x3001);
+}
+
+#[test]
+fn running_into_uninitialized_memory_exits_five() {
+    let program = ".ORIG x3000
+        ADD R0, R0, #1
+        ADD R0, R0, #1
+        .END";
+    let (code, summary) = run_with_summary("uninitialized", program, &[]);
+    assert_eq!(code, Some(5));
+    assert_eq!(summary["status"], "uninitialized_memory");
+    assert_eq!(summary["instructions"], 2);
+    assert_eq!(summary["pc"], 0x3002);
+    assert_eq!(
+        summary["message"],
+        "PC ran into uninitialized memory at 0x3002"
+    );
+}
+
+#[test]
+fn nested_faults_exit_six() {
+    // Install a handler for illegal opcodes, then put the supervisor stack right above the vector tables
+    let program = ".ORIG x3000
+        LD R0, HANDLER
+        STI R0, VECTOR
+        LD R6, SSP
+        .FILL xD000
+        HANDLER .FILL x4000
+        VECTOR .FILL x0101
+        SSP .FILL x0201
+        .END";
+    let (code, summary) = run_with_summary("nested-fault", program, &[]);
+    assert_eq!(code, Some(6));
+    assert_eq!(summary["status"], "nested_fault");
+    assert_eq!(summary["pc"], 0x3003);
+}
+
+#[test]
+fn sanitizer_stops_exit_seven() {
+    let program = ".ORIG x3000
+        LDI R0, POINTER
+        POINTER .FILL x4000
+        .END";
+    let (code, summary) = run_with_summary("sanitizer", program, &["--sanitize", "stop"]);
+    assert_eq!(code, Some(7));
+    assert_eq!(summary["status"], "sanitizer");
+    assert_eq!(summary["instructions"], 0);
+}
+
+#[test]
+fn stack_faults_exit_eight() {
+    let program = ".ORIG x3000
+        LD R6, STACK
+        PUSH ADD R6, R6, #-1
+        BRnzp PUSH
+        STACK .FILL x4004
+        .END";
+    let (code, summary) =
+        run_with_summary("stack", program, &["--supervisor-stack", "x4000-x4003"]);
+    assert_eq!(code, Some(8));
+    assert_eq!(summary["status"], "stack_fault");
+}
+
+#[test]
+fn summary_escapes_strings() {
+    let dir = scratch_dir("exec-summary-escapes");
+    let program = path_in(&dir, "a \"quoted\" \\ name.obj");
+    write_object(Path::new(&program), HALT_AFTER_TWO);
+    let summary = path_in(&dir, "summary.json");
+    let result = exec(&["--non-interactive", "--summary", &summary, &program], b"");
+    assert_eq!(result.status.code(), Some(0));
+    let summary: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&summary).unwrap()).unwrap();
+    assert_eq!(summary["program"], program.as_str());
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use alic3::asm_parser::Parser;
+use alic3::debug_info::DebugInfo;
+use alic3::emulator::{Diagnostic, DiagnosticKind, EmulatorError, SanitizerMode};
+use common::*;
+
+/// Falls off the end of its code into a string
+const FALLS_INTO_DATA: &str = "
+.ORIG x3000
+AND R0, R0, #0
+MESSAGE .STRINGZ \"hi\"
+.END
+";
+
+/// Patches the instruction at PATCHED before running it
+const SELF_MODIFYING: &str = "
+.ORIG x3000
+LD R0, NEW
+ST R0, PATCHED
+PATCHED ADD R1, R1, #1
+BRnzp PATCHED
+NEW AND R1, R1, #0
+.END
+";
+
+fn cpu_with_data(source: &str) -> TestCpu {
+    let mut cpu = cpu_with(&[source]);
+    for range in DebugInfo::from_program(&Parser::parse(source).unwrap()).data() {
+        cpu.mark_data(range);
+    }
+    cpu
+}
+
+#[test]
+fn warns_on_executing_data() {
+    let mut cpu = cpu_with_data(FALLS_INTO_DATA);
+    cpu.set_execution_checker(SanitizerMode::Warn);
+    run(&mut cpu, 3);
+    assert_eq!(
+        cpu.take_diagnostics(),
+        vec![
+            Diagnostic {
+                pc: 0x3001,
+                kind: DiagnosticKind::ExecutedData
+            },
+            Diagnostic {
+                pc: 0x3002,
+                kind: DiagnosticKind::ExecutedData
+            },
+        ]
+    );
+}
+
+#[test]
+fn warns_once_on_executing_modified_code() {
+    let mut cpu = cpu_with_data(SELF_MODIFYING);
+    cpu.set_execution_checker(SanitizerMode::Warn);
+    run(&mut cpu, 6);
+    assert_eq!(
+        cpu.take_diagnostics(),
+        vec![Diagnostic {
+            pc: 0x3002,
+            kind: DiagnosticKind::ExecutedModifiedCode
+        }]
+    );
+}
+
+#[test]
+fn stop_leaves_pc_on_instruction() {
+    let mut cpu = cpu_with_data(SELF_MODIFYING);
+    cpu.set_execution_checker(SanitizerMode::Stop);
+    run(&mut cpu, 2);
+    assert_eq!(
+        cpu.step(),
+        Err(EmulatorError::Sanitizer(Diagnostic {
+            pc: 0x3002,
+            kind: DiagnosticKind::ExecutedModifiedCode
+        }))
+    );
+    assert_eq!(cpu.pc, 0x3002);
+}
+
+#[test]
+fn reloading_clears_modification() {
+    let mut cpu = cpu_with_data(SELF_MODIFYING);
+    cpu.set_execution_checker(SanitizerMode::Stop);
+    run(&mut cpu, 2);
+    cpu.load_program(std::io::Cursor::new(object(SELF_MODIFYING)))
+        .unwrap();
+    cpu.pc = 0x3002;
+    run(&mut cpu, 1);
+}
+
+#[test]
+fn undo_restores_word_state() {
+    let mut cpu = cpu_with_data(SELF_MODIFYING);
+    cpu.enable_history(4);
+    cpu.set_execution_checker(SanitizerMode::Stop);
+    run(&mut cpu, 2);
+    assert!(cpu.step_back());
+    cpu.pc = 0x3002;
+    run(&mut cpu, 1);
+}
+
+#[test]
+fn off_by_default() {
+    let mut cpu = cpu_with_data(SELF_MODIFYING);
+    run(&mut cpu, 4);
+    assert_eq!(cpu.take_diagnostics(), vec![]);
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use alic3::emulator::{EmulatorError, Exception, Fault, FaultPolicy, FaultReason, StepEvent};
+use common::*;
+
+// The supervisor stack pointer is just above the vector tables, so delivering an exception would overwrite them
+const BAD_STACK: &str = "
+.ORIG x3000
+LD R6, SSP
+.FILL xD000
+SSP .FILL x0201
+.END
+";
+
+const VECTOR_TABLE: &str = "
+.ORIG x0101
+.FILL x5000
+.END
+";
+
+const DOUBLE_FAULT_VECTOR: &str = "
+.ORIG x0108
+.FILL x6000
+.END
+";
+
+fn stack_fault() -> Fault {
+    Fault {
+        vector: 0x01,
+        reason: FaultReason::StackPush { addr: 0x01FF },
+    }
+}
+
+#[test]
+fn halt_policy_reports_fault_chain() {
+    let mut cpu = cpu_with(&[BAD_STACK, VECTOR_TABLE]);
+    run(&mut cpu, 1);
+    match cpu.step() {
+        Err(EmulatorError::NestedFault(chain)) => {
+            assert_eq!(chain.pc, 0x3001);
+            assert_eq!(chain.faults, vec![stack_fault()]);
+        }
+        result => panic!("expected a nested fault, got {:?}", result),
+    }
+    // Nothing was pushed
+    assert_eq!(cpu.pc, 0x3001);
+    assert_eq!(cpu.registers()[6], 0x0201);
+}
+
+#[test]
+fn reset_policy_restarts_os() {
+    let mut cpu = cpu_with(&[BAD_STACK, VECTOR_TABLE]);
+    cpu.set_fault_policy(FaultPolicy::Reset);
+    run(&mut cpu, 1);
+    match cpu.step() {
+        Ok(StepEvent::FaultRecovered(chain)) => assert_eq!(chain.faults, vec![stack_fault()]),
+        result => panic!("expected a recovered fault, got {:?}", result),
+    }
+    assert_eq!(cpu.pc, 0x0200);
+    assert_eq!(cpu.registers(), [0; 8]);
+}
+
+#[test]
+fn vector_policy_delivers_double_fault_on_fresh_stack() {
+    let mut cpu = cpu_with(&[BAD_STACK, VECTOR_TABLE, DOUBLE_FAULT_VECTOR]);
+    cpu.set_fault_policy(FaultPolicy::Vector(0x08));
+    run(&mut cpu, 1);
+    match cpu.step() {
+        Ok(StepEvent::FaultRecovered(chain)) => assert_eq!(chain.faults, vec![stack_fault()]),
+        result => panic!("expected a recovered fault, got {:?}", result),
+    }
+    assert_eq!(cpu.pc, 0x6000);
+    assert_eq!(cpu.registers()[6], 0x2FFE);
+    assert_eq!(cpu.peek(0x2FFE), 0x3002);
+}
+
+#[test]
+fn missing_double_fault_handler_is_a_triple_fault() {
+    let mut cpu = cpu_with(&[BAD_STACK, VECTOR_TABLE]);
+    cpu.set_fault_policy(FaultPolicy::Vector(0x08));
+    run(&mut cpu, 1);
+    match cpu.step() {
+        Err(EmulatorError::NestedFault(chain)) => assert_eq!(
+            chain.faults,
+            vec![
+                stack_fault(),
+                Fault {
+                    vector: 0x08,
+                    reason: FaultReason::NoHandler
+                }
+            ]
+        ),
+        result => panic!("expected a nested fault, got {:?}", result),
+    }
+    assert_eq!(cpu.registers()[6], 0x0201);
+}
+
+#[test]
+fn exceptions_jump_to_the_address_in_the_vector_table() {
+    let program = "
+        .ORIG x3000
+        LD R6, SSP
+        .FILL xD000
+        SSP .FILL x3000
+        .END";
+    let mut cpu = cpu_with(&[program, VECTOR_TABLE]);
+    run(&mut cpu, 1);
+    assert_eq!(
+        cpu.step(),
+        Ok(StepEvent::Exception {
+            cause: Exception::IllegalOpcode
+        })
+    );
+    // The handler's address is read from the table, rather than the table entry itself being executed
+    assert_eq!(cpu.pc, 0x5000);
+    assert_eq!(cpu.registers()[6], 0x2FFE);
+    assert_eq!(cpu.peek(0x2FFE), 0x3002);
+}
+
+#[test]
+fn exceptions_without_a_handler_address_stop_at_the_faulting_instruction() {
+    let program = "
+        .ORIG x3000
+        LD R6, SSP
+        .FILL xD000
+        SSP .FILL x3000
+        .END";
+    let mut cpu = cpu_with(&[program]);
+    run(&mut cpu, 1);
+    assert_eq!(
+        cpu.step(),
+        Err(EmulatorError::UnhandledException {
+            exception: Exception::IllegalOpcode,
+            pc: 0x3001
+        })
+    );
+    assert_eq!(cpu.pc, 0x3001);
+    // Nothing was pushed
+    assert_eq!(cpu.registers()[6], 0x3000);
+}
@@ -0,0 +1,176 @@
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use alic3::emulator::{file_command, FileError};
+use common::*;
+
+/// An empty directory for one test to play in.
+fn directory(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("alic3-files-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Copy in.txt to out.txt a byte at a time, using handle 0 to read and handle 1 to write.
+const COPY: &str = ".ORIG x3000
+LEA R0, IN_NAME
+STI R0, FDR
+LD R0, OPEN_IN
+STI R0, FCR
+LEA R0, OUT_NAME
+STI R0, FDR
+LD R0, OPEN_OUT
+STI R0, FCR
+LOOP LD R0, READ
+STI R0, FCR
+LDI R1, FSR
+LD R2, END_OF_FILE
+AND R1, R1, R2
+BRnp DONE
+LD R0, WRITE
+STI R0, FCR
+BRnzp LOOP
+DONE LD R0, CLOSE_OUT
+STI R0, FCR
+AND R0, R0, #0
+STI R0, MCR
+OPEN_IN .FILL x0001
+OPEN_OUT .FILL x0102
+READ .FILL x0005
+WRITE .FILL x0106
+CLOSE_OUT .FILL x0104
+END_OF_FILE .FILL x4000
+FSR .FILL xFE30
+FCR .FILL xFE32
+FDR .FILL xFE34
+MCR .FILL xFFFE
+IN_NAME .STRINGZ \"in.txt\"
+OUT_NAME .STRINGZ \"data/out.txt\"
+.END";
+
+#[test]
+fn copies_a_file() {
+    let dir = directory("copy");
+    fs::write(dir.join("in.txt"), "hello\nworld\n").unwrap();
+    fs::create_dir(dir.join("data")).unwrap();
+    let mut cpu = cpu_with(&[COPY]);
+    cpu.enable_files(&dir);
+    for _ in 0..1000 {
+        if cpu.should_halt() {
+            break;
+        }
+        cpu.step().unwrap();
+    }
+    assert!(cpu.should_halt());
+    assert_eq!(
+        fs::read_to_string(dir.join("data/out.txt")).unwrap(),
+        "hello\nworld\n"
+    );
+}
+
+/// Issue a single command on handle 0 with FDR pointing at `name`, returning FSR afterwards.
+fn status_after(dir: &Path, name: &str, command: u16) -> u16 {
+    let program = format!(
+        ".ORIG x3000
+        LEA R0, NAME
+        STI R0, FDR
+        LD R0, COMMAND
+        STI R0, FCR
+        LDI R1, FSR
+        DONE BRnzp DONE
+        COMMAND .FILL x{:04X}
+        FSR .FILL xFE30
+        FCR .FILL xFE32
+        FDR .FILL xFE34
+        NAME .STRINGZ \"{}\"
+        .END",
+        command, name
+    );
+    let mut cpu = cpu_with(&[&program]);
+    cpu.enable_files(dir);
+    run(&mut cpu, 5);
+    cpu.registers()[1]
+}
+
+#[test]
+fn keeps_files_inside_the_directory() {
+    let outer = directory("sandbox");
+    fs::write(outer.join("secret.txt"), "secret").unwrap();
+    let dir = outer.join("inside");
+    fs::create_dir(&dir).unwrap();
+    let bad_name = 0x8000 | FileError::BadName as u16;
+
+    let escape = "../secret.txt";
+    assert_eq!(
+        status_after(&dir, escape, file_command::OPEN_READ),
+        bad_name
+    );
+    let absolute = outer.join("secret.txt");
+    let absolute = absolute.to_str().unwrap();
+    assert_eq!(
+        status_after(&dir, absolute, file_command::OPEN_READ),
+        bad_name
+    );
+    assert_eq!(
+        status_after(&dir, escape, file_command::OPEN_WRITE),
+        bad_name
+    );
+    assert_eq!(status_after(&dir, "", file_command::OPEN_WRITE), bad_name);
+    assert_eq!(
+        fs::read_to_string(outer.join("secret.txt")).unwrap(),
+        "secret"
+    );
+}
+
+#[test]
+fn reports_errors() {
+    let dir = directory("errors");
+    assert_eq!(
+        status_after(&dir, "missing.txt", file_command::OPEN_READ),
+        0x8000 | FileError::Io as u16
+    );
+    assert_eq!(
+        status_after(&dir, "", file_command::READ),
+        0x8000 | FileError::BadHandle as u16
+    );
+    assert_eq!(
+        status_after(&dir, "", 0x00FF),
+        0x8000 | FileError::UnknownCommand as u16
+    );
+    assert_eq!(
+        status_after(&dir, "new.txt", file_command::OPEN_APPEND),
+        0x8000
+    );
+    assert!(dir.join("new.txt").exists());
+}
+
+#[test]
+fn registers_are_memory_without_the_device() {
+    let mut cpu = cpu_with(&[".ORIG x3000
+        LD R0, VALUE
+        STI R0, FCR
+        LDI R1, FCR
+        VALUE .FILL x0001
+        FCR .FILL xFE32
+        .END"]);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.registers()[1], 0x0001);
+}
+
+#[test]
+fn leaves_the_memory_protection_register_alone() {
+    let dir = directory("mpr");
+    let mut cpu = cpu_with(&[".ORIG x3000
+        LD R0, VALUE
+        STI R0, MPR
+        LDI R1, MPR
+        VALUE .FILL x0001
+        MPR .FILL xFE12
+        .END"]);
+    cpu.enable_files(&dir);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.registers()[1], 0x0001);
+}
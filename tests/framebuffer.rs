@@ -0,0 +1,110 @@
+mod common;
+
+use alic3::emulator::{SanitizerMode, FRAMEBUFFER_HEIGHT, FRAMEBUFFER_WIDTH};
+use common::*;
+
+/// Draw a white pixel at the top left, a pure red one at (2, 1), and a green one at the bottom right, then ask for the
+/// picture to be saved.
+const DRAW: &str = ".ORIG x3000
+LD R0, SCREEN
+LD R1, WHITE
+STR R1, R0, #0
+LD R1, RED
+LD R2, ROW
+ADD R0, R0, R2
+STR R1, R0, #2
+LD R0, LAST
+LD R1, GREEN
+STR R1, R0, #0
+STI R1, VCR
+LDR R3, R0, #-1
+DONE BRnzp DONE
+SCREEN .FILL xC000
+LAST .FILL xFDFF
+ROW .FILL #128
+WHITE .FILL x7FFF
+RED .FILL x7C00
+GREEN .FILL x03E0
+VCR .FILL xFE0C
+.END";
+
+#[test]
+fn decodes_pixels() {
+    let mut cpu = cpu_with(&[DRAW]);
+    cpu.set_framebuffer_enabled(true);
+    run(&mut cpu, 14);
+    let frame = cpu.frame();
+    assert_eq!(frame.pixel(0, 0), 0x7FFF);
+    assert_eq!(frame.rgb(0, 0), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(frame.rgb(2, 1), [0xFF, 0x00, 0x00]);
+    assert_eq!(
+        frame.rgb(FRAMEBUFFER_WIDTH - 1, FRAMEBUFFER_HEIGHT - 1),
+        [0x00, 0xFF, 0x00]
+    );
+    assert_eq!(frame.rgb(1, 0), [0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn reports_changes_and_requests() {
+    let mut cpu = cpu_with(&[DRAW]);
+    cpu.set_framebuffer_enabled(true);
+    // Starts out changed, so the first picture gets drawn
+    assert!(cpu.take_frame_changed());
+    run(&mut cpu, 2);
+    assert!(!cpu.take_frame_changed());
+    run(&mut cpu, 1);
+    assert!(cpu.take_frame_changed());
+    assert!(!cpu.take_frame_changed());
+
+    run(&mut cpu, 7);
+    assert!(!cpu.take_frame_request());
+    run(&mut cpu, 1);
+    assert!(cpu.take_frame_request());
+    assert!(!cpu.take_frame_request());
+}
+
+#[test]
+fn screen_starts_black_and_initialized() {
+    let mut cpu = cpu_with(&[DRAW]);
+    cpu.set_sanitizer(SanitizerMode::Warn);
+    cpu.set_framebuffer_enabled(true);
+    run(&mut cpu, 14);
+    // Reading back a pixel that was never drawn is fine
+    assert!(cpu.take_diagnostics().is_empty());
+    assert_eq!(cpu.registers()[3], 0x0000);
+}
+
+#[test]
+fn writes_ppm() {
+    let mut cpu = cpu_with(&[DRAW]);
+    cpu.set_framebuffer_enabled(true);
+    run(&mut cpu, 14);
+    let mut ppm = Vec::new();
+    cpu.frame().write_ppm(&mut ppm).unwrap();
+    let header = b"P6\n128 124\n255\n";
+    assert_eq!(&ppm[..header.len()], header);
+    let pixels = &ppm[header.len()..];
+    assert_eq!(pixels.len(), FRAMEBUFFER_WIDTH * FRAMEBUFFER_HEIGHT * 3);
+    assert_eq!(&pixels[..6], [0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00]);
+    assert_eq!(&pixels[pixels.len() - 3..], [0x00, 0xFF, 0x00]);
+}
+
+#[test]
+fn writes_png() {
+    let mut cpu = cpu_with(&[DRAW]);
+    cpu.set_framebuffer_enabled(true);
+    run(&mut cpu, 14);
+    let mut png = Vec::new();
+    cpu.frame().write_png(&mut png).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    // Width and height in the IHDR chunk
+    assert_eq!(&png[16..24], [0, 0, 0, 128, 0, 0, 0, 124]);
+}
+
+#[test]
+fn vcr_is_memory_without_the_framebuffer() {
+    let mut cpu = cpu_with(&[DRAW]);
+    run(&mut cpu, 12);
+    assert!(!cpu.take_frame_request());
+    assert_eq!(cpu.peek(0xFE0C), 0x03E0);
+}
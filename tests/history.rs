@@ -0,0 +1,138 @@
+mod common;
+
+use std::collections::HashSet;
+
+use common::*;
+
+/// Count R1 up to 3, storing each value in COUNT.
+const COUNTER: &str = ".ORIG x3000
+AND R1, R1, #0
+LOOP ADD R1, R1, #1
+ST R1, COUNT
+ADD R2, R1, #-3
+BRn LOOP
+DONE BRnzp DONE
+COUNT .FILL #0
+.END";
+
+const COUNT: u16 = 0x3006;
+
+#[test]
+fn steps_back_one_instruction_at_a_time() {
+    let mut cpu = cpu_with(&[COUNTER]);
+    cpu.enable_history(16);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.registers()[1], 1);
+    assert_eq!(cpu.peek(COUNT), 1);
+    assert_eq!(cpu.history_len(), 3);
+
+    // Undo ST R1, COUNT
+    assert!(cpu.step_back());
+    assert_eq!(cpu.pc, 0x3002);
+    assert_eq!(cpu.peek(COUNT), 0);
+    assert_eq!(cpu.registers()[1], 1);
+    assert_eq!(cpu.instructions_retired(), 2);
+
+    // Undo ADD R1, R1, #1, along with the condition codes it set
+    assert!(cpu.step_back());
+    assert_eq!(cpu.pc, 0x3001);
+    assert_eq!(cpu.registers()[1], 0);
+    assert_eq!(nzp(&cpu), 0b010);
+
+    // Running forwards again gets the same results
+    run(&mut cpu, 2);
+    assert_eq!(cpu.peek(COUNT), 1);
+    assert_eq!(nzp(&cpu), 0b001);
+}
+
+#[test]
+fn cannot_step_back_without_history() {
+    let mut cpu = cpu_with(&[COUNTER]);
+    run(&mut cpu, 2);
+    assert!(!cpu.step_back());
+    assert_eq!(cpu.pc, 0x3002);
+
+    cpu.enable_history(16);
+    assert!(!cpu.step_back());
+    run(&mut cpu, 1);
+    cpu.disable_history();
+    assert!(!cpu.step_back());
+}
+
+#[test]
+fn reverse_continues_to_a_breakpoint() {
+    let mut cpu = cpu_with(&[COUNTER]);
+    cpu.enable_history(64);
+    while cpu.pc != 0x3005 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.registers()[1], 3);
+
+    // Back to the last time around the loop
+    assert!(cpu.reverse_continue(&HashSet::from([0x3001])));
+    assert_eq!(cpu.pc, 0x3001);
+    assert_eq!(cpu.registers()[1], 2);
+    assert_eq!(cpu.peek(COUNT), 2);
+
+    // Nothing before the start
+    assert!(!cpu.reverse_continue(&HashSet::from([0x3005])));
+    assert_eq!(cpu.pc, 0x3000);
+    assert_eq!(cpu.history_len(), 0);
+}
+
+#[test]
+fn reverses_to_the_last_write_of_an_address() {
+    let mut cpu = cpu_with(&[COUNTER]);
+    cpu.enable_history(64);
+    while cpu.pc != 0x3005 {
+        cpu.step().unwrap();
+    }
+
+    // Stops on the ST that wrote 3, before it ran
+    assert!(cpu.reverse_to_write(COUNT));
+    assert_eq!(cpu.pc, 0x3002);
+    assert_eq!(cpu.registers()[1], 3);
+    assert_eq!(cpu.peek(COUNT), 2);
+
+    // Then the one before it
+    assert!(cpu.reverse_to_write(COUNT));
+    assert_eq!(cpu.registers()[1], 2);
+    assert_eq!(cpu.peek(COUNT), 1);
+
+    // Nothing ever wrote here
+    assert!(!cpu.reverse_to_write(0x4000));
+    assert_eq!(cpu.pc, 0x3000);
+}
+
+#[test]
+fn forgets_the_oldest_steps_once_full() {
+    let mut cpu = cpu_with(&[COUNTER]);
+    cpu.enable_history(2);
+    run(&mut cpu, 5);
+    assert_eq!(cpu.history_len(), 2);
+
+    assert!(cpu.step_back());
+    assert!(cpu.step_back());
+    assert_eq!(cpu.pc, 0x3003);
+    assert_eq!(cpu.registers()[1], 1);
+    assert!(!cpu.step_back());
+    assert_eq!(cpu.pc, 0x3003);
+}
+
+#[test]
+fn keeps_nothing_with_no_capacity() {
+    let mut cpu = cpu_with(&[COUNTER]);
+    cpu.enable_history(0);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.history_len(), 0);
+    assert!(!cpu.step_back());
+}
+
+#[test]
+fn huge_capacity_is_not_allocated_up_front() {
+    let mut cpu = cpu_with(&[COUNTER]);
+    cpu.enable_history(usize::MAX);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.history_len(), 3);
+    assert!(cpu.step_back());
+}
@@ -0,0 +1,331 @@
+mod common;
+
+use std::io::Cursor;
+
+use alic3::emulator::{
+    Cpu, DisplayMode, Edition, Exception, SanitizerMode, ScriptedInput, StepEvent, TrapRoutine,
+};
+use common::*;
+
+type HleCpu = Cpu<ScriptedInput, SharedOutput>;
+
+const HALT_MESSAGE: &[u8] = b"\n\n--- halting the LC-3 ---\n\n";
+
+/// Load the programs with `routines` serviced natively, and `keys` typed `delay` instructions apart. The second edition
+/// is used so that R7 and the condition codes show what each routine did, and output is captured verbatim.
+fn cpu_servicing(
+    sources: &[&str],
+    routines: &[TrapRoutine],
+    keys: &str,
+    delay: u64,
+) -> (HleCpu, SharedOutput) {
+    let output = SharedOutput::default();
+    let mut cpu = Cpu::new(ScriptedInput::new(keys, delay), output.clone());
+    cpu.set_edition(Edition::Second);
+    cpu.set_display_mode(DisplayMode::Verbatim);
+    for routine in routines {
+        cpu.set_trap_hle(*routine, true);
+    }
+    let origins = sources
+        .iter()
+        .map(|source| cpu.load_program(Cursor::new(object(source))).unwrap())
+        .collect::<Vec<_>>();
+    cpu.pc = origins[0];
+    (cpu, output)
+}
+
+fn run_hle(cpu: &mut HleCpu, steps: usize) {
+    for _ in 0..steps {
+        cpu.step().unwrap();
+    }
+}
+
+fn printed(cpu: &mut HleCpu, output: &SharedOutput) -> Vec<u8> {
+    cpu.flush_display();
+    output.contents()
+}
+
+fn condition_codes(cpu: &HleCpu) -> u16 {
+    cpu.psr() & 0b111
+}
+
+#[test]
+fn getc_reads_a_key_into_r0() {
+    let program = ".ORIG x3000
+        AND R0, R0, #0
+        GETC
+        .END";
+    let (mut cpu, output) = cpu_servicing(&[program], &[TrapRoutine::Getc], "a", 0);
+    run_hle(&mut cpu, 2);
+    assert_eq!(cpu.pc, 0x3002);
+    assert_eq!(cpu.registers()[0], b'a' as u16);
+    assert_eq!(cpu.registers()[7], 0x3002);
+    assert_eq!(condition_codes(&cpu), 0b001);
+    // No echo
+    assert_eq!(printed(&mut cpu, &output), b"");
+}
+
+#[test]
+fn getc_retries_until_a_key_arrives() {
+    let program = ".ORIG x3000
+        AND R0, R0, #0
+        GETC
+        .END";
+    let (mut cpu, _output) = cpu_servicing(&[program], &[TrapRoutine::Getc], "a", 200);
+    run_hle(&mut cpu, 2);
+    // Nothing yet, so the TRAP is left to run again with nothing changed
+    assert_eq!(cpu.pc, 0x3001);
+    assert_eq!(cpu.registers()[0], 0);
+    assert_eq!(cpu.registers()[7], 0);
+    assert_eq!(condition_codes(&cpu), 0b010);
+
+    let mut retries = 0;
+    while cpu.pc == 0x3001 {
+        cpu.step().unwrap();
+        retries += 1;
+        assert!(retries < 1000, "no key arrived");
+    }
+    assert!(retries > 1);
+    assert_eq!(cpu.pc, 0x3002);
+    assert_eq!(cpu.registers()[0], b'a' as u16);
+}
+
+#[test]
+fn out_prints_r0_and_leaves_it_alone() {
+    let program = ".ORIG x3000
+        LD R0, CHAR
+        LD R1, MINUS_ONE
+        OUT
+        HALT
+        CHAR .FILL x41
+        MINUS_ONE .FILL #-1
+        .END";
+    let (mut cpu, output) = cpu_servicing(&[program], &[TrapRoutine::Out], "", 0);
+    run_hle(&mut cpu, 3);
+    assert_eq!(printed(&mut cpu, &output), b"A");
+    assert_eq!(cpu.pc, 0x3003);
+    assert_eq!(cpu.registers()[0], 0x41);
+    assert_eq!(cpu.registers()[7], 0x3003);
+    // The OS routine finishes by restoring R1
+    assert_eq!(condition_codes(&cpu), 0b100);
+}
+
+/// Point R0 at STRING, make the condition codes negative, and call `trap`.
+fn string_program(trap: &str, string: &str) -> String {
+    format!(
+        ".ORIG x3000
+        LEA R0, STRING
+        ADD R1, R1, #-1
+        {}
+        HALT
+        STRING {}
+        .END",
+        trap, string
+    )
+}
+
+#[test]
+fn puts_prints_a_word_per_character() {
+    let program = string_program("PUTS", ".STRINGZ \"hi\"");
+    let (mut cpu, output) = cpu_servicing(&[&program], &[TrapRoutine::Puts], "", 0);
+    run_hle(&mut cpu, 3);
+    assert_eq!(printed(&mut cpu, &output), b"hi");
+    assert_eq!(cpu.pc, 0x3003);
+    assert_eq!(cpu.registers()[0], 0x3004);
+    assert_eq!(cpu.registers()[7], 0x3003);
+    assert_eq!(condition_codes(&cpu), 0b001);
+}
+
+#[test]
+fn putsp_prints_two_characters_per_word() {
+    let program = string_program("PUTSP", ".FILL x6968\n.FILL x0021\n.FILL x0041");
+    let (mut cpu, output) = cpu_servicing(&[&program], &[TrapRoutine::Putsp], "", 0);
+    run_hle(&mut cpu, 3);
+    // The NUL in the second word's high byte ends the string
+    assert_eq!(printed(&mut cpu, &output), b"hi!");
+    assert_eq!(cpu.pc, 0x3003);
+    assert_eq!(cpu.registers()[0], 0x3004);
+    assert_eq!(cpu.registers()[7], 0x3003);
+    assert_eq!(condition_codes(&cpu), 0b001);
+}
+
+#[test]
+fn in_prompts_once_and_echoes() {
+    let program = ".ORIG x3000
+        AND R0, R0, #0
+        IN
+        .END";
+    let (mut cpu, output) = cpu_servicing(&[program], &[TrapRoutine::In], "z", 200);
+    run_hle(&mut cpu, 2);
+    assert_eq!(cpu.pc, 0x3001);
+    while cpu.pc == 0x3001 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(
+        printed(&mut cpu, &output),
+        b"\nInput a character> z\n".as_slice()
+    );
+    assert_eq!(cpu.registers()[0], b'z' as u16);
+    assert_eq!(cpu.registers()[7], 0x3002);
+    assert_eq!(condition_codes(&cpu), 0b001);
+}
+
+#[test]
+fn halt_stops_the_machine() {
+    let program = ".ORIG x3000
+        AND R7, R7, #0
+        HALT
+        .END";
+    let (mut cpu, output) = cpu_servicing(&[program], &[TrapRoutine::Halt], "", 0);
+    run_hle(&mut cpu, 2);
+    assert!(cpu.should_halt());
+    assert_eq!(printed(&mut cpu, &output), HALT_MESSAGE);
+    assert_eq!(cpu.pc, 0x3002);
+    assert_eq!(cpu.registers()[7], 0);
+}
+
+#[test]
+fn only_selected_routines_are_native() {
+    let program = string_program("OUT\nPUTS", ".STRINGZ \"hi\"");
+    let trap_table = ".ORIG x0022
+        .FILL x4000
+        .END";
+    let (mut cpu, output) = cpu_servicing(&[&program, trap_table], &[TrapRoutine::Out], "", 0);
+    run_hle(&mut cpu, 4);
+    // OUT printed the low byte of R0 itself, but PUTS went through the trap vector table
+    assert_eq!(printed(&mut cpu, &output), [0x05]);
+    assert_eq!(cpu.pc, 0x4000);
+    assert_eq!(cpu.registers()[7], 0x3004);
+}
+
+#[test]
+fn puts_stops_at_the_device_registers() {
+    let string = ".ORIG xFDFD
+        .FILL x41
+        .FILL x42
+        .END";
+    let program = ".ORIG x3000
+        LD R0, CHAR
+        STI R0, LAST
+        LD R0, STRING
+        PUTS
+        GETC
+        HALT
+        CHAR .FILL x43
+        LAST .FILL xFDFF
+        STRING .FILL xFDFD
+        .END";
+    let (mut cpu, output) = cpu_servicing(
+        &[program, string],
+        &[TrapRoutine::Puts, TrapRoutine::Getc],
+        "k",
+        0,
+    );
+    run_hle(&mut cpu, 5);
+    assert_eq!(printed(&mut cpu, &output), b"ABC");
+    // The key wasn't read as part of the string
+    assert_eq!(cpu.registers()[0], b'k' as u16);
+}
+
+#[test]
+fn puts_checks_access() {
+    // Drop into user mode at x3000 with the supervisor stack just below it
+    let setup = ".ORIG x0200
+        LD R6, SSP
+        LD R0, USER
+        JMPT R0
+        SSP .FILL x3000
+        USER .FILL x3000
+        .END";
+    let vector_table = ".ORIG x0102
+        .FILL x5000
+        .END";
+    let program = ".ORIG x3000
+        LD R0, STRING
+        PUTS
+        STRING .FILL x0200
+        .END";
+    let (mut cpu, output) =
+        cpu_servicing(&[setup, program, vector_table], &[TrapRoutine::Puts], "", 0);
+    cpu.set_jmpt_enabled(true);
+    cpu.pc = 0x0200;
+    run_hle(&mut cpu, 4);
+    assert_eq!(
+        cpu.step(),
+        Ok(StepEvent::Exception {
+            cause: Exception::AccessControlViolation
+        })
+    );
+    assert_eq!(cpu.pc, 0x5000);
+    // The pushed PC is just after the TRAP
+    assert_eq!(cpu.peek(0x2FFE), 0x3002);
+    assert_eq!(printed(&mut cpu, &output), b"");
+}
+
+#[test]
+fn output_waits_for_the_display() {
+    let program = ".ORIG x3000
+        LD R0, CHAR
+        STI R0, DDR
+        OUT
+        HALT
+        CHAR .FILL x41
+        DDR .FILL xFE06
+        .END";
+    let (mut cpu, output) = cpu_servicing(&[program], &[TrapRoutine::Out], "", 0);
+    cpu.set_display_delay(1000);
+    cpu.set_display_checker(SanitizerMode::Warn);
+    run_hle(&mut cpu, 3);
+    assert_eq!(printed(&mut cpu, &output), b"AA");
+    // Like the OS routine, OUT polled DSR until the first character was done
+    assert!(cpu.take_diagnostics().is_empty());
+    assert!(cpu.cycles() >= 1000);
+}
+
+#[test]
+fn out_checks_access() {
+    // Drop into user mode at x3000, where the display registers are off limits
+    let setup = ".ORIG x0200
+        LD R6, SSP
+        LD R0, USER
+        JMPT R0
+        SSP .FILL x3000
+        USER .FILL x3000
+        .END";
+    let vector_table = ".ORIG x0102
+        .FILL x5000
+        .END";
+    let program = ".ORIG x3000
+        OUT
+        .END";
+    let (mut cpu, output) =
+        cpu_servicing(&[setup, program, vector_table], &[TrapRoutine::Out], "", 0);
+    cpu.set_jmpt_enabled(true);
+    cpu.pc = 0x0200;
+    run_hle(&mut cpu, 3);
+    assert_eq!(
+        cpu.step(),
+        Ok(StepEvent::Exception {
+            cause: Exception::AccessControlViolation
+        })
+    );
+    assert_eq!(cpu.peek(0x2FFE), 0x3001);
+    assert_eq!(printed(&mut cpu, &output), b"");
+}
+
+#[test]
+fn stepping_back_over_in_prompts_again() {
+    let program = ".ORIG x3000
+        IN
+        .END";
+    let (mut cpu, output) = cpu_servicing(&[program], &[TrapRoutine::In], "z", 200);
+    cpu.enable_history(8);
+    run_hle(&mut cpu, 1);
+    assert!(cpu.step_back());
+    // Printing can't be taken back, but the prompt is owed again
+    run_hle(&mut cpu, 1);
+    assert_eq!(
+        printed(&mut cpu, &output),
+        b"\nInput a character> \nInput a character> ".as_slice()
+    );
+}
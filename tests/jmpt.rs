@@ -0,0 +1,89 @@
+mod common;
+
+use alic3::emulator::EmulatorError;
+use common::*;
+
+const ENTER_USER_MODE: &str = "
+.ORIG x3000
+LD R6, SSP
+LD R0, USER
+JMPT R0
+SSP .FILL x3000
+USER .FILL x4000
+.END
+";
+
+const USER_PROGRAM: &str = "
+.ORIG x4000
+ADD R1, R1, #1
+JMPT R0
+.END
+";
+
+const VECTOR_TABLE: &str = "
+.ORIG x0100
+.FILL x5000
+.END
+";
+
+#[test]
+fn jmpt_enters_user_mode() {
+    let mut cpu = cpu_with(&[ENTER_USER_MODE, USER_PROGRAM]);
+    cpu.set_jmpt_enabled(true);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.pc, 0x4000);
+    assert_eq!(cpu.psr() >> 15, 1);
+    // R6 now holds the initial user stack pointer
+    assert_eq!(cpu.registers()[6], 0xFE00);
+}
+
+#[test]
+fn jmpt_from_user_mode_is_a_privilege_violation() {
+    let mut cpu = cpu_with(&[ENTER_USER_MODE, USER_PROGRAM]);
+    cpu.set_jmpt_enabled(true);
+    run(&mut cpu, 4);
+    assert!(matches!(
+        cpu.step(),
+        Err(EmulatorError::UnhandledException { pc: 0x4001, .. })
+    ));
+
+    let mut cpu = cpu_with(&[ENTER_USER_MODE, USER_PROGRAM, VECTOR_TABLE]);
+    cpu.set_jmpt_enabled(true);
+    run(&mut cpu, 5);
+    assert_eq!(cpu.pc, 0x5000);
+    assert_eq!(cpu.psr() >> 15, 0);
+    // Back on the supervisor stack, with the user mode PSR and PC pushed
+    assert_eq!(cpu.registers()[6], 0x2FFE);
+    assert_eq!(cpu.peek(0x2FFE), 0x4002);
+}
+
+#[test]
+fn jmpt_is_a_plain_jump_when_disabled() {
+    let mut cpu = cpu_with(&[ENTER_USER_MODE, USER_PROGRAM]);
+    run(&mut cpu, 3);
+    assert_eq!(cpu.pc, 0x4000);
+    assert_eq!(cpu.psr() >> 15, 0);
+    assert_eq!(cpu.registers()[6], 0x3000);
+}
+
+#[test]
+fn jmpt_and_rtt_can_still_be_labels() {
+    let program = "
+        .ORIG x3000
+        LD R0, jmpt
+        LEA R1, rtt
+        JMPT R0
+        rtt ADD R1, R1, #1
+        RTT
+        BRnzp rtt
+        jmpt .FILL x3005
+        .END
+        ";
+    let mut cpu = cpu_with(&[program]);
+    run(&mut cpu, 2);
+    assert_eq!(cpu.registers()[0], 0x3005);
+    assert_eq!(cpu.registers()[1], 0x3003);
+    // Both instructions are still there
+    assert_eq!(cpu.peek(0x3002), 0xC001);
+    assert_eq!(cpu.peek(0x3004), 0xC1C1);
+}
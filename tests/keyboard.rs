@@ -0,0 +1,82 @@
+mod common;
+
+use std::io::{Cursor, Read};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use alic3::emulator::{Cpu, KeySource, ThreadedInput};
+use common::*;
+
+/// A stream that blocks until the test sends it something, like a terminal waiting on the user
+struct Typist(Receiver<u8>);
+
+impl Read for Typist {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.recv() {
+            Ok(key) => {
+                buf[0] = key;
+                Ok(1)
+            }
+            Err(_) => Ok(0),
+        }
+    }
+}
+
+fn keyboard() -> (ThreadedInput, Sender<u8>) {
+    let (sender, receiver) = channel();
+    (ThreadedInput::new(Typist(receiver)), sender)
+}
+
+/// Poll until a key shows up, giving the input thread a chance to catch up.
+fn wait_for_key(keyboard: &mut ThreadedInput) -> u8 {
+    for _ in 0..1000 {
+        if let Some(key) = keyboard.poll_key(0) {
+            return key;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    panic!("no key arrived");
+}
+
+#[test]
+fn queues_typeahead() {
+    let (mut keyboard, typist) = keyboard();
+    assert_eq!(keyboard.poll_key(0), None);
+    for key in b"hi" {
+        typist.send(*key).unwrap();
+    }
+    assert_eq!(wait_for_key(&mut keyboard), b'h');
+    assert_eq!(wait_for_key(&mut keyboard), b'i');
+    assert_eq!(keyboard.poll_key(0), None);
+}
+
+#[test]
+fn polling_kbsr_does_not_block() {
+    let program = ".ORIG x3000
+        POLL LDI R1, KBSR
+        BRzp POLL
+        LDI R0, KBDR
+        DONE BRnzp DONE
+        KBSR .FILL xFE00
+        KBDR .FILL xFE02
+        .END";
+    let (keyboard, typist) = keyboard();
+    let mut cpu = Cpu::new(keyboard, Vec::new());
+    cpu.pc = cpu.load_program(Cursor::new(object(program))).unwrap();
+    for _ in 0..100 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.pc, 0x3000);
+    assert_eq!(cpu.registers()[1], 0x0000);
+
+    typist.send(b'y').unwrap();
+    for _ in 0..1000 {
+        if cpu.pc == 0x3003 {
+            break;
+        }
+        cpu.step().unwrap();
+        thread::sleep(Duration::from_micros(100));
+    }
+    assert_eq!(cpu.registers()[0], b'y' as u16);
+}
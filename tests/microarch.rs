@@ -0,0 +1,280 @@
+mod common;
+
+use alic3::emulator::{
+    Diagnostic, DiagnosticKind, Edition, EmulatorError, Gate, Latch, MicroStep, Microsequencer,
+    SanitizerMode, StepEvent,
+};
+use common::*;
+
+const PROGRAM: &str = ".ORIG x3000
+LD R6, STACK
+AND R0, R0, #0
+ADD R0, R0, #3
+ADD R1, R0, R0
+NOT R2, R1
+LEA R3, DATA
+LDR R4, R3, #0
+LDI R5, POINTER
+ST R1, SCRATCH
+STR R2, R3, #1
+STI R0, POINTER
+LOOP ADD R0, R0, #-1
+BRp LOOP
+JSR SUB
+LEA R3, SUB
+JSRR R3
+TRAP x30
+TRAP x31
+.FILL xD000
+DONE BRnzp DONE
+SUB RET
+STACK .FILL x3000
+POINTER .FILL DATA
+DATA .FILL x1234
+.BLKW 1
+SCRATCH .BLKW 1
+.END";
+
+const HANDLERS: &str = ".ORIG x4000
+ADD R5, R5, #1
+RTI
+.END";
+
+// The second edition returns from traps with RET, the third with RTI
+const TRAP_HANDLERS: &str = ".ORIG x0030
+.FILL x4010
+.FILL x4020
+.END";
+
+const TRAP_ROUTINES: &str = ".ORIG x4010
+ADD R5, R5, #1
+RET
+.BLKW 13
+ADD R5, R5, #1
+RTI
+.END";
+
+const EXCEPTION_VECTORS: &str = ".ORIG x0100
+.FILL x4000
+.FILL x4000
+.FILL x4000
+.END";
+
+/// Run two CPUs set up the same way in lockstep, one with `Cpu::step` and one with a `Microsequencer`, checking that
+/// every step has the same outcome. `before_step` is called on both CPUs before each step.
+fn lockstep(
+    sources: &[&str],
+    setup: impl Fn(&mut TestCpu),
+    steps: usize,
+    before_step: impl Fn(usize, &mut TestCpu),
+) -> Vec<StepEvent> {
+    let mut reference = cpu_with(sources);
+    let mut cpu = cpu_with(sources);
+    setup(&mut reference);
+    setup(&mut cpu);
+    let mut microsequencer = Microsequencer::new();
+    let mut events = Vec::new();
+    for step in 0..steps {
+        before_step(step, &mut reference);
+        before_step(step, &mut cpu);
+        let expected = reference.step();
+        let actual = microsequencer.step(&mut cpu, |_| {});
+        assert_eq!(actual, expected, "step {}", step);
+        assert_eq!(cpu.registers(), reference.registers(), "step {}", step);
+        assert_eq!(cpu.pc, reference.pc, "step {}", step);
+        assert_eq!(cpu.psr(), reference.psr(), "step {}", step);
+        assert_eq!(cpu.cycles(), reference.cycles(), "step {}", step);
+        assert_eq!(
+            cpu.instructions_retired(),
+            reference.instructions_retired(),
+            "step {}",
+            step
+        );
+        events.push(expected.unwrap());
+    }
+    for addr in 0..0xFE00 {
+        assert_eq!(
+            cpu.peek(addr),
+            reference.peek(addr),
+            "memory at {:#06x}",
+            addr
+        );
+    }
+    events
+}
+
+fn trace(source: &str, steps: usize) -> Vec<MicroStep> {
+    let mut cpu = cpu_with(&[source]);
+    let mut microsequencer = Microsequencer::new();
+    let mut states = Vec::new();
+    for _ in 0..steps {
+        microsequencer
+            .step(&mut cpu, |step| states.push(step.clone()))
+            .unwrap();
+    }
+    states
+}
+
+#[test]
+fn matches_interpreter_in_both_editions() {
+    for edition in [Edition::Second, Edition::Third] {
+        let trap = match edition {
+            Edition::Second => "TRAP x30",
+            Edition::Third => "TRAP x31",
+        };
+        let program = PROGRAM.replace("TRAP x30\nTRAP x31", trap);
+        let sources = [
+            &program,
+            HANDLERS,
+            TRAP_HANDLERS,
+            TRAP_ROUTINES,
+            EXCEPTION_VECTORS,
+        ];
+        let events = lockstep(&sources, |cpu| cpu.set_edition(edition), 30, |_, _| {});
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, StepEvent::TrapEntered { .. })));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, StepEvent::Exception { .. })));
+    }
+}
+
+#[test]
+fn matches_interpreter_on_exceptions_in_user_mode() {
+    let setup = ".ORIG x0200
+        LD R6, SSP
+        LD R0, USER
+        JMPT R0
+        SSP .FILL x3000
+        USER .FILL x3000
+        .END";
+    let user = ".ORIG x3000
+        RTI
+        LDI R0, SSP
+        JMPT R0
+        SSP .FILL x0200
+        .END";
+    let handler = ".ORIG x4000
+        LD R0, USER
+        JMPT R0
+        USER .FILL x3001
+        .END";
+    let events = lockstep(
+        &[setup, user, handler, EXCEPTION_VECTORS],
+        |cpu| cpu.set_jmpt_enabled(true),
+        16,
+        |_, _| {},
+    );
+    assert!(
+        events
+            .iter()
+            .filter(|event| matches!(event, StepEvent::Exception { .. }))
+            .count()
+            >= 3
+    );
+}
+
+#[test]
+fn matches_interpreter_on_interrupts() {
+    let events = lockstep(
+        &[
+            PROGRAM,
+            HANDLERS,
+            EXCEPTION_VECTORS,
+            ".ORIG x0180\n.FILL x4000\n.END",
+        ],
+        |_| {},
+        12,
+        |step, cpu| {
+            if step == 3 {
+                cpu.request_interrupt(0x80, 4);
+            }
+        },
+    );
+    assert_eq!(
+        events[3],
+        StepEvent::InterruptAccepted {
+            vector: 0x80,
+            priority: 4
+        }
+    );
+}
+
+#[test]
+fn traces_states() {
+    let states = trace(
+        ".ORIG x3000
+        ADD R1, R1, #2
+        LDI R2, POINTER
+        POINTER .FILL x3000
+        .END",
+        2,
+    );
+    assert_eq!(
+        states.iter().map(|step| step.state).collect::<Vec<_>>(),
+        [18, 33, 35, 32, 1, 18, 33, 35, 32, 10, 24, 26, 25, 27]
+    );
+
+    assert_eq!(states[0].bus, Some((Gate::Pc, 0x3000)));
+    assert_eq!(states[0].mar, 0x3000);
+    assert_eq!(states[0].pc, 0x3001);
+    assert_eq!(states[3].ir, 0x1262);
+    assert_eq!(states[4].bus, Some((Gate::Alu, 2)));
+    assert_eq!(states[4].latches, [Latch::Reg, Latch::Cc]);
+    // LDI reads the pointer, then what it points to
+    assert_eq!(states[11].mar, 0x3000);
+    assert_eq!(states[13].mdr, 0x1262);
+}
+
+#[test]
+fn evaluates_branch_enable() {
+    let states = trace(
+        ".ORIG x3000
+        AND R0, R0, #0
+        BRp SKIP
+        BRz SKIP
+        SKIP BRnzp SKIP
+        .END",
+        3,
+    );
+    let branches = states
+        .iter()
+        .filter(|step| step.state == 0 || step.state == 22)
+        .map(|step| (step.state, step.ben))
+        .collect::<Vec<_>>();
+    assert_eq!(branches, [(0, false), (0, true), (22, true)]);
+}
+
+#[test]
+fn stores_go_through_the_display_checker() {
+    let program = ".ORIG x3000
+        LD R0, CHAR
+        STI R0, DDR
+        STI R0, DDR
+        DONE BRnzp DONE
+        CHAR .FILL x41
+        DDR .FILL xFE06
+        .END";
+    let setup = |cpu: &mut TestCpu| {
+        cpu.set_display_delay(100);
+        cpu.set_display_checker(SanitizerMode::Stop);
+    };
+    lockstep(&[program], setup, 2, |_, _| {});
+    let mut cpu = cpu_with(&[program]);
+    setup(&mut cpu);
+    let mut microsequencer = Microsequencer::new();
+    for _ in 0..2 {
+        microsequencer.step(&mut cpu, |_| {}).unwrap();
+    }
+    // The second character is written before the display is ready for it
+    assert_eq!(
+        microsequencer.step(&mut cpu, |_| {}),
+        Err(EmulatorError::Sanitizer(Diagnostic {
+            pc: 0x3002,
+            kind: DiagnosticKind::DisplayNotReady
+        }))
+    );
+    assert_eq!(cpu.pc, 0x3002);
+    assert_eq!(cpu.instructions_retired(), 2);
+}
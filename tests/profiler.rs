@@ -0,0 +1,83 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use alic3::asm_parser::Parser;
+use alic3::debug_info::DebugInfo;
+use alic3::emulator::{Profiler, TrapRoutine};
+use alic3::opcode::Opcode;
+use common::*;
+
+const PROGRAM: &str = "
+.ORIG x3000
+MAIN JSR DOUBLE
+JSR DOUBLE
+TRAP x21
+DONE BRnzp DONE
+DOUBLE ADD R1, R1, R1
+LOOP ADD R2, R2, #-1
+BRp LOOP
+RET
+.END
+";
+
+fn profile(steps: usize) -> (Rc<RefCell<Profiler>>, DebugInfo) {
+    let mut cpu = cpu_with(&[PROGRAM]);
+    cpu.set_trap_hle(TrapRoutine::Out, true);
+    let profiler = Rc::new(RefCell::new(Profiler::new()));
+    cpu.add_observer(Box::new(profiler.clone()));
+    run(&mut cpu, steps);
+    let debug_info = DebugInfo::from_program(&Parser::parse(PROGRAM).unwrap());
+    (profiler, debug_info)
+}
+
+#[test]
+fn counts_addresses_and_opcodes() {
+    // Two calls of 4 instructions each (R2 starts at 0, so the loop falls through at once), the trap, and one spin
+    let (profiler, _) = profile(12);
+    let profiler = profiler.borrow();
+    assert_eq!(profiler.instructions(), 12);
+    assert_eq!(profiler.count_at(0x3004), 2);
+    assert_eq!(profiler.count_at(0x3003), 1);
+    assert_eq!(profiler.opcode_count(Opcode::Add), 4);
+    assert_eq!(profiler.opcode_count(Opcode::Jsr), 2);
+    assert_eq!(profiler.opcode_count(Opcode::Trap), 1);
+}
+
+#[test]
+fn counts_calls_and_traps() {
+    let (profiler, _) = profile(12);
+    let profiler = profiler.borrow();
+    assert_eq!(profiler.calls_to(0x3004), 2);
+    assert_eq!(profiler.trap_calls(0x21), 1);
+    assert_eq!(profiler.trap_calls(0x25), 0);
+}
+
+#[test]
+fn groups_counts_by_label() {
+    let (profiler, debug_info) = profile(12);
+    assert_eq!(
+        profiler.borrow().routine_counts(&debug_info),
+        vec![
+            ("LOOP".to_string(), 6),
+            ("MAIN".to_string(), 3),
+            ("DOUBLE".to_string(), 2),
+            ("DONE".to_string(), 1),
+        ]
+    );
+}
+
+#[test]
+fn folds_call_stacks() {
+    let (profiler, debug_info) = profile(12);
+    let mut folded = Vec::new();
+    profiler
+        .borrow()
+        .write_folded(&debug_info, &mut folded)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(folded).unwrap(),
+        "MAIN 4\nMAIN;DOUBLE 8\n"
+    );
+}
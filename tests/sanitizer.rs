@@ -0,0 +1,84 @@
+mod common;
+
+use alic3::emulator::{Diagnostic, DiagnosticKind, EmulatorError, SanitizerMode};
+use common::*;
+
+const PROGRAM: &str = "
+.ORIG x3000
+ADD R1, R1, #1
+LD R2, POINTER
+LDR R3, R2, #0
+ADD R1, R1, #1
+POINTER .FILL x4000
+.END
+";
+
+#[test]
+fn warns_once_per_instruction_and_location() {
+    let mut cpu = cpu_with(&[PROGRAM]);
+    cpu.set_sanitizer(SanitizerMode::Warn);
+    run(&mut cpu, 4);
+    assert_eq!(
+        cpu.take_diagnostics(),
+        vec![
+            Diagnostic {
+                pc: 0x3000,
+                kind: DiagnosticKind::UninitializedRegister(1)
+            },
+            Diagnostic {
+                pc: 0x3002,
+                kind: DiagnosticKind::UninitializedMemory(0x4000)
+            },
+        ]
+    );
+    // R1 was written by the first ADD, so the second one is fine
+    assert_eq!(cpu.registers()[1], 2);
+    assert_eq!(cpu.take_diagnostics(), vec![]);
+}
+
+#[test]
+fn stop_leaves_pc_on_instruction() {
+    let mut cpu = cpu_with(&[PROGRAM]);
+    cpu.set_sanitizer(SanitizerMode::Stop);
+    let diagnostic = Diagnostic {
+        pc: 0x3000,
+        kind: DiagnosticKind::UninitializedRegister(1),
+    };
+    assert_eq!(cpu.step(), Err(EmulatorError::Sanitizer(diagnostic)));
+    assert_eq!(cpu.pc, 0x3000);
+    assert_eq!(cpu.registers()[1], 0);
+}
+
+#[test]
+fn stop_on_memory_read_has_no_effect() {
+    let mut cpu = cpu_with(&[PROGRAM]);
+    run(&mut cpu, 2);
+    cpu.set_sanitizer(SanitizerMode::Stop);
+    assert_eq!(
+        cpu.step(),
+        Err(EmulatorError::Sanitizer(Diagnostic {
+            pc: 0x3002,
+            kind: DiagnosticKind::UninitializedMemory(0x4000)
+        }))
+    );
+    assert_eq!(cpu.pc, 0x3002);
+}
+
+#[test]
+fn off_by_default() {
+    let mut cpu = cpu_with(&[PROGRAM]);
+    run(&mut cpu, 4);
+    assert_eq!(cpu.take_diagnostics(), vec![]);
+}
+
+#[test]
+fn undo_restores_register_shadow() {
+    let mut cpu = cpu_with(&[PROGRAM]);
+    cpu.enable_history(4);
+    cpu.set_sanitizer(SanitizerMode::Warn);
+    run(&mut cpu, 1);
+    cpu.take_diagnostics();
+    assert!(cpu.step_back());
+    cpu.set_sanitizer(SanitizerMode::Stop);
+    assert!(matches!(cpu.step(), Err(EmulatorError::Sanitizer(_))));
+}
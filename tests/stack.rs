@@ -0,0 +1,279 @@
+mod common;
+
+use alic3::emulator::{
+    Edition, EmulatorError, Stack, StackBounds, StackFault, StackFaultKind, StackPolicy, StepEvent,
+};
+use common::*;
+
+// Pushes forever onto a four-word stack at x4000-x4003
+const RUNAWAY_PUSH: &str = "
+.ORIG x3000
+LD R6, STACK
+PUSH ADD R6, R6, #-1
+STR R0, R6, #0
+BRnzp PUSH
+STACK .FILL x4004
+.END
+";
+
+const STACK_FAULT_VECTOR: &str = "
+.ORIG x0110
+.FILL x5000
+.END
+";
+
+const HANDLER: &str = "
+.ORIG x5000
+HALT
+.END
+";
+
+fn bounds() -> StackBounds {
+    StackBounds {
+        supervisor: Some(0x4000..=0x4003),
+        user: None,
+    }
+}
+
+#[test]
+fn overflow_stops_before_the_push() {
+    let mut cpu = cpu_with(&[RUNAWAY_PUSH]);
+    cpu.set_stack_bounds(bounds());
+    run(&mut cpu, 1 + 4 * 3);
+    assert_eq!(cpu.registers()[6], 0x4000);
+    assert_eq!(
+        cpu.step(),
+        Err(EmulatorError::StackFault(StackFault {
+            stack: Stack::Supervisor,
+            kind: StackFaultKind::Overflow,
+            pc: 0x3001,
+            sp: 0x3FFF
+        }))
+    );
+    assert_eq!(cpu.pc, 0x3001);
+    assert_eq!(cpu.registers()[6], 0x4000);
+}
+
+#[test]
+fn unbounded_by_default() {
+    let mut cpu = cpu_with(&[RUNAWAY_PUSH]);
+    run(&mut cpu, 1 + 5 * 3);
+    assert_eq!(cpu.registers()[6], 0x3FFF);
+}
+
+#[test]
+fn underflow() {
+    let mut cpu = cpu_with(&["
+        .ORIG x3000
+        LD R6, STACK
+        ADD R6, R6, #1
+        STACK .FILL x4004
+        .END
+        "]);
+    cpu.set_stack_bounds(bounds());
+    run(&mut cpu, 1);
+    assert!(matches!(
+        cpu.step(),
+        Err(EmulatorError::StackFault(StackFault {
+            kind: StackFaultKind::Underflow,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn rti_pop_past_the_top() {
+    let mut cpu = cpu_with(&["
+        .ORIG x3000
+        LD R6, STACK
+        RTI
+        STACK .FILL x4003
+        .END
+        "]);
+    cpu.set_stack_bounds(bounds());
+    run(&mut cpu, 1);
+    assert_eq!(
+        cpu.step(),
+        Err(EmulatorError::StackFault(StackFault {
+            stack: Stack::Supervisor,
+            kind: StackFaultKind::Underflow,
+            pc: 0x3001,
+            sp: 0x4005
+        }))
+    );
+}
+
+#[test]
+fn trap_push_past_the_bottom() {
+    let mut cpu = cpu_with(&["
+        .ORIG x3000
+        LD R6, STACK
+        TRAP x30
+        STACK .FILL x4001
+        .END
+        "]);
+    cpu.set_edition(Edition::Third);
+    cpu.set_stack_bounds(bounds());
+    run(&mut cpu, 1);
+    assert!(matches!(
+        cpu.step(),
+        Err(EmulatorError::StackFault(StackFault {
+            kind: StackFaultKind::Overflow,
+            sp: 0x3FFF,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn exception_push_past_the_bottom() {
+    let program = "
+        .ORIG x3000
+        LD R6, STACK
+        .FILL xD000
+        STACK .FILL x4001
+        .END
+        ";
+    let illegal_opcode_vector = "
+        .ORIG x0101
+        .FILL x5000
+        .END
+        ";
+    let mut cpu = cpu_with(&[program, illegal_opcode_vector]);
+    cpu.set_stack_bounds(bounds());
+    run(&mut cpu, 1);
+    assert_eq!(
+        cpu.step(),
+        Err(EmulatorError::StackFault(StackFault {
+            stack: Stack::Supervisor,
+            kind: StackFaultKind::Overflow,
+            pc: 0x3001,
+            sp: 0x3FFF
+        }))
+    );
+    assert_eq!(cpu.registers()[6], 0x4001);
+
+    // The stack fault handler gets a fresh stack instead
+    let mut cpu = cpu_with(&[program, illegal_opcode_vector, STACK_FAULT_VECTOR]);
+    cpu.set_stack_bounds(bounds());
+    cpu.set_stack_policy(StackPolicy::Vector(0x10));
+    run(&mut cpu, 1);
+    assert!(matches!(cpu.step(), Ok(StepEvent::StackFault(_))));
+    assert_eq!(cpu.pc, 0x5000);
+    assert_eq!(cpu.registers()[6], 0x4002);
+    assert_eq!(cpu.peek(0x4002), 0x3002);
+}
+
+#[test]
+fn vector_policy_enters_handler_on_fresh_stack() {
+    let mut cpu = cpu_with(&[RUNAWAY_PUSH, STACK_FAULT_VECTOR, HANDLER]);
+    cpu.set_stack_bounds(bounds());
+    cpu.set_stack_policy(StackPolicy::Vector(0x10));
+    run(&mut cpu, 1 + 4 * 3);
+    assert!(matches!(cpu.step(), Ok(StepEvent::StackFault(_))));
+    assert_eq!(cpu.pc, 0x5000);
+    // The handler's stack started out empty, so it only holds the pushed PSR and PC
+    assert_eq!(cpu.registers()[6], 0x4002);
+    assert_eq!(cpu.peek(0x4002), 0x3002);
+}
+
+#[test]
+fn user_stack_is_checked_in_user_mode() {
+    let mut cpu = cpu_with(&[
+        "
+        .ORIG x3000
+        LD R6, SSP
+        LD R0, USER
+        JMPT R0
+        SSP .FILL x3000
+        USER .FILL x3100
+        .END
+        ",
+        "
+        .ORIG x3100
+        LD R6, USP
+        ADD R6, R6, #-1
+        USP .FILL xF000
+        .END
+        ",
+    ]);
+    cpu.set_jmpt_enabled(true);
+    cpu.set_stack_bounds(StackBounds {
+        supervisor: None,
+        user: Some(0xF000..=0xFDFF),
+    });
+    run(&mut cpu, 4);
+    assert!(matches!(
+        cpu.step(),
+        Err(EmulatorError::StackFault(StackFault {
+            stack: Stack::User,
+            kind: StackFaultKind::Overflow,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn push_that_wraps_around_is_an_overflow() {
+    let mut cpu = cpu_with(&["
+        .ORIG x3000
+        AND R6, R6, #0
+        ADD R6, R6, #-1
+        .END
+        "]);
+    cpu.set_stack_bounds(StackBounds {
+        supervisor: Some(0x0000..=0x0003),
+        user: None,
+    });
+    run(&mut cpu, 1);
+    assert_eq!(
+        cpu.step(),
+        Err(EmulatorError::StackFault(StackFault {
+            stack: Stack::Supervisor,
+            kind: StackFaultKind::Overflow,
+            pc: 0x3001,
+            sp: 0xFFFF
+        }))
+    );
+    // And the other way round
+    let bounds = StackBounds {
+        supervisor: Some(0xFFF0..=0xFFFE),
+        user: None,
+    };
+    assert_eq!(
+        bounds.check(Stack::Supervisor, 0xFFFF, 0x0001),
+        Some(StackFaultKind::Underflow)
+    );
+}
+
+#[test]
+fn interrupt_push_past_the_bottom() {
+    let program = "
+        .ORIG x3000
+        LD R6, STACK
+        ADD R0, R0, #1
+        STACK .FILL x4001
+        .END
+        ";
+    let interrupt_vector = "
+        .ORIG x0180
+        .FILL x5000
+        .END
+        ";
+    let mut cpu = cpu_with(&[program, interrupt_vector]);
+    cpu.set_stack_bounds(bounds());
+    run(&mut cpu, 1);
+    cpu.request_interrupt(0x80, 4);
+    // The interrupt arrived before the ADD, which is left to run
+    assert_eq!(
+        cpu.step(),
+        Err(EmulatorError::StackFault(StackFault {
+            stack: Stack::Supervisor,
+            kind: StackFaultKind::Overflow,
+            pc: 0x3001,
+            sp: 0x3FFF
+        }))
+    );
+    assert_eq!(cpu.pc, 0x3001);
+    assert_eq!(cpu.registers()[6], 0x4001);
+}
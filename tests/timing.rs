@@ -0,0 +1,143 @@
+mod common;
+
+use std::io::Cursor;
+
+use alic3::emulator::{Cpu, ScriptedInput, StepEvent, TimingModel};
+use alic3::opcode::Opcode;
+use common::*;
+
+#[test]
+fn textbook_model_charges_memory_latency() {
+    let timing = TimingModel::textbook(5);
+    assert_eq!(timing.cycles(Opcode::Add), 9);
+    assert_eq!(timing.cycles(Opcode::Ld), 15);
+    assert_eq!(timing.cycles(Opcode::Ldi), 21);
+    assert_eq!(TimingModel::textbook(1).cycles(Opcode::Ldi), 9);
+}
+
+#[test]
+fn counts_cycles() {
+    let mut cpu = cpu_with(&[".ORIG x3000
+        ADD R1, R1, #1
+        LDI R2, POINTER
+        BRp SKIP
+        SKIP BRz SKIP
+        POINTER .FILL x3000
+        .END"]);
+    run(&mut cpu, 4);
+    // The first branch is taken, the second isn't
+    assert_eq!(cpu.cycles(), 9 + 21 + 10 + 9);
+    assert_eq!(cpu.instructions_retired(), 4);
+}
+
+#[test]
+fn overrides_opcodes() {
+    let mut timing = TimingModel::default();
+    timing.parse_overrides("add=1, LDI=2,taken=0").unwrap();
+    assert_eq!(timing.cycles(Opcode::Add), 1);
+    assert_eq!(timing.cycles(Opcode::Ldi), 2);
+    assert_eq!(timing.branch_taken, 0);
+    assert!(timing.parse_overrides("FROB=1").is_err());
+    assert!(timing.parse_overrides("ADD").is_err());
+
+    let mut cpu = cpu_with(&[".ORIG x3000\nADD R1, R1, #1\n.END"]);
+    cpu.set_timing_model(timing);
+    run(&mut cpu, 1);
+    assert_eq!(cpu.cycles(), 1);
+}
+
+#[test]
+fn scripted_input_delay_counts_instructions() {
+    let program = ".ORIG x3000
+        POLL LDI R1, KBSR
+        BRzp POLL
+        LDI R0, KBDR
+        DONE BRnzp DONE
+        KBSR .FILL xFE00
+        KBDR .FILL xFE02
+        .END";
+    for memory_latency in [1, 50] {
+        let mut cpu = Cpu::new(ScriptedInput::new("k", 10), Vec::new());
+        cpu.set_timing_model(TimingModel::textbook(memory_latency));
+        cpu.pc = cpu.load_program(Cursor::new(object(program))).unwrap();
+        while cpu.pc != 0x3003 {
+            cpu.step().unwrap();
+        }
+        // The key shows up on the poll that starts after 10 instructions
+        assert_eq!(cpu.instructions_retired(), 13);
+        assert_eq!(cpu.registers()[0], b'k' as u16);
+    }
+}
+
+#[test]
+fn undo_restores_cycles() {
+    let mut cpu = cpu_with(&[".ORIG x3000\nADD R1, R1, #1\nADD R1, R1, #1\n.END"]);
+    cpu.enable_history(4);
+    run(&mut cpu, 2);
+    assert!(cpu.step_back());
+    assert_eq!(cpu.cycles(), 9);
+}
+
+const TIMER_PROGRAM: &str = ".ORIG x3000
+LD R6, STACK
+LD R0, INTERVAL
+STI R0, TMI
+LD R0, ENABLE
+STI R0, TMR
+SPIN BRnzp SPIN
+STACK .FILL x3000
+INTERVAL .FILL #100
+ENABLE .FILL x4000
+TMR .FILL xFE08
+TMI .FILL xFE0A
+.END";
+
+const TIMER_VECTOR: &str = ".ORIG x0181
+.FILL x1000
+.END";
+
+const TIMER_HANDLER: &str = ".ORIG x1000
+LDI R1, TMR
+LDI R2, TMR
+RTI
+TMR .FILL xFE08
+.END";
+
+#[test]
+fn timer_interrupts_after_interval() {
+    let mut cpu = cpu_with(&[TIMER_PROGRAM, TIMER_VECTOR, TIMER_HANDLER]);
+    let mut accepted_at = None;
+    for _ in 0..50 {
+        if let StepEvent::InterruptAccepted { vector, .. } = cpu.step().unwrap() {
+            assert_eq!(vector, 0x81);
+            accepted_at = Some(cpu.cycles());
+            break;
+        }
+    }
+    // The interval started when TMI was written, 3 instructions in
+    let started = 15 + 15 + 21;
+    assert!(accepted_at.unwrap() >= started + 100);
+
+    // Reading TMR shows the interval elapsed, and clears it
+    run(&mut cpu, 2);
+    assert_eq!(cpu.registers()[1], 0xC000);
+    assert_eq!(cpu.registers()[2], 0x4000);
+}
+
+#[test]
+fn timer_without_interrupts_just_sets_status() {
+    let mut cpu = cpu_with(&[".ORIG x3000
+        LD R0, INTERVAL
+        STI R0, TMI
+        POLL LDI R1, TMR
+        BRzp POLL
+        LDI R2, TMR
+        DONE BRnzp DONE
+        INTERVAL .FILL #100
+        TMR .FILL xFE08
+        TMI .FILL xFE0A
+        .END"]);
+    run(&mut cpu, 20);
+    assert_eq!(cpu.registers()[1], 0x8000);
+    assert_eq!(cpu.registers()[2], 0x0000);
+}